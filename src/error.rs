@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+pub type UtilrsResult<T> = Result<T, UtilrsError>;
+
+/// Errors of the split, process and database functions
+#[derive(Debug)]
+pub enum UtilrsError {
+    /// the database config file can not be read or does not define the required tables
+    Config { file: String, message: String },
+    /// an input file can not be opened or read
+    Io { path: String, source: io::Error },
+    /// an input file can not be split into documents, e.g. because of an invalid separator
+    /// regex or input which can not be decoded. `byte_offset` is the offset in the
+    /// (transcoded) input the error was found at, if known.
+    Split {
+        file: String,
+        byte_offset: Option<u64>,
+        message: String,
+    },
+    /// a document is not well-formed xml, `position` is the byte position in the document
+    XmlParse {
+        doc_id: isize,
+        position: usize,
+        message: String,
+    },
+    /// a database operation failed
+    Database { source: rusqlite::Error },
+    /// an xml schema file can not be read as schema or is not in the schema directory
+    Schema { file: String, message: String },
+}
+
+impl UtilrsError {
+    pub fn config(file: &str, message: impl Into<String>) -> Self {
+        UtilrsError::Config {
+            file: file.to_owned(),
+            message: message.into(),
+        }
+    }
+
+    pub fn schema(file: &str, message: impl Into<String>) -> Self {
+        UtilrsError::Schema {
+            file: file.to_owned(),
+            message: message.into(),
+        }
+    }
+
+    pub fn split(file: &str, byte_offset: Option<u64>, message: impl Into<String>) -> Self {
+        UtilrsError::Split {
+            file: file.to_owned(),
+            byte_offset,
+            message: message.into(),
+        }
+    }
+
+    /// Error reading the input file `path`. Input that can not be decoded or split
+    /// (`io::ErrorKind::InvalidData`) and an unknown input encoding
+    /// (`io::ErrorKind::InvalidInput`) are split errors, the byte offset is taken from an
+    /// [`InvalidInput`] error or else `byte_offset` is used.
+    pub fn input(path: &str, byte_offset: Option<u64>, source: io::Error) -> Self {
+        match source.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {}
+            _ => {
+                return UtilrsError::Io {
+                    path: path.to_owned(),
+                    source,
+                }
+            }
+        }
+        match source
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<InvalidInput>())
+        {
+            Some(invalid) => UtilrsError::split(path, Some(invalid.byte_offset), &invalid.message),
+            None => UtilrsError::split(path, byte_offset, source.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for UtilrsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UtilrsError::Config { file, message } => {
+                write!(f, "invalid database config file '{}': {}", file, message)
+            }
+            UtilrsError::Io { path, source } => write!(f, "error reading '{}': {}", path, source),
+            UtilrsError::Split {
+                file,
+                byte_offset: Some(byte_offset),
+                message,
+            } => write!(
+                f,
+                "error splitting '{}' at byte {}: {}",
+                file, byte_offset, message
+            ),
+            UtilrsError::Split { file, message, .. } => {
+                write!(f, "error splitting '{}': {}", file, message)
+            }
+            UtilrsError::XmlParse {
+                doc_id,
+                position,
+                message,
+            } => write!(
+                f,
+                "error parsing document {} at position {}: {}",
+                doc_id, position, message
+            ),
+            UtilrsError::Database { source } => write!(f, "database error: {}", source),
+            UtilrsError::Schema { file, message } => {
+                write!(f, "invalid schema '{}': {}", file, message)
+            }
+        }
+    }
+}
+
+impl Error for UtilrsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            UtilrsError::Io { source, .. } => Some(source),
+            UtilrsError::Database { source } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for UtilrsError {
+    fn from(source: rusqlite::Error) -> Self {
+        UtilrsError::Database { source }
+    }
+}
+
+/// Input which can not be decoded or split, carried inside an `io::Error` of kind
+/// `InvalidData` by the streaming splitters so the byte offset survives the `io::Read`
+/// interfaces
+#[derive(Debug)]
+pub struct InvalidInput {
+    pub byte_offset: u64,
+    pub message: String,
+}
+
+impl InvalidInput {
+    pub fn new_io_error(byte_offset: u64, message: impl Into<String>) -> io::Error {
+        let invalid = InvalidInput {
+            byte_offset,
+            message: message.into(),
+        };
+        io::Error::new(io::ErrorKind::InvalidData, invalid)
+    }
+}
+
+impl fmt::Display for InvalidInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (byte {})", self.message, self.byte_offset)
+    }
+}
+
+impl Error for InvalidInput {}
@@ -1,6 +1,15 @@
 #![allow(dead_code, unused_imports, unused_variables)]
+use pyo3::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
 use std::time::Instant;
 
+#[path = "error.rs"]
+mod error;
+pub use error::*;
+
 #[path = "filesplit.rs"]
 mod filesplit;
 pub use filesplit::*;
@@ -13,111 +22,884 @@ pub use xmlparser::*;
 mod persistence;
 pub use persistence::*;
 
+#[path = "xsd.rs"]
+mod xsd;
+pub use xsd::*;
+
+/// Number of valid and invalid documents stored by a split run, and the number of
+/// documents found to be duplicates of an earlier document
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SplitSummary {
+    pub valid_docs: usize,
+    pub invalid_docs: usize,
+    pub duplicate_docs: usize,
+    /// the run was cancelled before all documents were split
+    pub cancelled: bool,
+}
+
+/// Progress of a split or process run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// number of documents split or parsed so far
+    pub done: usize,
+    /// number of documents to process, not known while a file is being split
+    pub total: Option<usize>,
+    /// number of invalid documents or parse errors so far
+    pub errors: usize,
+}
+
+/// Progress reporting and cancellation of a split or process run.
+///
+/// The progress is only reported to the progress callback. A cancelled run stops after the
+/// current document and leaves the database consistent: all documents handled so far are
+/// stored and the indices are created, a process run can be resumed as it only parses
+/// documents which have not been parsed yet.
+#[derive(Clone, Default)]
+pub struct RunControl {
+    on_progress: Option<Arc<dyn Fn(Progress) + Send + Sync>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl RunControl {
+    pub fn new() -> Self {
+        RunControl::default()
+    }
+
+    /// Call `on_progress` after every document and once at the end of the run
+    pub fn with_progress(mut self, on_progress: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(on_progress));
+        self
+    }
+
+    /// Use a shared flag to cancel the run, e.g. from another thread
+    pub fn with_cancel_flag(mut self, cancelled: Arc<AtomicBool>) -> Self {
+        self.cancelled = cancelled;
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn report(&self, progress: Progress) {
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(progress);
+        }
+    }
+}
+
+/// What to do with a document having the same content hash as a document split before,
+/// in the same run or already stored in the database
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// store the duplicate like any other document
+    #[default]
+    Keep,
+    /// store the duplicate and record the id of the first copy in DuplicateOf
+    Flag,
+    /// do not store the duplicate
+    Skip,
+}
+
+/// Options of a split run besides the split mode
+#[derive(Debug, Clone, Default)]
+pub struct SplitOptions {
+    /// root element name every document must have
+    pub expected_root: Option<String>,
+    /// namespace the root element of every document must declare
+    pub expected_namespace: Option<String>,
+    /// encoding of the input files, detected from a BOM or the xml declaration if not given
+    pub encoding: Option<String>,
+    pub duplicate_policy: DuplicatePolicy,
+    /// add the documents to the ones already in the database, the doc ids continue after
+    /// the highest stored doc id. Otherwise the database content is replaced.
+    pub append: bool,
+}
+
+/// Result of a split preview, see [`preview_split`]
+#[derive(Debug, Clone)]
+#[pyclass]
+pub struct SplitPreview {
+    /// number of chunks found
+    #[pyo3(get)]
+    pub chunks: usize,
+    /// chunk sizes in bytes, the median is the lower one for an even number of chunks
+    #[pyo3(get)]
+    pub min_size: usize,
+    #[pyo3(get)]
+    pub median_size: usize,
+    #[pyo3(get)]
+    pub max_size: usize,
+    /// the first chunks of the file
+    #[pyo3(get)]
+    pub first_chunks: Vec<ChunkHead>,
+    /// the last chunks of the file, not repeating any of the first chunks
+    #[pyo3(get)]
+    pub last_chunks: Vec<ChunkHead>,
+    /// all chunks failing the well-formedness check
+    #[pyo3(get)]
+    pub malformed_chunks: Vec<ChunkHead>,
+}
+
+/// The start of a chunk found by a split preview
+#[derive(Debug, Clone)]
+#[pyclass]
+pub struct ChunkHead {
+    #[pyo3(get)]
+    pub index: usize,
+    #[pyo3(get)]
+    pub archive_entry: String,
+    /// byte offset in the input file, or in the unpacked archive entry
+    #[pyo3(get)]
+    pub start_offset: u64,
+    #[pyo3(get)]
+    pub start_line: u64,
+    #[pyo3(get)]
+    pub size: usize,
+    #[pyo3(get)]
+    pub head: String,
+    #[pyo3(get)]
+    pub invalid_reason: Option<String>,
+}
+
+/// Split all documents in a file and store them to the database
 pub fn split_file(
     databasename: &str,
     configfilename: &str,
     filetoprocess: &str,
-    reg_ex: &str,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    let out: bool = true;
+    split_mode: &filesplit::SplitMode,
+    options: &SplitOptions,
+    control: &RunControl,
+) -> UtilrsResult<SplitSummary> {
+    let files = vec![filetoprocess.to_owned()];
+    split_input_files(
+        databasename,
+        configfilename,
+        &files,
+        split_mode,
+        options,
+        control,
+    )
+}
 
-    let mut db = DataBase::new(databasename, configfilename);
-    let documents = filesplit::split_file(filetoprocess, reg_ex);
+/// Split all documents in all files of a directory or matching a glob pattern and store
+/// them to the database. The doc ids are unique over all files, the source file of every
+/// document is recorded in the SourceFileList table.
+pub fn split_files(
+    databasename: &str,
+    configfilename: &str,
+    path_or_pattern: &str,
+    split_mode: &filesplit::SplitMode,
+    options: &SplitOptions,
+    control: &RunControl,
+) -> UtilrsResult<SplitSummary> {
+    let files = filesplit::resolve_input_files(path_or_pattern)?;
+    split_input_files(
+        databasename,
+        configfilename,
+        &files,
+        split_mode,
+        options,
+        control,
+    )
+}
+
+fn split_input_files(
+    databasename: &str,
+    configfilename: &str,
+    files: &[String],
+    split_mode: &filesplit::SplitMode,
+    options: &SplitOptions,
+    control: &RunControl,
+) -> UtilrsResult<SplitSummary> {
+    let mut out = SplitSummary {
+        valid_docs: 0,
+        invalid_docs: 0,
+        duplicate_docs: 0,
+        cancelled: false,
+    };
+    let mut docs_split: usize = 0;
+    let expected_root = options.expected_root.as_deref();
+    let expected_namespace = options.expected_namespace.as_deref();
+    let encoding = options.encoding.as_deref();
+
+    let mut db = DataBase::new(databasename, configfilename)?;
+    if !options.append {
+        // a new run replaces the stored documents together with their parsed data and
+        // the process log
+        db.drop_indices(persistence::IndexGroup::DocStore)?;
+        db.truncate_doc_store()?;
+        db.drop_indices(persistence::IndexGroup::XmlStore)?;
+        db.truncate_xml_store()?;
+        db.truncate_process_log()?;
+    }
+    let first_file_id = db.get_next_file_id()?;
+    let mut idx = db.get_next_doc_id()? as usize;
+    // duplicates are looked up on the content hash index, also while the other document
+    // indices are dropped
+    db.create_indices(persistence::IndexGroup::DocHash)?;
 
-    let docs_to_process = documents.len();
-    for (idx, doc) in documents.into_iter().enumerate() {
-        // status update
-        // calculate the pct and then round to 2 decimal places
-        let mut progress_pct: f32 = (idx as f32 * 100f32) / docs_to_process as f32;
-        progress_pct = (progress_pct * 100.0).round() / 100.0;
-        // display update if new progress is an integer value
-        if progress_pct.fract() == 0.0 {
-            print!("\rProcessing split file is at {:.0}%...", progress_pct);
+    for (file_id, filetoprocess) in (first_file_id..).zip(files.iter()) {
+        if control.is_cancelled() {
+            break;
         }
-        // TODO: check doc valididty
-        db.store_doc(idx as isize, persistence::DocValidity::Valid, &doc, "none");
-        // db.commit_writes();
+        // register the source file
+        let checksum = filesplit::file_checksum(filetoprocess)
+            .map_err(|e| UtilrsError::input(filetoprocess, None, e))?;
+        db.store_source_file(file_id, filetoprocess, &checksum)?;
+
+        // the documents are streamed from the file (or from each entry of an archive) one
+        // by one
+        let input = filesplit::InputFile::open(filetoprocess)?;
+        let mut doc_source = persistence::DocSource {
+            file_id,
+            archive_entry: String::new(),
+            file_doc_ordinal: 0,
+            start_offset: 0,
+            end_offset: 0,
+            start_line: 0,
+        };
+
+        input.for_each_entry(|entry_name, reader| {
+            if control.is_cancelled() {
+                return Ok(());
+            }
+            // transcode the input to UTF-8 before splitting
+            let reader = filesplit::DecodingReader::new(reader, encoding)
+                .map_err(|e| UtilrsError::input(filetoprocess, None, e))?;
+            let input_encoding = reader.encoding();
+            let decode_errors = reader.decode_errors();
+            let source_offsets = reader.source_offsets();
+            let mut documents = open_splitter(filetoprocess, reader, split_mode)?;
+            doc_source.archive_entry = entry_name.to_owned();
+            while let Some(doc) = documents.next() {
+                let doc = doc.map_err(|e| {
+                    let offset = source_offsets
+                        .borrow_mut()
+                        .source_offset(documents.bytes_consumed());
+                    UtilrsError::input(filetoprocess, Some(offset), e)
+                })?;
+                control.report(Progress {
+                    done: docs_split,
+                    total: None,
+                    errors: out.invalid_docs,
+                });
+                if control.is_cancelled() {
+                    break;
+                }
+                for warning in documents.take_warnings() {
+                    let log_text = split_warning(&warning, filetoprocess, entry_name);
+                    db.log_event(idx as isize, &log_text, persistence::LogLevel::Warning)?;
+                }
+                let mut offsets = source_offsets.borrow_mut();
+                doc_source.start_offset = offsets.source_offset(doc.start_offset) as isize;
+                doc_source.end_offset = offsets.source_offset(doc.end_offset) as isize;
+                drop(offsets);
+                doc_source.start_line = doc.start_line as isize;
+                // whitespace between two separators is not stored as a document
+                if doc.text.trim().is_empty() {
+                    let log_text = format!(
+                        "whitespace only text skipped ({})",
+                        source_location(filetoprocess, &doc_source)
+                    );
+                    db.log_event(idx as isize, &log_text, persistence::LogLevel::Warning)?;
+                    continue;
+                }
+                docs_split += 1;
+                // check the doc validity
+                let doc_decode_errors = decode_errors
+                    .borrow_mut()
+                    .count_between(doc.start_offset, doc.end_offset);
+                let doc = doc.text;
+                let validity = check_document(
+                    &doc,
+                    doc_decode_errors,
+                    input_encoding,
+                    expected_root,
+                    expected_namespace,
+                );
+                // look for an earlier copy of a valid document
+                let mut doc_hash = persistence::DocHash {
+                    hash: filesplit::document_hash(&doc),
+                    duplicate_of: None,
+                };
+                let first_copy = match validity {
+                    Ok(()) => db.find_valid_doc_with_hash(&doc_hash.hash)?,
+                    Err(_) => None,
+                };
+                if let Some(first_doc_id) = first_copy {
+                    out.duplicate_docs += 1;
+                    let (action, log_level) = match options.duplicate_policy {
+                        DuplicatePolicy::Keep => ("kept", persistence::LogLevel::Info),
+                        DuplicatePolicy::Flag => ("flagged", persistence::LogLevel::Warning),
+                        DuplicatePolicy::Skip => ("skipped", persistence::LogLevel::Warning),
+                    };
+                    let log_text = format!(
+                        "duplicate of document {} {} ({})",
+                        first_doc_id,
+                        action,
+                        source_location(filetoprocess, &doc_source)
+                    );
+                    match options.duplicate_policy {
+                        DuplicatePolicy::Keep => {}
+                        DuplicatePolicy::Flag => doc_hash.duplicate_of = Some(first_doc_id),
+                        DuplicatePolicy::Skip => {
+                            // the skipped document gets no doc id, the log entry refers to
+                            // the first copy
+                            db.log_event(first_doc_id, &log_text, log_level)?;
+                            doc_source.file_doc_ordinal += 1;
+                            continue;
+                        }
+                    }
+                    db.log_event(idx as isize, &log_text, log_level)?;
+                }
+                // store the doc
+                match validity {
+                    Ok(()) => {
+                        db.store_doc(
+                            idx as isize,
+                            persistence::DocValidity::Valid,
+                            &doc,
+                            "none",
+                            &doc_source,
+                            &doc_hash,
+                        )?;
+                        out.valid_docs += 1;
+                    }
+                    Err(reason) => {
+                        db.store_doc(
+                            idx as isize,
+                            persistence::DocValidity::Invalid,
+                            &doc,
+                            &reason,
+                            &doc_source,
+                            &doc_hash,
+                        )?;
+                        let log_text = format!(
+                            "{} ({})",
+                            reason,
+                            source_location(filetoprocess, &doc_source)
+                        );
+                        db.log_event(idx as isize, &log_text, persistence::LogLevel::Warning)?;
+                        out.invalid_docs += 1;
+                    }
+                }
+                idx += 1;
+                doc_source.file_doc_ordinal += 1;
+            }
+            for warning in documents.take_warnings() {
+                let log_text = split_warning(&warning, filetoprocess, entry_name);
+                db.log_event(idx as isize, &log_text, persistence::LogLevel::Warning)?;
+            }
+            Ok(())
+        })?;
     }
 
-    db.commit_writes();
+    if control.is_cancelled() {
+        out.cancelled = true;
+        let log_text = format!("Split cancelled after {} documents.", docs_split);
+        db.log_run_event(&log_text, persistence::LogLevel::Warning)?;
+    }
+    control.report(Progress {
+        done: docs_split,
+        total: Some(docs_split),
+        errors: out.invalid_docs,
+    });
+
+    db.commit_writes()?;
     db.create_indices(persistence::IndexGroup::ProcessLog)?;
     db.create_indices(persistence::IndexGroup::DocStore)?;
 
-    println!("writes comitted and incices created. value of out is: {out}");
-    match out {
-        false => db.log_event(
-            0,
-            "File successfully split into single documents with invalid docutments found.",
-            persistence::LogLevel::Warning,
-        ),
-        true => db.log_event(
-            0,
+    match out.invalid_docs {
+        0 => db.log_run_event(
             "File successfully split into single documents without any errors.",
             persistence::LogLevel::Info,
         ),
+        _ => db.log_run_event(
+            "File successfully split into single documents with invalid documents found.",
+            persistence::LogLevel::Warning,
+        ),
+    }?;
+    if out.duplicate_docs > 0 {
+        let log_text = format!(
+            "{} duplicate documents found, duplicate policy: {:?}",
+            out.duplicate_docs, options.duplicate_policy
+        );
+        db.log_run_event(&log_text, persistence::LogLevel::Info)?;
     }
+    db.commit_writes()?;
     Ok(out)
 }
 
-pub fn process_file(
+// check a split document for undecodable bytes - `decode_errors` of them were replaced
+// with U+FFFD while transcoding the document - and for well-formedness
+fn check_document(
+    doc: &str,
+    decode_errors: usize,
+    input_encoding: &'static encoding_rs::Encoding,
+    expected_root: Option<&str>,
+    expected_namespace: Option<&str>,
+) -> Result<(), String> {
+    if decode_errors > 0 {
+        return Err(format!(
+            "document contains bytes that can not be decoded as {}",
+            input_encoding.name()
+        ));
+    }
+    xmlparser::check_well_formed(doc, expected_root, expected_namespace)
+}
+
+/// Split all documents in a file without storing anything and summarize the result, used
+/// to try out a split mode on a file.
+///
+/// `head_count` is the number of chunks reported at the start and at the end of the file,
+/// `head_len` the number of characters of the text shown per chunk.
+pub fn preview_split(
+    filetoprocess: &str,
+    split_mode: &filesplit::SplitMode,
+    encoding: Option<&str>,
+    head_count: usize,
+    head_len: usize,
+) -> UtilrsResult<SplitPreview> {
+    let mut sizes: Vec<usize> = Vec::new();
+    let mut first_chunks: Vec<ChunkHead> = Vec::new();
+    let mut last_chunks: VecDeque<ChunkHead> = VecDeque::new();
+    let mut malformed_chunks: Vec<ChunkHead> = Vec::new();
+
+    let input = filesplit::InputFile::open(filetoprocess)?;
+    input.for_each_entry(|entry_name, reader| {
+        let reader = filesplit::DecodingReader::new(reader, encoding)
+            .map_err(|e| UtilrsError::input(filetoprocess, None, e))?;
+        let input_encoding = reader.encoding();
+        let decode_errors = reader.decode_errors();
+        let source_offsets = reader.source_offsets();
+        let mut documents = open_splitter(filetoprocess, reader, split_mode)?;
+        while let Some(doc) = documents.next() {
+            let doc = doc.map_err(|e| {
+                let offset = source_offsets
+                    .borrow_mut()
+                    .source_offset(documents.bytes_consumed());
+                UtilrsError::input(filetoprocess, Some(offset), e)
+            })?;
+            // the whitespace between separators is skipped as by the split
+            if doc.text.trim().is_empty() {
+                continue;
+            }
+            let doc_decode_errors = decode_errors
+                .borrow_mut()
+                .count_between(doc.start_offset, doc.end_offset);
+            let validity = check_document(&doc.text, doc_decode_errors, input_encoding, None, None);
+            let chunk = ChunkHead {
+                index: sizes.len(),
+                archive_entry: entry_name.to_owned(),
+                start_offset: source_offsets.borrow_mut().source_offset(doc.start_offset),
+                start_line: doc.start_line,
+                size: doc.text.len(),
+                head: doc.text.chars().take(head_len).collect(),
+                invalid_reason: validity.err(),
+            };
+            sizes.push(chunk.size);
+            if chunk.invalid_reason.is_some() {
+                malformed_chunks.push(chunk.clone());
+            }
+            if first_chunks.len() < head_count {
+                first_chunks.push(chunk);
+            } else if head_count > 0 {
+                if last_chunks.len() == head_count {
+                    last_chunks.pop_front();
+                }
+                last_chunks.push_back(chunk);
+            }
+        }
+        Ok(())
+    })?;
+
+    sizes.sort_unstable();
+    Ok(SplitPreview {
+        chunks: sizes.len(),
+        min_size: sizes.first().copied().unwrap_or(0),
+        median_size: sizes
+            .get(sizes.len().saturating_sub(1) / 2)
+            .copied()
+            .unwrap_or(0),
+        max_size: sizes.last().copied().unwrap_or(0),
+        first_chunks,
+        last_chunks: last_chunks.into(),
+        malformed_chunks,
+    })
+}
+
+// open the splitter of the split mode over the (transcoded) input of a file
+fn open_splitter<'a, R: std::io::BufRead + 'a>(
+    filetoprocess: &str,
+    reader: R,
+    split_mode: &filesplit::SplitMode,
+) -> UtilrsResult<Box<dyn filesplit::DocumentSource + 'a>> {
+    filesplit::open_splitter(reader, split_mode)
+        .map_err(|e| UtilrsError::split(filetoprocess, None, format!("invalid split regex: {}", e)))
+}
+
+// describe where a document is located in its source file for log entries
+fn source_location(file_path: &str, doc_source: &persistence::DocSource) -> String {
+    format!("source: {}, {}", file_path, doc_source)
+}
+
+// log entry text of a warning reported by the splitter
+fn split_warning(warning: &str, file_path: &str, archive_entry: &str) -> String {
+    match archive_entry {
+        "" => format!("{} (source: {})", warning, file_path),
+        _ => format!(
+            "{} (source: {}, entry '{}')",
+            warning, file_path, archive_entry
+        ),
+    }
+}
+
+// number of parsed documents buffered between the parse workers and the database writer
+const PARSED_DOC_CHANNEL_CAPACITY: usize = 64;
+
+// number of documents read from the database at once for parsing, at most two pages are
+// waiting for the parse workers
+const UNPARSED_DOC_PAGE_SIZE: usize = 256;
+
+// the result of validating a parsed document against its schema
+enum Validation {
+    // no schemas were given
+    NotChecked,
+    // there is no schema for the type and namespace of the document
+    NoSchema,
+    Checked {
+        schema_file: String,
+        violations: Vec<xsd::Violation>,
+    },
+}
+
+// a parsed document on its way from a parse worker to the database writer
+struct ParsedDoc {
+    doc: persistence::TableDocList,
+    xml: Result<xmlparser::XmlDoc, String>,
+    validation: Validation,
+}
+
+fn parse_document(
+    doc: persistence::TableDocList,
+    options: &xmlparser::ParseOptions,
+    schemas: Option<&xsd::SchemaStore>,
+) -> ParsedDoc {
+    let xml = xmlparser::XmlDoc::with_options(doc.doc_id as usize, &doc.doc_text, options)
+        .map_err(|err| err.to_string());
+    let validation = match (&xml, schemas) {
+        (Ok(xml), Some(schemas)) => {
+            match schemas.schema_for(&xml.doc_type, xml.default_namespace.as_deref()) {
+                Some(schema) => Validation::Checked {
+                    schema_file: schema.file.to_owned(),
+                    violations: schema.validate(&doc.doc_text),
+                },
+                None => Validation::NoSchema,
+            }
+        }
+        _ => Validation::NotChecked,
+    };
+    ParsedDoc {
+        doc,
+        xml,
+        validation,
+    }
+}
+
+// log the result of the schema validation of a parsed document and record the parse status,
+// a document violating its schema is marked invalid. Returns false if the document is
+// invalid.
+fn store_validation(
+    db: &mut DataBase,
+    xml_parsed: &xmlparser::XmlDoc,
+    doc_id: isize,
+    validation: Validation,
+) -> UtilrsResult<bool> {
+    let parse_status = match validation {
+        Validation::NotChecked => persistence::ParseStatus::Parsed,
+        Validation::NoSchema => {
+            let log_text = format!(
+                "No schema found for document type '{}' and namespace '{}', the document is \
+                not validated",
+                xml_parsed.doc_type,
+                xml_parsed.default_namespace.as_deref().unwrap_or("")
+            );
+            db.log_event(doc_id, &log_text, persistence::LogLevel::Warning)?;
+            persistence::ParseStatus::Parsed
+        }
+        Validation::Checked {
+            schema_file,
+            violations,
+        } if violations.is_empty() => {
+            let log_text = format!("Document is valid against schema '{}'", schema_file);
+            db.log_event(doc_id, &log_text, persistence::LogLevel::Info)?;
+            persistence::ParseStatus::Parsed
+        }
+        Validation::Checked {
+            schema_file,
+            violations,
+        } => {
+            for violation in &violations {
+                let log_text = format!(
+                    "Schema violation at {}: expected {}, found {}",
+                    violation.path, violation.expected, violation.found
+                );
+                db.log_event(doc_id, &log_text, persistence::LogLevel::Error)?;
+            }
+            let reason = format!(
+                "{} violation(s) of schema '{}', first: {}",
+                violations.len(),
+                schema_file,
+                violations[0]
+            );
+            db.set_doc_validity(doc_id, persistence::DocValidity::Invalid, &reason)?;
+            persistence::ParseStatus::SchemaInvalid
+        }
+    };
+    db.set_parse_status(doc_id, parse_status)?;
+    Ok(parse_status == persistence::ParseStatus::Parsed)
+}
+
+// store a parsed document or log why it could not be parsed, returns false on a parse error
+// or if the document violates its schema
+fn store_parsed_document(
+    db: &mut DataBase,
+    parsed: ParsedDoc,
+    source_files: &HashMap<isize, String>,
+) -> UtilrsResult<bool> {
+    let doc = &parsed.doc;
+    match parsed.xml {
+        Ok(xml_parsed) => {
+            db.store_xml_parsed(doc.doc_id, &xml_parsed)?;
+            let log_text = "Document successfully loaded";
+            db.log_event(doc.doc_id, log_text, persistence::LogLevel::Info)?;
+            store_validation(db, &xml_parsed, doc.doc_id, parsed.validation)
+        }
+        Err(err) => {
+            let file_path = source_files
+                .get(&doc.file_id)
+                .map(|path| path.as_str())
+                .unwrap_or("unknown file");
+            let log_text = format!(
+                "The following error ocurred while parsing the xml document: {} ({})",
+                err,
+                source_location(file_path, &doc.get_source())
+            );
+            db.log_event(doc.doc_id, &log_text, persistence::LogLevel::Error)?;
+            db.set_parse_status(doc.doc_id, persistence::ParseStatus::Failed)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Parse all valid documents which have not been parsed yet and store the results to the
+/// database
+pub fn process_file(databasename: &str, configfilename: &str) -> UtilrsResult<String> {
+    process_file_with_workers(
+        databasename,
+        configfilename,
+        1,
+        &xmlparser::ParseOptions::default(),
+        None,
+        false,
+        &RunControl::new(),
+    )
+}
+
+/// Parse all valid documents which have not been parsed yet with the parse `options` using
+/// `workers` threads, 0 uses one thread per available CPU. Documents which could not be
+/// parsed by an earlier run are skipped, with `reparse` the stored parse results are
+/// removed and all documents which are valid or only violated their schema are parsed
+/// again, e.g. with changed options. With
+/// `schemas` every parsed document is validated against the schema for its type or
+/// namespace, each violation is logged and a document violating its schema is marked
+/// invalid. The parts of the schemas which are not checked are logged as warnings.
+/// Returns "success", "error" if a document could not be parsed or is invalid against its
+/// schema or "cancelled".
+///
+/// The calling thread is the single writer owning the database connection. It reads the
+/// documents to parse page by page and hands them to the parse workers, which send the
+/// parsed documents back over a bounded channel. The stored results
+/// are the same as with a single worker, only the order in which the documents are
+/// written may differ.
+pub fn process_file_with_workers(
     databasename: &str,
     configfilename: &str,
-) -> Result<String, Box<dyn std::error::Error>> {
+    workers: usize,
+    options: &xmlparser::ParseOptions,
+    schemas: Option<&xsd::SchemaStore>,
+    reparse: bool,
+    control: &RunControl,
+) -> UtilrsResult<String> {
     let mut out = "success".to_string();
+    let workers = match workers {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
 
-    let mut db = DataBase::new(databasename, configfilename);
-
-    // truncate the process log and remove indices
-    db.drop_indices(persistence::IndexGroup::ProcessLog)?;
-    db.truncate_process_log()?;
-
-    // check if file has already been processed
-    // if yes, truncate all xlm related tables and remove indices
-    if db.get_xml_count() > 0 {
-        db.drop_indices(persistence::IndexGroup::XmlStore)?;
+    let mut db = DataBase::new(databasename, configfilename)?;
+    if reparse {
         db.truncate_xml_store()?;
+        db.reset_parse_status()?;
+        let log_text = "Parse results removed, all valid documents are parsed again";
+        db.log_run_event(log_text, persistence::LogLevel::Info)?;
+    }
+    for warning in schemas.into_iter().flat_map(|schemas| schemas.warnings()) {
+        db.log_run_event(&warning, persistence::LogLevel::Warning)?;
     }
 
-    // process the data
-    let docs_to_process = db.get_doc_count(persistence::DocValidity::Valid);
-    println!("No of docs to process: {}", docs_to_process);
+    // only documents which have not been parsed yet are processed, documents appended to
+    // the database since the last run are parsed without parsing all others again. They
+    // are read page by page on the parse status index, so only the documents being parsed
+    // are held in memory and each page only reads its own documents.
+    db.create_indices(persistence::IndexGroup::DocStore)?;
+    let docs_to_process = db.get_unparsed_doc_count(persistence::DocValidity::Valid, false)?;
+    let log_text = format!("{} documents to process", docs_to_process);
+    db.log_run_event(&log_text, persistence::LogLevel::Info)?;
+    control.report(Progress {
+        done: 0,
+        total: Some(docs_to_process),
+        errors: 0,
+    });
+    let mut last_doc_id = -1;
+    let mut next_page = |db: &mut DataBase| -> UtilrsResult<Vec<persistence::TableDocList>> {
+        let page = db.get_unparsed_docs_after(
+            last_doc_id,
+            UNPARSED_DOC_PAGE_SIZE,
+            persistence::DocValidity::Valid,
+            false,
+        )?;
+        if let Some(doc) = page.last() {
+            last_doc_id = doc.doc_id;
+        }
+        Ok(page)
+    };
+
+    // remove the indices while inserting
+    db.drop_indices(persistence::IndexGroup::ProcessLog)?;
+    db.drop_indices(persistence::IndexGroup::XmlStore)?;
 
-    let documents = db.get_all_docs(persistence::DocValidity::Valid);
-    for doc in documents {
-        // status update
-        // calculate the pct and then round to 2 decimal places
-        let mut progress_pct: f32 = (doc.doc_id as f32 * 100f32) / docs_to_process as f32;
-        progress_pct = (progress_pct * 100.0).round() / 100.0;
-        // display update if new progress is an integer value
-        if progress_pct.fract() == 0.0 {
-            print!("\rProcessing documents is at {:.0}%...", progress_pct);
+    // the source files are needed to point to the location of a failing document
+    let source_files: HashMap<isize, String> = db
+        .get_all_source_files()?
+        .into_iter()
+        .map(|f| (f.file_id, f.file_path))
+        .collect();
+
+    let mut parse_errors = 0;
+    let mut docs_done = 0;
+    let mut store_result = |db: &mut DataBase, parsed: ParsedDoc| -> UtilrsResult<()> {
+        if !store_parsed_document(db, parsed, &source_files)? {
+            parse_errors += 1;
         }
+        docs_done += 1;
+        control.report(Progress {
+            done: docs_done,
+            total: Some(docs_to_process),
+            errors: parse_errors,
+        });
+        Ok(())
+    };
 
-        match xmlparser::XmlDoc::new(
-            doc.doc_id as usize,
-            &doc.doc_text,
-            xmlparser::AttributeUsage::AddSeparateTag,
-            None,
-            None,
-        ) {
-            Ok(xml_parsed) => {
-                db.store_xml_parsed(doc.doc_id, &xml_parsed);
-                let log_text = format!("Document successfully loaded");
-                db.log_event(doc.doc_id, &log_text, persistence::LogLevel::Info);
+    if workers == 1 {
+        'pages: loop {
+            let page = next_page(&mut db)?;
+            if page.is_empty() {
+                break;
             }
-            Err(err) => {
-                let log_text = format!(
-                    "The following error ocurred while parsing the xml document: {}",
-                    err
-                );
-                db.log_event(doc.doc_id, &log_text, persistence::LogLevel::Error);
-                out = "error".to_string();
+            for doc in page {
+                if control.is_cancelled() {
+                    break 'pages;
+                }
+                store_result(&mut db, parse_document(doc, options, schemas))?;
             }
         }
+    } else {
+        thread::scope(|scope| -> UtilrsResult<()> {
+            // the channels are dropped when storing a document fails, which stops the workers
+            let (doc_tx, doc_rx) = mpsc::channel();
+            let doc_rx = Arc::new(Mutex::new(doc_rx));
+            let (parsed_tx, parsed_rx) = mpsc::sync_channel(PARSED_DOC_CHANNEL_CAPACITY);
+            for _ in 0..workers {
+                let parsed_tx = parsed_tx.clone();
+                let doc_rx = Arc::clone(&doc_rx);
+                scope.spawn(move || {
+                    while !control.is_cancelled() {
+                        let doc = match doc_rx.lock().map(|doc_rx| doc_rx.recv()) {
+                            Ok(Ok(doc)) => doc,
+                            _ => break,
+                        };
+                        // the writer only stops receiving when storing a document failed
+                        if parsed_tx
+                            .send(parse_document(doc, options, schemas))
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(parsed_tx);
+
+            // the writer reads the next pages while the workers parse, the workers stop
+            // once the sender is dropped after the last page or on a cancellation
+            let mut doc_tx = Some(doc_tx);
+            let mut docs_queued = 0;
+            loop {
+                while docs_queued < 2 * UNPARSED_DOC_PAGE_SIZE {
+                    let sender = match &doc_tx {
+                        Some(sender) if !control.is_cancelled() => sender,
+                        _ => break,
+                    };
+                    let page = next_page(&mut db)?;
+                    if page.is_empty() {
+                        doc_tx = None;
+                        break;
+                    }
+                    docs_queued += page.len();
+                    for doc in page {
+                        // sending only fails if all workers stopped on a cancellation
+                        let _ = sender.send(doc);
+                    }
+                }
+                if control.is_cancelled() {
+                    doc_tx = None;
+                }
+                if doc_tx.is_none() && docs_queued == 0 {
+                    break;
+                }
+                // documents parsed before a cancellation are still stored
+                match parsed_rx.recv() {
+                    Ok(parsed) => {
+                        docs_queued -= 1;
+                        store_result(&mut db, parsed)?;
+                    }
+                    Err(_) => break,
+                }
+            }
+            Ok(())
+        })?;
+    }
+
+    // a cancellation while storing the last document does not stop anything anymore
+    if docs_done < docs_to_process {
+        out = "cancelled".to_string();
+        let log_text = format!(
+            "Processing cancelled after {} of {} documents.",
+            docs_done, docs_to_process
+        );
+        db.log_run_event(&log_text, persistence::LogLevel::Warning)?;
+    } else if parse_errors > 0 {
+        out = "error".to_string();
     }
 
     // final commit of changes to the database
-    db.commit_writes();
+    db.commit_writes()?;
 
     // once all data is processed and stored, create the indices
     db.create_indices(persistence::IndexGroup::ProcessLog)?;
@@ -126,9 +908,7 @@ pub fn process_file(
     Ok(out)
 }
 
-pub fn process_single_document(
-    doc_content: &str,
-) -> Result<Vec<xmlparser::Tag>, Box<dyn std::error::Error>> {
+pub fn process_single_document(doc_content: &str) -> UtilrsResult<Vec<xmlparser::Tag>> {
     // let xml = r#"<?xml version="1.0" encoding="UTF-8"?><Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><CstmrDrctDbtInitn><GrpHdr><MsgId>yd5oBwTm19W2rZG3</MsgId><CreDtTm>2013-10-08T12:57:52</CreDtTm><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><InitgPty><Nm>PILOTFORETAG B</Nm><Id><OrgId><Othr><Id>7158637412</Id><SchmeNm><Cd>BANK</Cd></SchmeNm></Othr></OrgId></Id></InitgPty></GrpHdr><PmtInf><PmtInfId>SEND PAYMENT VER 009</PmtInfId><PmtMtd>DD</PmtMtd><BtchBookg>true</BtchBookg><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><PmtTpInf><SvcLvl><Cd>SEPA</Cd></SvcLvl><LclInstrm><Cd>B2B</Cd></LclInstrm><SeqTp>RCUR</SeqTp></PmtTpInf><ReqdColltnDt>2013-11-08</ReqdColltnDt><Cdtr><Nm>PILOTFORETAG B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr></Cdtr><CdtrAcct><Id><IBAN>CH23885378935554937471</IBAN></Id></CdtrAcct><CdtrAgt><FinInstnId><BIC>HANDNL2A</BIC></FinInstnId></CdtrAgt><CdtrSchmeId><Id><PrvtId><Othr><Id>CH13546501204560291467</Id><SchmeNm><Prtry>SEPA</Prtry></SchmeNm></Othr></PrvtId></Id></CdtrSchmeId><DrctDbtTxInf><PmtId><EndToEndId>BMO1 SEND PROD VER 10 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">49975405.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER8</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>Pilot B</Nm><PstlAdr><Ctry>NL</Ctry></PstlAdr><Id><OrgId><Othr><Id>5497683033</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 1</Ustrd></RmtInf></DrctDbtTxInf><DrctDbtTxInf><PmtId><EndToEndId>BMO2 SEND PROD VER 11 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">6489979.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER9</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>PILOT B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr><Id><OrgId><Othr><Id>7159672956</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 2</Ustrd></RmtInf></DrctDbtTxInf></PmtInf></CstmrDrctDbtInitn></Document>"#;
     // let xml_inv = r#"<Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance">"#;
     // process the valid doc
@@ -143,66 +923,169 @@ pub fn process_single_document(
         xmlparser::AttributeUsage::AddSeparateTag,
         None,
         None,
-    )
-    .unwrap();
-    let doc_tags_n_values: Vec<Tag> = parsed_xml.tags_n_values.unwrap();
+    )?;
+    let doc_tags_n_values: Vec<Tag> = parsed_xml.tags_n_values.unwrap_or_default();
     Ok(doc_tags_n_values)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Write;
 
-    #[test]
-    fn parse_and_store_xml() {
-        let mut db = DataBase::new("xml_parser_test.db", "tabledef.yaml");
+    const PAIN_008: &str = r#"<?xml version="1.0" encoding="UTF-8"?><Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><CstmrDrctDbtInitn><GrpHdr><MsgId>yd5oBwTm19W2rZG3</MsgId><CreDtTm>2013-10-08T12:57:52</CreDtTm><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><InitgPty><Nm>PILOTFORETAG B</Nm><Id><OrgId><Othr><Id>7158637412</Id><SchmeNm><Cd>BANK</Cd></SchmeNm></Othr></OrgId></Id></InitgPty></GrpHdr><PmtInf><PmtInfId>SEND PAYMENT VER 009</PmtInfId><PmtMtd>DD</PmtMtd><BtchBookg>true</BtchBookg><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><PmtTpInf><SvcLvl><Cd>SEPA</Cd></SvcLvl><LclInstrm><Cd>B2B</Cd></LclInstrm><SeqTp>RCUR</SeqTp></PmtTpInf><ReqdColltnDt>2013-11-08</ReqdColltnDt><Cdtr><Nm>PILOTFORETAG B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr></Cdtr><CdtrAcct><Id><IBAN>CH23885378935554937471</IBAN></Id></CdtrAcct><CdtrAgt><FinInstnId><BIC>HANDNL2A</BIC></FinInstnId></CdtrAgt><CdtrSchmeId><Id><PrvtId><Othr><Id>CH13546501204560291467</Id><SchmeNm><Prtry>SEPA</Prtry></SchmeNm></Othr></PrvtId></Id></CdtrSchmeId><DrctDbtTxInf><PmtId><EndToEndId>BMO1 SEND PROD VER 10 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">49975405.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER8</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>Pilot B</Nm><PstlAdr><Ctry>NL</Ctry></PstlAdr><Id><OrgId><Othr><Id>5497683033</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 1</Ustrd></RmtInf></DrctDbtTxInf><DrctDbtTxInf><PmtId><EndToEndId>BMO2 SEND PROD VER 11 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">6489979.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER9</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>PILOT B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr><Id><OrgId><Othr><Id>7159672956</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 2</Ustrd></RmtInf></DrctDbtTxInf></PmtInf></CstmrDrctDbtInitn></Document>"#;
+
+    // get a database / input file path in the temp directory, each test uses its own
+    // database as the connection is opened in exclusive locking mode
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir().join(name).to_str().unwrap().to_owned()
+    }
+
+    fn purge(db_name: &str) {
+        let mut db = DataBase::new(db_name, "tabledef.yaml").unwrap();
         db.truncate_doc_store().unwrap();
         db.truncate_process_log().unwrap();
         db.truncate_xml_store().unwrap();
         db.drop_indices(persistence::IndexGroup::DocStore).unwrap();
+        db.drop_indices(persistence::IndexGroup::DocHash).unwrap();
         db.drop_indices(persistence::IndexGroup::ProcessLog)
             .unwrap();
         db.drop_indices(persistence::IndexGroup::XmlStore).unwrap();
-        let xml = r#"<?xml version="1.0" encoding="UTF-8"?><Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><CstmrDrctDbtInitn><GrpHdr><MsgId>yd5oBwTm19W2rZG3</MsgId><CreDtTm>2013-10-08T12:57:52</CreDtTm><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><InitgPty><Nm>PILOTFORETAG B</Nm><Id><OrgId><Othr><Id>7158637412</Id><SchmeNm><Cd>BANK</Cd></SchmeNm></Othr></OrgId></Id></InitgPty></GrpHdr><PmtInf><PmtInfId>SEND PAYMENT VER 009</PmtInfId><PmtMtd>DD</PmtMtd><BtchBookg>true</BtchBookg><NbOfTxs>2</NbOfTxs><CtrlSum>56465384.0</CtrlSum><PmtTpInf><SvcLvl><Cd>SEPA</Cd></SvcLvl><LclInstrm><Cd>B2B</Cd></LclInstrm><SeqTp>RCUR</SeqTp></PmtTpInf><ReqdColltnDt>2013-11-08</ReqdColltnDt><Cdtr><Nm>PILOTFORETAG B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr></Cdtr><CdtrAcct><Id><IBAN>CH23885378935554937471</IBAN></Id></CdtrAcct><CdtrAgt><FinInstnId><BIC>HANDNL2A</BIC></FinInstnId></CdtrAgt><CdtrSchmeId><Id><PrvtId><Othr><Id>CH13546501204560291467</Id><SchmeNm><Prtry>SEPA</Prtry></SchmeNm></Othr></PrvtId></Id></CdtrSchmeId><DrctDbtTxInf><PmtId><EndToEndId>BMO1 SEND PROD VER 10 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">49975405.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER8</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>Pilot B</Nm><PstlAdr><Ctry>NL</Ctry></PstlAdr><Id><OrgId><Othr><Id>5497683033</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 1</Ustrd></RmtInf></DrctDbtTxInf><DrctDbtTxInf><PmtId><EndToEndId>BMO2 SEND PROD VER 11 1106</EndToEndId></PmtId><InstdAmt Ccy="EUR">6489979.0</InstdAmt><ChrgBr>SLEV</ChrgBr><DrctDbtTx><MndtRltdInf><MndtId>PRODVER9</MndtId><DtOfSgntr>2011-10-01</DtOfSgntr></MndtRltdInf></DrctDbtTx><DbtrAgt><FinInstnId><BIC>HANDDEFF</BIC></FinInstnId></DbtrAgt><Dbtr><Nm>PILOT B</Nm><PstlAdr><Ctry>DE</Ctry></PstlAdr><Id><OrgId><Othr><Id>7159672956</Id><SchmeNm><Cd>CUST</Cd></SchmeNm></Othr></OrgId></Id></Dbtr><DbtrAcct><Id><IBAN>CH89549400409945581319</IBAN></Id></DbtrAcct><RmtInf><Ustrd>Invoice 2</Ustrd></RmtInf></DrctDbtTxInf></PmtInf></CstmrDrctDbtInitn></Document>"#;
-        let parsed_xml = XmlDoc::new(1, xml, AttributeUsage::AddSeparateTag, None, None).unwrap();
-        db.store_xml_parsed(1, &parsed_xml);
-        db.commit_writes();
     }
+
+    #[test]
+    fn parse_and_store_xml() {
+        let db_name = temp_path("utilrs_parse_and_store_xml.db");
+        purge(&db_name);
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let parsed_xml =
+            XmlDoc::new(1, PAIN_008, AttributeUsage::AddSeparateTag, None, None).unwrap();
+        db.store_xml_parsed(1, &parsed_xml).unwrap();
+        db.commit_writes().unwrap();
+        assert_eq!(db.get_xml_count().unwrap(), 1);
+    }
+
+    #[test]
+    fn write_parsed_xml_round_trip() {
+        let parsed_xml =
+            XmlDoc::new(1, PAIN_008, AttributeUsage::AddSeparateTag, None, None).unwrap();
+        let compact = xmlparser::WriteOptions {
+            indent: None,
+            declaration: true,
+        };
+        assert_eq!(parsed_xml.to_xml(&compact), PAIN_008);
+
+        let pretty = xmlparser::WriteOptions {
+            indent: Some(4),
+            declaration: false,
+        };
+        let written = parsed_xml.to_xml(&pretty);
+        assert!(written.starts_with("<Document xmlns="));
+        assert!(written.contains("\n            <MsgId>yd5oBwTm19W2rZG3</MsgId>\n"));
+        let reparsed =
+            XmlDoc::new(1, &written, AttributeUsage::AddSeparateTag, None, None).unwrap();
+        assert_eq!(reparsed.xml_parsed, parsed_xml.xml_parsed);
+        assert_eq!(reparsed.doc_type, parsed_xml.doc_type);
+    }
+
+    #[test]
+    fn diff_reconciled_pain_008() {
+        let original =
+            XmlDoc::new(1, PAIN_008, AttributeUsage::AddSeparateTag, None, None).unwrap();
+        // the downstream version lists the transactions in reverse order and changed the
+        // amount of the first one
+        let start = PAIN_008.find("<DrctDbtTxInf>").unwrap();
+        let second = PAIN_008.rfind("<DrctDbtTxInf>").unwrap();
+        let end = PAIN_008.find("</PmtInf>").unwrap();
+        let produced = format!(
+            "{}{}{}{}",
+            &PAIN_008[..start],
+            &PAIN_008[second..end],
+            PAIN_008[start..second].replace("49975405.0", "49975406.0"),
+            &PAIN_008[end..]
+        );
+        let produced =
+            XmlDoc::new(2, &produced, AttributeUsage::AddSeparateTag, None, None).unwrap();
+
+        let in_order = original.diff(&produced, &xmlparser::DiffOptions::default());
+        assert!(in_order.len() > 10);
+        let options = xmlparser::DiffOptions {
+            ignore_order: true,
+            ignore_whitespace: false,
+        };
+        let changes = original.diff(&produced, &options);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].kind, xmlparser::ChangeKind::Changed);
+        assert_eq!(
+            changes[0].path,
+            "/Document/CstmrDrctDbtInitn/PmtInf/DrctDbtTxInf[1]/InstdAmt"
+        );
+        assert_eq!(changes[0].old_value.as_deref(), Some("49975405.0"));
+        assert_eq!(changes[0].new_value.as_deref(), Some("49975406.0"));
+        assert!(xmlparser::diff_report("original", "produced", &changes)
+            .ends_with("-49975405.0\n+49975406.0\n"));
+    }
+
     #[test]
     fn split_and_process_file() {
-        let mut db = DataBase::new("xml_parser_test.db", "tabledef.yaml");
-        db.truncate_doc_store().unwrap();
-        db.truncate_process_log().unwrap();
-        db.truncate_xml_store().unwrap();
-        db.drop_indices(persistence::IndexGroup::DocStore).unwrap();
-        db.drop_indices(persistence::IndexGroup::ProcessLog)
-            .unwrap();
-        db.drop_indices(persistence::IndexGroup::XmlStore).unwrap();
-        drop(db);
+        let db_name = temp_path("utilrs_split_and_process_file.db");
+        purge(&db_name);
+
+        let file_to_process = temp_path("utilrs_split_and_process_file.xml");
+        let mut file = std::fs::File::create(&file_to_process).unwrap();
+        for _ in 0..120 {
+            writeln!(file, "{}", PAIN_008).unwrap();
+        }
+        drop(file);
 
         // Start the timer
         let mut start = Instant::now();
 
-        let file_to_process = "P:/Programming/Python/xml_examples/xml_test_data_large.xml";
-        let reg_ex = r"(<\?xml .*?>)";
+        let split_mode = SplitMode::Regex {
+            reg_ex: r"(<\?xml .*?>)".to_string(),
+            delimiter: DelimiterPlacement::AttachToNext,
+        };
 
         let res = split_file(
-            "xml_parser_test.db",
+            &db_name,
             "tabledef.yaml",
-            file_to_process,
-            reg_ex,
+            &file_to_process,
+            &split_mode,
+            &SplitOptions {
+                expected_root: Some("Document".to_string()),
+                expected_namespace: Some(
+                    "urn:iso:std:iso:20022:tech:xsd:pain.008.001.02".to_string(),
+                ),
+                ..Default::default()
+            },
+            &RunControl::new(),
         )
         .unwrap();
-        assert_eq!(res, true);
+        assert_eq!(
+            res,
+            SplitSummary {
+                valid_docs: 120,
+                invalid_docs: 0,
+                duplicate_docs: 119,
+                cancelled: false
+            }
+        );
 
         // Stop the timer
         let mut duration = start.elapsed();
 
         println!("file split and stored to DB in {:?} ", duration);
 
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(
+            db.get_doc_count(persistence::DocValidity::Valid).unwrap(),
+            120
+        );
+        drop(db);
+
         start = Instant::now();
 
-        let res1 = process_file("xml_parser_test.db", "tabledef.yaml").unwrap();
+        let res1 = process_file(&db_name, "tabledef.yaml").unwrap();
         assert_eq!(res1, "success".to_string());
 
         // Stop the timer
@@ -213,14 +1096,1032 @@ mod tests {
 
     #[test]
     fn purge_database() {
-        let mut db = DataBase::new("xml_parser_test.db", "tabledef.yaml");
-        db.truncate_doc_store().unwrap();
-        db.truncate_process_log().unwrap();
-        db.truncate_xml_store().unwrap();
-        db.drop_indices(persistence::IndexGroup::DocStore).unwrap();
-        db.drop_indices(persistence::IndexGroup::ProcessLog)
+        let db_name = temp_path("utilrs_purge_database.db");
+        purge(&db_name);
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(
+            db.get_doc_count(persistence::DocValidity::Valid).unwrap(),
+            0
+        );
+        assert_eq!(db.get_xml_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn split_file_flags_invalid_documents() {
+        let file_to_process = temp_path("utilrs_split_file_flags_invalid_documents.xml");
+        let content = format!(
+            "{}\n<?xml version=\"1.0\"?><Document><A></Document>\n{}\n",
+            PAIN_008, PAIN_008
+        );
+        std::fs::write(&file_to_process, content).unwrap();
+
+        // the split modes with the length of the invalid document, the regex split keeps
+        // the line break up to the next separator
+        let split_modes = [
+            (
+                SplitMode::Regex {
+                    reg_ex: r"(<\?xml .*?>)".to_string(),
+                    delimiter: DelimiterPlacement::AttachToNext,
+                },
+                46,
+            ),
+            // the malformed document does not stop the split at the element structure
+            (
+                SplitMode::XmlStructure {
+                    root_element: Some("Document".to_string()),
+                },
+                45,
+            ),
+        ];
+        for (split_mode, invalid_len) in split_modes.iter() {
+            let db_name = temp_path("utilrs_split_file_flags_invalid_documents.db");
+            purge(&db_name);
+            let res = split_file(
+                &db_name,
+                "tabledef.yaml",
+                &file_to_process,
+                split_mode,
+                &SplitOptions::default(),
+                &RunControl::new(),
+            )
             .unwrap();
-        db.drop_indices(persistence::IndexGroup::XmlStore).unwrap();
+            assert_eq!(
+                res,
+                SplitSummary {
+                    valid_docs: 2,
+                    invalid_docs: 1,
+                    duplicate_docs: 1,
+                    cancelled: false
+                },
+                "{:?}",
+                split_mode
+            );
+
+            let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+            let invalid = db.get_all_docs(persistence::DocValidity::Invalid).unwrap();
+            assert_eq!(invalid.len(), 1);
+            assert_eq!(invalid[0].doc_id, 1);
+            assert!(invalid[0].doc_invalid_reason.starts_with("not well-formed"));
+            // the invalid doc is the 2nd line of the file
+            let first_len = PAIN_008.len() as isize + 1;
+            assert_eq!(invalid[0].start_line, 2);
+            assert_eq!(invalid[0].start_offset, first_len);
+            assert_eq!(invalid[0].end_offset, first_len + invalid_len);
+        }
+    }
+
+    #[test]
+    fn split_file_transcodes_and_reports_undecodable_documents() {
+        let db_name = temp_path("utilrs_split_file_transcodes.db");
+        purge(&db_name);
+
+        let file_to_process = temp_path("utilrs_split_file_transcodes.xml");
+        let mut content: Vec<u8> = Vec::new();
+        content.extend_from_slice(b"<?xml version=\"1.0\"?><Nm>M\xfcller</Nm>\n");
+        content.extend_from_slice(b"<?xml version=\"1.0\"?><Nm>Gen\xe8ve</Nm>\n");
+        // a replacement character which was in the input is not a decode error
+        content.extend_from_slice("<?xml version=\"1.0\"?><Nm>\u{FFFD}</Nm>\n".as_bytes());
+        std::fs::write(&file_to_process, &content).unwrap();
+
+        let split_mode = SplitMode::Regex {
+            reg_ex: r"(<\?xml .*?>)".to_string(),
+            delimiter: DelimiterPlacement::AttachToNext,
+        };
+        // read as UTF-8 both documents contain invalid bytes
+        let res = split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &split_mode,
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!((res.valid_docs, res.invalid_docs), (1, 2));
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let invalid = db.get_all_docs(persistence::DocValidity::Invalid).unwrap();
+        assert!(invalid[0].doc_invalid_reason.contains("UTF-8"));
+        let valid = db.get_all_docs(persistence::DocValidity::Valid).unwrap();
+        assert_eq!(valid[0].doc_id, 2);
+        drop(db);
+
+        purge(&db_name);
+        let res = split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &split_mode,
+            &SplitOptions {
+                encoding: Some("ISO-8859-1".to_string()),
+                ..Default::default()
+            },
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(res.valid_docs, 3);
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let valid = db.get_all_docs(persistence::DocValidity::Valid).unwrap();
+        assert_eq!(
+            valid[0].doc_text,
+            "<?xml version=\"1.0\"?><Nm>Müller</Nm>\n"
+        );
+        assert_eq!(
+            valid[1].doc_text,
+            "<?xml version=\"1.0\"?><Nm>Genève</Nm>\n"
+        );
+        // the offsets are the ones in the file, not in the text decoded to UTF-8
+        let first_len = content.iter().position(|&b| b == b'\n').unwrap() as isize + 1;
+        assert_eq!((valid[0].start_offset, valid[0].end_offset), (0, first_len));
+        assert_eq!(
+            (valid[1].start_offset, valid[1].end_offset),
+            (first_len, 2 * first_len)
+        );
+    }
+
+    #[test]
+    fn split_directory_with_file_provenance() {
+        let db_name = temp_path("utilrs_split_directory.db");
+        purge(&db_name);
+
+        let dir = std::env::temp_dir().join("utilrs_split_directory");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("day1.xml"),
+            format!("{}\n{}\n", PAIN_008, PAIN_008),
+        )
+        .unwrap();
+        std::fs::write(dir.join("day2.xml"), format!("{}\n", PAIN_008)).unwrap();
+
+        let split_mode = SplitMode::XmlStructure {
+            root_element: Some("Document".to_string()),
+        };
+        let res = split_files(
+            &db_name,
+            "tabledef.yaml",
+            dir.join("day*.xml").to_str().unwrap(),
+            &split_mode,
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(res.valid_docs, 3);
+
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let files = db.get_all_source_files().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files[0].file_path.ends_with("day1.xml"));
+        assert_eq!(files[0].file_checksum.len(), 64);
+
+        let docs = db.get_all_docs(persistence::DocValidity::Valid).unwrap();
+        let provenance: Vec<(isize, isize, isize)> = docs
+            .iter()
+            .map(|d| (d.doc_id, d.file_id, d.file_doc_ordinal))
+            .collect();
+        let first = files[0].file_id;
+        assert_eq!(
+            provenance,
+            vec![(0, first, 0), (1, first, 1), (2, first + 1, 0)]
+        );
+    }
+
+    #[test]
+    fn preview_split_summarizes_chunks() {
+        let file_to_process = temp_path("utilrs_preview_split.xml");
+        std::fs::write(
+            &file_to_process,
+            "<A>1</A>\n \n<B>22</B>\n<C>333\n<D>4444</D>\n<E/>",
+        )
+        .unwrap();
+        let split_mode = SplitMode::Regex {
+            reg_ex: r"\n".to_string(),
+            delimiter: DelimiterPlacement::Drop,
+        };
+        let preview = preview_split(&file_to_process, &split_mode, None, 2, 4).unwrap();
+        assert_eq!(preview.chunks, 5);
+        assert_eq!(
+            (preview.min_size, preview.median_size, preview.max_size),
+            (4, 8, 11)
+        );
+        let heads = |chunks: &[ChunkHead]| -> Vec<(usize, String)> {
+            chunks.iter().map(|c| (c.index, c.head.clone())).collect()
+        };
+        assert_eq!(
+            heads(&preview.first_chunks),
+            vec![(0, "<A>1".to_string()), (1, "<B>2".to_string())]
+        );
+        assert_eq!(
+            heads(&preview.last_chunks),
+            vec![(3, "<D>4".to_string()), (4, "<E/>".to_string())]
+        );
+        assert_eq!(preview.malformed_chunks.len(), 1);
+        let malformed = &preview.malformed_chunks[0];
+        assert_eq!((malformed.index, malformed.start_line), (2, 4));
+        assert!(malformed
+            .invalid_reason
+            .as_ref()
+            .unwrap()
+            .starts_with("not well-formed"));
+
+        // the split skips the whitespace chunk like the preview
+        let db_name = temp_path("utilrs_preview_split.db");
+        purge(&db_name);
+        let res = split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &split_mode,
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!((res.valid_docs, res.invalid_docs), (4, 1));
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let warnings = db.get_log_entries(LogLevel::Warning, Some(1)).unwrap();
+        assert_eq!(
+            warnings[0].log_entry,
+            format!(
+                "whitespace only text skipped (source: {}, line 2, bytes 9-10)",
+                file_to_process
+            )
+        );
+    }
+
+    #[test]
+    fn split_files_handles_duplicates() {
+        let dir = std::env::temp_dir().join("utilrs_split_files_handles_duplicates");
+        std::fs::create_dir_all(&dir).unwrap();
+        let doc_a = "<A><X>1</X></A>";
+        std::fs::write(dir.join("day1.xml"), format!("{}<B>2</B>{}", doc_a, doc_a)).unwrap();
+        // a re-indented copy of A is a duplicate as well
+        std::fs::write(dir.join("day2.xml"), "<A>\r\n  <X>1</X>\r\n</A>\n<C>3</C>").unwrap();
+        let split_mode = SplitMode::XmlStructure { root_element: None };
+        let pattern = dir.join("day*.xml");
+
+        // flag: all documents are stored, duplicates refer to the first copy, also to
+        // documents stored to the database before
+        let db_name = temp_path("utilrs_split_files_flag_duplicates.db");
+        purge(&db_name);
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let hash = persistence::DocHash {
+            hash: filesplit::document_hash("<C>3</C>"),
+            duplicate_of: None,
+        };
+        let source = persistence::DocSource::default();
+        db.store_doc(100, DocValidity::Valid, "<C>3</C>", "none", &source, &hash)
+            .unwrap();
+        db.commit_writes().unwrap();
+        drop(db);
+
+        let options = SplitOptions {
+            duplicate_policy: DuplicatePolicy::Flag,
+            append: true,
+            ..Default::default()
+        };
+        let res = split_files(
+            &db_name,
+            "tabledef.yaml",
+            pattern.to_str().unwrap(),
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!((res.valid_docs, res.duplicate_docs), (5, 3));
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let duplicates: Vec<(isize, isize)> = db
+            .get_all_docs(DocValidity::Valid)
+            .unwrap()
+            .iter()
+            .map(|d| (d.doc_id, d.duplicate_of))
+            .collect();
+        assert_eq!(
+            duplicates,
+            vec![
+                (100, -1),
+                (101, -1),
+                (102, -1),
+                (103, 101),
+                (104, 101),
+                (105, 100)
+            ]
+        );
+
+        // skip: duplicates are not stored
+        let db_name = temp_path("utilrs_split_files_skip_duplicates.db");
+        purge(&db_name);
+        let options = SplitOptions {
+            duplicate_policy: DuplicatePolicy::Skip,
+            ..Default::default()
+        };
+        let res = split_files(
+            &db_name,
+            "tabledef.yaml",
+            pattern.to_str().unwrap(),
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!((res.valid_docs, res.duplicate_docs), (3, 2));
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let stored: Vec<(String, isize)> = db
+            .get_all_docs(DocValidity::Valid)
+            .unwrap()
+            .iter()
+            .map(|d| (d.doc_text.clone(), d.file_doc_ordinal))
+            .collect();
+        assert_eq!(
+            stored,
+            vec![
+                (doc_a.to_string(), 0),
+                ("<B>2</B>".to_string(), 1),
+                ("<C>3</C>".to_string(), 1)
+            ]
+        );
+
+        // an invalid document is neither a duplicate of a valid one nor the first copy of
+        // one, here the undecodable byte reads as the replacement character of the valid copy
+        let dir = std::env::temp_dir().join("utilrs_split_files_invalid_duplicates");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("mixed.xml");
+        std::fs::write(&file, b"<A>\xff</A><A>\xef\xbf\xbd</A><A>\xff</A>").unwrap();
+        let db_name = temp_path("utilrs_split_files_invalid_duplicates.db");
+        purge(&db_name);
+        let options = SplitOptions {
+            duplicate_policy: DuplicatePolicy::Flag,
+            ..Default::default()
+        };
+        let res = split_files(
+            &db_name,
+            "tabledef.yaml",
+            file.to_str().unwrap(),
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            (res.valid_docs, res.invalid_docs, res.duplicate_docs),
+            (1, 2, 0)
+        );
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let valid = db.get_all_docs(DocValidity::Valid).unwrap();
+        let invalid = db.get_all_docs(DocValidity::Invalid).unwrap();
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].doc_id, 1);
+        assert!(valid
+            .iter()
+            .chain(invalid.iter())
+            .all(|d| d.duplicate_of == -1));
+        assert_eq!(invalid[0].doc_hash, valid[0].doc_hash);
+    }
+
+    #[test]
+    fn query_processed_results() {
+        let db_name = temp_path("utilrs_query_processed_results.db");
+        purge(&db_name);
+        let file_to_process = temp_path("utilrs_query_processed_results.xml");
+        std::fs::write(
+            &file_to_process,
+            "<A><B>1</B></A><A><B>2</B><B>3</B></A><A>4</A>",
+        )
+        .unwrap();
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &SplitMode::XmlStructure { root_element: None },
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(process_file(&db_name, "tabledef.yaml").unwrap(), "success");
+
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let page: Vec<isize> = db
+            .get_docs_after(-1, 2, None)
+            .unwrap()
+            .iter()
+            .map(|d| d.doc_id)
+            .collect();
+        assert_eq!(page, vec![0, 1]);
+        let page = db.get_docs_after(1, 2, Some(DocValidity::Valid)).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].doc_text, "<A>4</A>");
+        assert!(db
+            .get_docs_after(2, 2, Some(DocValidity::Invalid))
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.find_doc(1).unwrap().unwrap().doc_id, 1);
+        assert!(db.find_doc(42).unwrap().is_none());
+
+        assert_eq!(
+            db.get_tag_values("A.B", None).unwrap(),
+            vec![
+                (0, "1".to_string()),
+                (1, "2".to_string()),
+                (1, "3".to_string())
+            ]
+        );
+        assert_eq!(
+            db.get_tag_values("A.B", Some(0)).unwrap(),
+            vec![(0, "1".to_string())]
+        );
+
+        let info = db.get_log_entries(LogLevel::Info, None).unwrap();
+        assert!(!info.is_empty());
+        assert!(info.iter().all(|l| l.log_level == LogLevel::Info as isize));
+        assert!(db
+            .get_log_entries(LogLevel::Error, None)
+            .unwrap()
+            .is_empty());
+        let doc_logs = db.get_log_entries(LogLevel::All, Some(2)).unwrap();
+        assert!(doc_logs.iter().all(|l| l.doc_id == Some(2)));
+    }
+
+    #[test]
+    fn process_with_local_names_and_store_namespace() {
+        let db_name = temp_path("utilrs_process_with_local_names.db");
+        purge(&db_name);
+        let file_to_process = temp_path("utilrs_process_with_local_names.xml");
+        std::fs::write(
+            &file_to_process,
+            "<ns2:Document xmlns:ns2=\"urn:x\"><ns2:A>1</ns2:A></ns2:Document>\n\
+            <Document xmlns=\"urn:x\"><A>2</A></Document>\n<Document><A>3</A></Document>",
+        )
+        .unwrap();
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &SplitMode::XmlStructure { root_element: None },
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        let options = ParseOptions {
+            namespace_mode: NamespaceMode::LocalName,
+            ..ParseOptions::default()
+        };
+        let res = process_file_with_workers(
+            &db_name,
+            "tabledef.yaml",
+            1,
+            &options,
+            None,
+            false,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(res, "success");
+
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let values: Vec<String> = db
+            .get_tag_values("Document.A", None)
+            .unwrap()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(values, vec!["1", "2", "3"]);
         drop(db);
+        let conn = rusqlite::Connection::open(&db_name).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT Namespace FROM ParsedXmlStore ORDER BY DocID")
+            .unwrap();
+        let namespaces: Vec<String> = stmt
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|ns| ns.unwrap())
+            .collect();
+        assert_eq!(namespaces, vec!["urn:x", "urn:x", ""]);
+    }
+
+    #[test]
+    fn store_iso_20022_message_definition() {
+        let db_name = temp_path("utilrs_store_iso_20022_message_definition.db");
+        purge(&db_name);
+        let file_to_process = temp_path("utilrs_store_iso_20022_message_definition.xml");
+        std::fs::write(
+            &file_to_process,
+            format!(
+                "{}\n<Document xmlns=\"urn:x\"><A>1</A></Document>",
+                PAIN_008
+            ),
+        )
+        .unwrap();
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &SplitMode::XmlStructure { root_element: None },
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(process_file(&db_name, "tabledef.yaml").unwrap(), "success");
+
+        let conn = rusqlite::Connection::open(&db_name).unwrap();
+        let mut stmt = conn
+            .prepare(
+                "SELECT MessageDefinition, BusinessArea, MessageFunctionality, MessageVariant, \
+                MessageVersion FROM ParsedXmlStore ORDER BY DocID",
+            )
+            .unwrap();
+        let messages: Vec<[String; 5]> = stmt
+            .query_map([], |r| {
+                Ok([r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?])
+            })
+            .unwrap()
+            .map(|message| message.unwrap())
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                ["pain.008.001.02", "pain", "008", "001", "02"].map(String::from),
+                Default::default(),
+            ]
+        );
+    }
+
+    #[test]
+    fn process_with_schema_validation() {
+        let db_name = temp_path("utilrs_process_with_schema_validation.db");
+        purge(&db_name);
+        let schema_dir = temp_path("utilrs_process_with_schema_validation");
+        let _ = std::fs::remove_dir_all(&schema_dir);
+        std::fs::create_dir(&schema_dir).unwrap();
+        std::fs::write(
+            format!("{}/x.xsd", schema_dir),
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:x" elementFormDefault="qualified">
+                <xs:element name="Document"><xs:complexType><xs:sequence>
+                    <xs:element name="A" type="xs:integer" maxOccurs="unbounded"/>
+                </xs:sequence></xs:complexType></xs:element>
+                <xs:simpleType name="B"><xs:restriction base="xs:string">
+                    <xs:pattern value="\p{IsUnknown}"/>
+                </xs:restriction></xs:simpleType>
+            </xs:schema>"#,
+        )
+        .unwrap();
+        let file_to_process = temp_path("utilrs_process_with_schema_validation.xml");
+        std::fs::write(
+            &file_to_process,
+            "<Document xmlns=\"urn:x\"><A>1</A><A>2</A></Document>\n\
+            <Document xmlns=\"urn:x\"><A>1</A><A>x</A><B/></Document>\n\
+            <Document><A>3</A></Document>",
+        )
+        .unwrap();
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &SplitMode::XmlStructure { root_element: None },
+            &SplitOptions::default(),
+            &RunControl::new(),
+        )
+        .unwrap();
+
+        let mapping = HashMap::from([("urn:y".to_string(), "y.xsd".to_string())]);
+        assert!(matches!(
+            SchemaStore::load(&schema_dir, &mapping),
+            Err(UtilrsError::Schema { .. })
+        ));
+        let schemas = SchemaStore::load(&schema_dir, &HashMap::new()).unwrap();
+        let res = process_file_with_workers(
+            &db_name,
+            "tabledef.yaml",
+            1,
+            &ParseOptions::default(),
+            Some(&schemas),
+            false,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(res, "error");
+
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let log_entries: Vec<(Option<isize>, String)> = db
+            .get_log_entries(persistence::LogLevel::All, None)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.log_entry != "Document successfully loaded")
+            .map(|entry| (entry.doc_id, entry.log_entry))
+            .collect();
+        let schema_file = format!("{}/x.xsd", schema_dir);
+        assert_eq!(
+            log_entries,
+            vec![
+                (
+                    None,
+                    "File successfully split into single documents without any errors.".to_string()
+                ),
+                (
+                    None,
+                    format!(
+                        "Schema '{}': the pattern '\\p{{IsUnknown}}' is not checked: the block \
+                        'Unknown' is not supported",
+                        schema_file
+                    )
+                ),
+                (None, "3 documents to process".to_string()),
+                (
+                    Some(0),
+                    format!("Document is valid against schema '{}'", schema_file)
+                ),
+                (
+                    Some(1),
+                    "Schema violation at /Document: expected element 'A', found element 'B'"
+                        .to_string()
+                ),
+                (
+                    Some(1),
+                    "Schema violation at /Document/A[2]: expected an integer, found 'x'"
+                        .to_string()
+                ),
+                (
+                    Some(2),
+                    "No schema found for document type 'A' and namespace '', the document is \
+                    not validated"
+                        .to_string()
+                ),
+            ]
+        );
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 2);
+        assert_eq!(
+            db.get_single_doc(1).unwrap().doc_invalid_reason,
+            format!(
+                "2 violation(s) of schema '{}', first: /Document: expected element 'A', found \
+                element 'B'",
+                schema_file
+            )
+        );
+        let parse_status: Vec<isize> = (0..3)
+            .map(|doc_id| db.get_single_doc(doc_id).unwrap().parse_status)
+            .collect();
+        assert_eq!(
+            parse_status,
+            vec![
+                persistence::ParseStatus::Parsed as isize,
+                persistence::ParseStatus::SchemaInvalid as isize,
+                persistence::ParseStatus::Parsed as isize,
+            ]
+        );
+        drop(db);
+
+        // the document violating its schema is checked again by a reparse, without the
+        // schemas it is valid
+        let process = |schemas: Option<&SchemaStore>| {
+            process_file_with_workers(
+                &db_name,
+                "tabledef.yaml",
+                1,
+                &ParseOptions::default(),
+                schemas,
+                true,
+                &RunControl::new(),
+            )
+            .unwrap()
+        };
+        assert_eq!(process(Some(&schemas)), "error");
+        assert_eq!(process(None), "success");
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 3);
+        assert_eq!(db.get_xml_count().unwrap(), 3);
+    }
+
+    #[test]
+    fn validate_pain_008_with_iso_schema() {
+        let schema_dir = concat!(env!("CARGO_MANIFEST_DIR"), "/testdata");
+        let schemas = SchemaStore::load(schema_dir, &HashMap::new()).unwrap();
+        assert_eq!(schemas.warnings().count(), 0);
+        let parsed_xml =
+            XmlDoc::new(1, PAIN_008, AttributeUsage::AddSeparateTag, None, None).unwrap();
+        let schema = schemas
+            .schema_for(
+                &parsed_xml.doc_type,
+                parsed_xml.default_namespace.as_deref(),
+            )
+            .unwrap();
+        assert!(schema.file.ends_with("pain.008.001.02.xsd"));
+        assert!(schema.validate(PAIN_008).is_empty());
+
+        let invalid = PAIN_008
+            .replacen("<BIC>HANDNL2A</BIC>", "<BIC>HAND-NL</BIC>", 1)
+            .replacen("<SeqTp>RCUR</SeqTp>", "<SeqTp>ONCE</SeqTp>", 1);
+        let violations: Vec<String> = schema
+            .validate(&invalid)
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                "/Document/CstmrDrctDbtInitn/PmtInf/PmtTpInf/SeqTp: expected one of (FRST, \
+                RCUR, FNAL, OOFF), found 'ONCE'",
+                "/Document/CstmrDrctDbtInitn/PmtInf/CdtrAgt/FinInstnId/BIC: expected a value \
+                matching '[A-Z]{6,6}[A-Z2-9][A-NP-Z0-9]([A-Z0-9]{3,3}){0,1}', found 'HAND-NL'",
+            ]
+        );
+    }
+
+    #[test]
+    fn append_split_and_process_new_documents_only() {
+        let db_name = temp_path("utilrs_append_split.db");
+        purge(&db_name);
+        let day1 = temp_path("utilrs_append_split_day1.xml");
+        let day2 = temp_path("utilrs_append_split_day2.xml");
+        std::fs::write(&day1, "<A>1</A><A>2</A>").unwrap();
+        std::fs::write(&day2, "<A>3</A><A>4</A><A>5</A>").unwrap();
+        let split_mode = SplitMode::XmlStructure { root_element: None };
+        let mut options = SplitOptions::default();
+
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &day1,
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        assert_eq!(process_file(&db_name, "tabledef.yaml").unwrap(), "success");
+
+        // the doc ids of the appended documents continue after the stored ones and only
+        // the new documents are parsed
+        options.append = true;
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &day2,
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        let unparsed: Vec<isize> = db
+            .get_unparsed_docs(DocValidity::Valid, false)
+            .unwrap()
+            .iter()
+            .map(|d| d.doc_id)
+            .collect();
+        assert_eq!(unparsed, vec![2, 3, 4]);
+        drop(db);
+        assert_eq!(process_file(&db_name, "tabledef.yaml").unwrap(), "success");
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(db.get_xml_count().unwrap(), 5);
+        assert!(db
+            .get_unparsed_docs(DocValidity::Valid, false)
+            .unwrap()
+            .is_empty());
+        let file_ids: Vec<isize> = db
+            .get_all_source_files()
+            .unwrap()
+            .iter()
+            .map(|f| f.file_id)
+            .collect();
+        assert_eq!(file_ids, vec![0, 1]);
+        drop(db);
+
+        // without append the database content is replaced
+        options.append = false;
+        split_file(
+            &db_name,
+            "tabledef.yaml",
+            &day2,
+            &split_mode,
+            &options,
+            &RunControl::new(),
+        )
+        .unwrap();
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 3);
+        assert_eq!(db.get_xml_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn process_skips_failed_documents_unless_reparsed() {
+        let db_name = temp_path("utilrs_process_skips_failed_documents.db");
+        purge(&db_name);
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        for (doc_id, text) in [(0, "<A>1</A>"), (1, "<A><!x></A>")] {
+            db.store_doc(
+                doc_id,
+                DocValidity::Valid,
+                text,
+                "none",
+                &persistence::DocSource::default(),
+                &persistence::DocHash::default(),
+            )
+            .unwrap();
+        }
+        db.commit_writes().unwrap();
+        drop(db);
+        let process = |reparse: bool| {
+            process_file_with_workers(
+                &db_name,
+                "tabledef.yaml",
+                1,
+                &ParseOptions::default(),
+                None,
+                reparse,
+                &RunControl::new(),
+            )
+            .unwrap()
+        };
+        let parse_errors = || {
+            let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+            let errors = db.get_log_entries(persistence::LogLevel::Error, Some(1));
+            (errors.unwrap().len(), db.get_xml_count().unwrap())
+        };
+
+        assert_eq!(process(false), "error");
+        assert_eq!(parse_errors(), (1, 1));
+        // the failed document is not parsed and logged again
+        assert_eq!(process(false), "success");
+        assert_eq!(parse_errors(), (1, 1));
+        // all valid documents are parsed again
+        assert_eq!(process(true), "error");
+        assert_eq!(parse_errors(), (2, 1));
+    }
+
+    // all rows of a table in a stable order, read after the DataBase is closed
+    fn dump_table(db_name: &str, table: &str, order_by: &str) -> Vec<String> {
+        let conn = rusqlite::Connection::open(db_name).unwrap();
+        let sql = format!("SELECT * FROM {} ORDER BY {}", table, order_by);
+        let mut stmt = conn.prepare(&sql).unwrap();
+        let columns = stmt.column_count();
+        let rows = stmt
+            .query_map([], |r| {
+                let values: Vec<rusqlite::types::Value> =
+                    (0..columns).map(|i| r.get(i)).collect::<Result<_, _>>()?;
+                Ok(format!("{:?}", values))
+            })
+            .unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    }
+
+    #[test]
+    fn parallel_processing_matches_sequential() {
+        let file_to_process = temp_path("utilrs_parallel_processing.xml");
+        let mut content = String::new();
+        // more documents than the pages waiting for the parse workers
+        for i in 0..2 * UNPARSED_DOC_PAGE_SIZE + 40 {
+            content.push_str(&PAIN_008.replace("Invoice 1", &format!("Invoice {}", i)));
+            content.push('\n');
+        }
+        std::fs::write(&file_to_process, content).unwrap();
+        let split_mode = SplitMode::Regex {
+            reg_ex: r"(<\?xml .*?>)".to_string(),
+            delimiter: DelimiterPlacement::AttachToNext,
+        };
+
+        let mut results = Vec::new();
+        for workers in [1, 4] {
+            let db_name = temp_path(&format!("utilrs_parallel_processing_{}.db", workers));
+            purge(&db_name);
+            let options = SplitOptions::default();
+            split_file(
+                &db_name,
+                "tabledef.yaml",
+                &file_to_process,
+                &split_mode,
+                &options,
+                &RunControl::new(),
+            )
+            .unwrap();
+            let res = process_file_with_workers(
+                &db_name,
+                "tabledef.yaml",
+                workers,
+                &ParseOptions::default(),
+                None,
+                false,
+                &RunControl::new(),
+            )
+            .unwrap();
+            results.push((
+                res,
+                dump_table(&db_name, "ParsedXmlStore", "DocID"),
+                dump_table(&db_name, "XmlTagsAndValues", "DocID, TagOrder"),
+                dump_table(&db_name, "ProcessLog", "DocID, LogLevel, LogEntry"),
+            ));
+        }
+        assert_eq!(results[0].0, "success");
+        assert!(results[0].2.len() > 40);
+        assert_eq!(results[0], results[1]);
+    }
+
+    // run control cancelling the run once `limit` documents are done
+    fn cancel_after(limit: usize, reported: Arc<std::sync::Mutex<Vec<Progress>>>) -> RunControl {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        RunControl::new()
+            .with_cancel_flag(cancelled.clone())
+            .with_progress(move |progress| {
+                reported.lock().unwrap().push(progress);
+                if progress.done >= limit {
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            })
+    }
+
+    #[test]
+    fn cancelled_runs_leave_a_consistent_database() {
+        let db_name = temp_path("utilrs_cancelled_runs.db");
+        purge(&db_name);
+        let file_to_process = temp_path("utilrs_cancelled_runs.xml");
+        let content: String = (0..12).map(|i| format!("<A>{}</A>\n", i)).collect();
+        std::fs::write(&file_to_process, content).unwrap();
+        let split_mode = SplitMode::XmlStructure { root_element: None };
+
+        let reported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let control = cancel_after(5, reported.clone());
+        let res = split_file(
+            &db_name,
+            "tabledef.yaml",
+            &file_to_process,
+            &split_mode,
+            &SplitOptions::default(),
+            &control,
+        )
+        .unwrap();
+        assert!(res.cancelled);
+        assert_eq!(res.valid_docs, 5);
+        assert_eq!(
+            reported.lock().unwrap().last(),
+            Some(&Progress {
+                done: 5,
+                total: Some(5),
+                errors: 0
+            })
+        );
+
+        // the parsing stops after two documents, the next run parses the others
+        let control = cancel_after(2, reported);
+        let res = process_file_with_workers(
+            &db_name,
+            "tabledef.yaml",
+            1,
+            &ParseOptions::default(),
+            None,
+            false,
+            &control,
+        )
+        .unwrap();
+        assert_eq!(res, "cancelled");
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 5);
+        assert_eq!(db.get_xml_count().unwrap(), 2);
+        drop(db);
+        assert_eq!(process_file(&db_name, "tabledef.yaml").unwrap(), "success");
+        let mut db = DataBase::new(&db_name, "tabledef.yaml").unwrap();
+        assert_eq!(db.get_xml_count().unwrap(), 5);
+    }
+
+    #[test]
+    fn split_errors_are_typed() {
+        let db_name = temp_path("utilrs_split_errors_are_typed.db");
+        let split = |file: &str, split_mode: filesplit::SplitMode| {
+            split_file(
+                &db_name,
+                "tabledef.yaml",
+                file,
+                &split_mode,
+                &SplitOptions::default(),
+                &RunControl::new(),
+            )
+        };
+        let regex = |reg_ex: &str| filesplit::SplitMode::Regex {
+            reg_ex: reg_ex.to_owned(),
+            delimiter: DelimiterPlacement::AttachToNext,
+        };
+
+        let missing = temp_path("utilrs_split_errors_are_typed.none");
+        match split(&missing, regex("<A>")) {
+            Err(UtilrsError::Io { path, source }) => {
+                assert_eq!(path, missing);
+                assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let file_to_process = temp_path("utilrs_split_errors_are_typed.xml");
+        std::fs::write(&file_to_process, "<A>1</A><A>2</B>").unwrap();
+        match split(&file_to_process, regex("(<A>")) {
+            Err(UtilrsError::Split { message, .. }) => {
+                assert!(message.starts_with("invalid split regex"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // a malformed document is stored as invalid document instead of ending the split
+        let split_mode = filesplit::SplitMode::XmlStructure { root_element: None };
+        match split(&file_to_process, split_mode) {
+            Ok(summary) => assert_eq!((summary.valid_docs, summary.invalid_docs), (1, 1)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        match DataBase::new(&db_name, "no_such_config.yaml") {
+            Err(UtilrsError::Config { .. }) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
     }
 }
@@ -1,99 +1,2184 @@
+use encoding_rs::{DecoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use flate2::read::MultiGzDecoder;
+use quick_xml::events::Event;
+use quick_xml::Reader;
 use regex::Regex;
+use regex_automata::hybrid::{self, LazyStateID};
+use regex_automata::{Anchored, Input};
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::fs::File;
-use std::io::Read;
-use std::time::Instant;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::OnceLock;
 
-pub fn split_file(filename: &str, reg_ex: &str) -> Vec<String> {
-    let mut content = String::new();
+use super::error::{InvalidInput, UtilrsError, UtilrsResult};
 
-    // Open the file in read-only mode.
-    match File::open(filename) {
-        // The file is open (no error).
-        Ok(mut file) => {
-            // Start the timer
-            //let start = Instant::now();
+// number of bytes read from the input per refill of the split buffer
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+// number of bytes at the start of the input used to detect its encoding
+const ENCODING_DETECTION_BYTES: u64 = 1024;
+// the encoding pseudo attribute of an xml declaration, compiled on first use
+static ENCODING_DECLARATION: OnceLock<Regex> = OnceLock::new();
 
-            // Read all the file content into a variable (ignoring the result of the operation).
-            file.read_to_string(&mut content).unwrap();
+/// How a file is cut into single documents
+#[derive(Debug, Clone)]
+pub enum SplitMode {
+    /// cut the file at every match of the separator regex, e.g. `(<\?xml .*?>)`, the
+    /// matched separator is placed according to the delimiter placement. The regex is matched
+    /// incrementally while the input is read, Unicode word boundaries (`\b`) are not supported
+    /// there, use the ASCII word boundary `(?-u:\b)` instead
+    Regex {
+        reg_ex: String,
+        delimiter: DelimiterPlacement,
+    },
+    /// cut the file based on the xml element structure: a document is emitted each time a
+    /// top-level element closes, or - if a root element name is given - each time the
+    /// outermost element with that name (matched by local name if given without prefix)
+    /// closes
+    XmlStructure { root_element: Option<String> },
+    /// extract the regions enclosed by a match of the start regex and the next match of the
+    /// end regex, e.g. `<Message>` and `</Message>`, text between the envelopes is dropped
+    Envelope {
+        start_reg_ex: String,
+        end_reg_ex: String,
+    },
+}
+
+/// Where the separator matched by the regex splitter ends up
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DelimiterPlacement {
+    /// the separator starts the document following it, e.g. an xml declaration
+    AttachToNext,
+    /// the separator ends the document preceding it, e.g. a trailer line
+    AttachToPrevious,
+    /// the separator is not part of any document
+    Drop,
+    /// the separator is emitted as a document of its own
+    Separate,
+}
 
-            // Stop the timer
-            //let duration = start.elapsed();
+/// A document cut out of the input together with its location in the input.
+///
+/// The offsets refer to the UTF-8 text of the input (after transcoding and removal of a
+/// BOM), [`SourceOffsets`] maps them to the byte offsets in the input file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitDocument {
+    pub text: String,
+    /// offset of the first byte of the document
+    pub start_offset: u64,
+    /// offset right after the last byte of the document
+    pub end_offset: u64,
+    /// line number the document starts at, starting at 1
+    pub start_line: u64,
+}
 
-            // println!(
-            //     "file read into memory in {:?} , now occupying {} bytes.",
-            //     duration,
-            //     content.len()
-            // );
+/// A streaming source of split documents
+pub trait DocumentSource: Iterator<Item = io::Result<SplitDocument>> {
+    /// Number of bytes read from the input so far
+    fn bytes_consumed(&self) -> u64;
+    /// Warnings about the input found since the last call, e.g. text which does not belong
+    /// to any document
+    fn take_warnings(&mut self) -> Vec<String> {
+        Vec::new()
+    }
+}
 
-            // The file is automatically closed when is goes out of scope.
+/// Create the streaming splitter for the given split mode over a reader
+pub fn open_splitter<'a, R: BufRead + 'a>(
+    reader: R,
+    split_mode: &SplitMode,
+) -> Result<Box<dyn DocumentSource + 'a>, regex::Error> {
+    match split_mode {
+        SplitMode::Regex { reg_ex, delimiter } => {
+            Ok(Box::new(DocSplitter::new(reader, reg_ex, *delimiter)?))
         }
-        // Error handling.
-        Err(error) => {
-            panic!("Error opening file {}: {}", filename, error);
+        SplitMode::XmlStructure { root_element } => Ok(Box::new(XmlDocSplitter::new(
+            reader,
+            root_element.as_deref(),
+        ))),
+        SplitMode::Envelope {
+            start_reg_ex,
+            end_reg_ex,
+        } => Ok(Box::new(EnvelopeSplitter::new(
+            reader,
+            start_reg_ex,
+            end_reg_ex,
+        )?)),
+    }
+}
+
+/// Container format of an input file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Plain,
+    Gzip,
+    Tar,
+    TarGz,
+    Zip,
+}
+
+// reader counting the bytes read from the input file, used for the progress display
+struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// An input file to split, either a plain file or a compressed / archived one.
+///
+/// The container format is detected from the file content, not from the file name.
+/// Archives are processed entry by entry, see [`InputFile::for_each_entry`].
+pub struct InputFile {
+    filename: String,
+    format: InputFormat,
+    size: u64,
+    bytes_read: Rc<Cell<u64>>,
+}
+
+impl InputFile {
+    pub fn open(filename: &str) -> UtilrsResult<Self> {
+        let io_error = |e| UtilrsError::input(filename, None, e);
+        let file = File::open(filename).map_err(io_error)?;
+        let size = file.metadata().map_err(io_error)?.len();
+        let format = detect_input_format(file).map_err(io_error)?;
+        Ok(InputFile {
+            filename: filename.to_owned(),
+            format,
+            size,
+            bytes_read: Rc::new(Cell::new(0)),
+        })
+    }
+
+    pub fn format(&self) -> InputFormat {
+        self.format
+    }
+
+    /// Size of the input file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Number of (compressed) bytes read from the input file so far
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.get()
+    }
+
+    /// Call `f` for every entry of the input file with the entry name and a reader over
+    /// the decompressed entry content. A plain file is a single entry with an empty name,
+    /// a gzip file is a single entry named after the compressed file.
+    pub fn for_each_entry<F>(&self, mut f: F) -> UtilrsResult<()>
+    where
+        F: FnMut(&str, &mut dyn BufRead) -> UtilrsResult<()>,
+    {
+        self.bytes_read.set(0);
+        let io_error = |e| UtilrsError::input(&self.filename, None, e);
+        let file = CountingReader {
+            inner: File::open(&self.filename).map_err(io_error)?,
+            count: Rc::clone(&self.bytes_read),
+        };
+        match self.format {
+            InputFormat::Plain => f("", &mut BufReader::new(file)),
+            InputFormat::Gzip => {
+                let decoder = MultiGzDecoder::new(file);
+                let entry_name = match decoder.header().and_then(|h| h.filename()) {
+                    Some(name) => String::from_utf8_lossy(name).to_string(),
+                    None => Path::new(&self.filename)
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                        .unwrap_or_default(),
+                };
+                f(&entry_name, &mut BufReader::new(decoder))
+            }
+            InputFormat::Tar => for_each_tar_entry(tar::Archive::new(file), f).map_err(io_error)?,
+            InputFormat::TarGz => {
+                for_each_tar_entry(tar::Archive::new(MultiGzDecoder::new(file)), f)
+                    .map_err(io_error)?
+            }
+            InputFormat::Zip => {
+                let zip_error = |e: zip::result::ZipError| io_error(e.into());
+                let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+                for i in 0..archive.len() {
+                    let entry = archive.by_index(i).map_err(zip_error)?;
+                    if entry.is_dir() {
+                        continue;
+                    }
+                    let entry_name = entry.name().to_owned();
+                    f(&entry_name, &mut BufReader::new(entry))?;
+                }
+                Ok(())
+            }
         }
     }
+}
 
-    // run the regex split
-    do_regex_split(&content, reg_ex)
+// call `f` for every file in a tar archive, the outer result holds the errors reading the
+// archive, the inner one the result of `f`
+fn for_each_tar_entry<R: Read, F>(
+    mut archive: tar::Archive<R>,
+    mut f: F,
+) -> io::Result<UtilrsResult<()>>
+where
+    F: FnMut(&str, &mut dyn BufRead) -> UtilrsResult<()>,
+{
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let entry_name = entry.path()?.to_string_lossy().to_string();
+        if let Err(e) = f(&entry_name, &mut BufReader::new(entry)) {
+            return Ok(Err(e));
+        }
+    }
+    Ok(Ok(()))
 }
 
-fn do_regex_split(text_to_split: &str, reg_ex: &str) -> Vec<String> {
-    // Start the timer
-    let start_2 = Instant::now();
-    // Split by regex
-    let seperator = Regex::new(reg_ex).expect("Invalid regex"); // r"(<\?xml .*?>)" <Document .*?>
-    let keep_tog: bool = true;
-    let splits = split_keep_regex(&seperator, &text_to_split, keep_tog);
-    // Stop the timer
-    let duration_2 = start_2.elapsed();
-    // Print the result
-    println!(
-        "vector created with regex in {:?}, it has {} items",
-        duration_2,
-        splits.len()
-    );
+// detect the container format from the magic bytes at the start of the file
+fn detect_input_format(mut file: File) -> io::Result<InputFormat> {
+    let mut head = Vec::new();
+    (&mut file).take(512).read_to_end(&mut head)?;
 
-    // println!("{:?}", splits)
-    // for (i, split) in splits[..3].iter().enumerate() {
-    //     //
-    //     println!("Item {} in vec is: {}\n", i, split);
-    // }
+    let is_tar = |head: &[u8]| head.len() >= 262 && &head[257..262] == b"ustar";
 
-    // return the vector
-    splits
+    if head.starts_with(&[0x1f, 0x8b]) {
+        // a gzip file - check if the decompressed content is a tar archive
+        file.seek(SeekFrom::Start(0))?;
+        let mut inner_head = Vec::new();
+        MultiGzDecoder::new(file)
+            .take(512)
+            .read_to_end(&mut inner_head)?;
+        if is_tar(&inner_head) {
+            Ok(InputFormat::TarGz)
+        } else {
+            Ok(InputFormat::Gzip)
+        }
+    } else if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        Ok(InputFormat::Zip)
+    } else if is_tar(&head) {
+        Ok(InputFormat::Tar)
+    } else {
+        Ok(InputFormat::Plain)
+    }
 }
 
-fn split_keep_regex(r: &Regex, text: &str, keep_together: bool) -> Vec<String> {
-    let mut result: Vec<String> = Vec::new();
-    let mut last = 0;
+// Text read from the input block by block, used by the regex based splitters. Only the
+// part of the input which has not been split off yet is kept in memory.
+struct TextBuffer<R: BufRead> {
+    reader: R,
+    text: String,
+    pending: Vec<u8>,
+    bytes_consumed: u64,
+    // input offset and line number of the first byte in the buffer
+    offset: u64,
+    line: u64,
+    eof: bool,
+}
 
-    // debug only
-    // let v: Vec<_> = text.match_indices(r).collect();
-    // for (idx, itm) in v {
-    //     println!("{} | {} | {}", idx, itm, itm.len());
-    // }
-    // end debug only
-    if keep_together {
-        for (index, _) in text.match_indices(r) {
-            if last != index {
-                result.push(text[last..index - 1].to_owned());
-                last = index;
+impl<R: BufRead> TextBuffer<R> {
+    fn new(reader: R) -> Self {
+        TextBuffer {
+            reader,
+            text: String::new(),
+            pending: Vec::new(),
+            bytes_consumed: 0,
+            offset: 0,
+            line: 1,
+            eof: false,
+        }
+    }
+
+    // read the next block from the input and append the decoded text to the buffer
+    fn fill(&mut self) -> io::Result<()> {
+        let data = self.reader.fill_buf()?;
+        if data.is_empty() {
+            self.eof = true;
+            if !self.pending.is_empty() {
+                return Err(InvalidInput::new_io_error(
+                    self.bytes_consumed - self.pending.len() as u64,
+                    "input ends with an incomplete UTF-8 sequence",
+                ));
             }
+            return Ok(());
         }
-        if last < text.len() {
-            result.push(text[last..].to_owned());
+        let block_len = data.len().min(READ_BLOCK_SIZE);
+        self.pending.extend_from_slice(&data[..block_len]);
+        self.reader.consume(block_len);
+        self.bytes_consumed += block_len as u64;
+
+        // move the valid UTF-8 part of the pending bytes into the buffer, an incomplete
+        // multi-byte sequence at the end of the block is kept for the next read
+        let valid_up_to = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            Err(e) => {
+                if e.error_len().is_some() {
+                    return Err(InvalidInput::new_io_error(
+                        self.bytes_consumed - (self.pending.len() - e.valid_up_to()) as u64,
+                        "input is not valid UTF-8",
+                    ));
+                }
+                e.valid_up_to()
+            }
+        };
+        let text = std::str::from_utf8(&self.pending[..valid_up_to]).unwrap();
+        self.text.push_str(text);
+        self.pending.drain(..valid_up_to);
+        Ok(())
+    }
+
+    // take the first `len` bytes of the buffer
+    fn take(&mut self, len: usize) -> SplitDocument {
+        let text: String = self.text.drain(..len).collect();
+        let doc = SplitDocument {
+            start_offset: self.offset,
+            end_offset: self.offset + len as u64,
+            start_line: self.line,
+            text,
+        };
+        self.offset = doc.end_offset;
+        self.line += doc.text.matches('\n').count() as u64;
+        doc
+    }
+}
+
+// Incremental leftmost-first regex search over text which is read block by block. The state
+// of the search automaton is kept while more input is appended to the text, so every byte
+// is scanned once, and a match is only reported when more input can not change it anymore:
+// the automaton has rejected every continuation of the text, or the input has ended.
+struct StreamSearch {
+    regex: hybrid::regex::Regex,
+    cache: hybrid::regex::Cache,
+    // maximum length of a match in bytes, if the regex has one
+    max_len: Option<usize>,
+    // start of the search, number of bytes fed to the automaton, the automaton state after
+    // them and the end of the preferred match found so far
+    from: usize,
+    pos: usize,
+    state: Option<LazyStateID>,
+    match_end: Option<usize>,
+}
+
+impl StreamSearch {
+    fn new(reg_ex: &str) -> Result<Self, regex::Error> {
+        // syntax errors are reported with the messages of the regex crate
+        Regex::new(reg_ex)?;
+        let regex = hybrid::regex::Regex::new(reg_ex)
+            .map_err(|e| regex::Error::Syntax(format!("{}: {}", reg_ex, e)))?;
+        let max_len = regex_automata::util::syntax::parse(reg_ex)
+            .map_err(|e| regex::Error::Syntax(e.to_string()))?
+            .properties()
+            .maximum_len();
+        let cache = regex.create_cache();
+        Ok(StreamSearch {
+            regex,
+            cache,
+            max_len,
+            from: 0,
+            pos: 0,
+            state: None,
+            match_end: None,
+        })
+    }
+
+    // start a new search at `from`
+    fn restart(&mut self, from: usize) {
+        self.from = from;
+        self.pos = from;
+        self.state = None;
+        self.match_end = None;
+    }
+
+    // the first `len` bytes of the text were removed, they must not contain the start of a
+    // match which may still be found
+    fn shift(&mut self, len: usize) {
+        self.from = self.from.saturating_sub(len);
+        self.pos = self.pos.saturating_sub(len);
+        self.match_end = self.match_end.map(|end| end.saturating_sub(len));
+    }
+
+    // continue the search over the text appended since the last call, the text before must
+    // not have changed. Returns the match once it is final, the same match is returned again
+    // until the search is restarted.
+    fn find(&mut self, text: &str, eof: bool) -> io::Result<Option<(usize, usize)>> {
+        loop {
+            let end = match self.scan(text, eof)? {
+                Some(end) => end,
+                None => return Ok(None),
+            };
+            if end == self.from {
+                return Ok(Some((end, end)));
+            }
+            // the start of the match is found by the reverse automaton
+            let input = Input::new(text)
+                .range(self.from..end)
+                .anchored(Anchored::Yes);
+            let (_, reverse_cache) = self.cache.as_parts_mut();
+            let start = self
+                .regex
+                .reverse()
+                .try_search_rev(reverse_cache, &input)
+                .map_err(search_error)?
+                .map_or(self.from, |m| m.offset());
+            if start == end && !text.is_char_boundary(end) {
+                // an empty match must not split a character, search again after it
+                let next = (end..=text.len())
+                    .find(|&pos| text.is_char_boundary(pos))
+                    .unwrap_or(text.len());
+                self.restart(next);
+                continue;
+            }
+            return Ok(Some((start, end)));
+        }
+    }
+
+    // feed the text not scanned yet to the forward automaton, returns the end of the match
+    // if no more input can change it
+    fn scan(&mut self, text: &str, eof: bool) -> io::Result<Option<usize>> {
+        let dfa = self.regex.forward();
+        let (cache, _) = self.cache.as_parts_mut();
+        let mut state = match self.state {
+            Some(state) => state,
+            None => dfa
+                .start_state_forward(cache, &Input::new(text).range(self.from..))
+                .map_err(search_error)?,
+        };
+        let bytes = text.as_bytes();
+        while self.pos < bytes.len() && !state.is_dead() {
+            state = dfa
+                .next_state(cache, state, bytes[self.pos])
+                .map_err(search_error)?;
+            // matches are reported one byte late by the automaton
+            if state.is_match() {
+                self.match_end = Some(self.pos);
+            } else if state.is_quit() {
+                return Err(search_error("the search automaton gave up"));
+            }
+            self.pos += 1;
         }
+        self.state = Some(state);
+        if state.is_dead() {
+            return Ok(self.match_end);
+        }
+        if eof {
+            if dfa
+                .next_eoi_state(cache, state)
+                .map_err(search_error)?
+                .is_match()
+            {
+                self.match_end = Some(bytes.len());
+            }
+            return Ok(self.match_end);
+        }
+        Ok(None)
+    }
+}
+
+fn search_error<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::other(e)
+}
+
+/// Streaming document splitter.
+///
+/// Reads the input block by block and yields one document at a time. The input is cut at
+/// every match of the separator regex, the separator itself is placed according to the
+/// [`DelimiterPlacement`]. Every document is an unmodified slice of the input, also one
+/// consisting of whitespace only, so the documents and the dropped separators together are
+/// the complete input. Only the document currently being assembled is held in memory, so
+/// the peak memory usage is bounded by the largest single document and not by the size of
+/// the input.
+pub struct DocSplitter<R: BufRead> {
+    input: TextBuffer<R>,
+    separator: StreamSearch,
+    delimiter: DelimiterPlacement,
+}
+
+impl<R: BufRead> DocSplitter<R> {
+    pub fn new(
+        reader: R,
+        reg_ex: &str,
+        delimiter: DelimiterPlacement,
+    ) -> Result<Self, regex::Error> {
+        Ok(DocSplitter {
+            input: TextBuffer::new(reader),
+            separator: StreamSearch::new(reg_ex)?,
+            delimiter,
+        })
+    }
+
+    // find the next final separator cutting off a non-empty part of the buffer
+    fn find_next_separator(&mut self) -> io::Result<Option<(usize, usize)>> {
+        while let Some((start, end)) = self.separator.find(&self.input.text, self.input.eof)? {
+            if start > 0 || (end > 0 && self.delimiter != DelimiterPlacement::AttachToNext) {
+                return Ok(Some((start, end)));
+            }
+            // the separator at the start of the buffer belongs to the current document
+            let text = &self.input.text;
+            let search_from = match text[end..].chars().next() {
+                Some(c) if end == start => end + c.len_utf8(),
+                Some(_) => end,
+                None => return Ok(None),
+            };
+            self.separator.restart(search_from);
+        }
+        Ok(None)
+    }
+}
+
+impl<R: BufRead> Iterator for DocSplitter<R> {
+    type Item = io::Result<SplitDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next_separator = match self.find_next_separator() {
+                Ok(next_separator) => next_separator,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some((start, end)) = next_separator {
+                let doc = match self.delimiter {
+                    DelimiterPlacement::AttachToNext => self.input.take(start),
+                    DelimiterPlacement::AttachToPrevious => self.input.take(end),
+                    DelimiterPlacement::Drop => {
+                        let doc = self.input.take(start);
+                        self.input.take(end - start);
+                        doc
+                    }
+                    // the separator is cut off first and emitted by the next call
+                    DelimiterPlacement::Separate if start > 0 => self.input.take(start),
+                    DelimiterPlacement::Separate => self.input.take(end),
+                };
+                self.separator.restart(0);
+                // nothing is left between two dropped separators
+                if doc.text.is_empty() {
+                    continue;
+                }
+                return Some(Ok(doc));
+            }
+
+            if self.input.eof {
+                self.separator.restart(0);
+                let len = self.input.text.len();
+                return match len {
+                    0 => None,
+                    _ => Some(Ok(self.input.take(len))),
+                };
+            }
+
+            // no final separator in the buffer yet: read more of the input
+            if let Err(e) = self.input.fill() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> DocumentSource for DocSplitter<R> {
+    fn bytes_consumed(&self) -> u64 {
+        self.input.bytes_consumed
+    }
+}
+
+// text between envelopes which was discarded, reported as one warning per region
+struct StrayText {
+    start_offset: u64,
+    end_offset: u64,
+    start_line: u64,
+    has_content: bool,
+}
+
+/// Streaming splitter extracting the regions enclosed by a start and an end pattern.
+///
+/// A document starts at a match of the start regex and ends right after the next match of
+/// the end regex, e.g. `<Message>` ... `</Message>` or `{1:` ... `-}`. Text outside of the
+/// envelopes is discarded, non-whitespace stray text and an envelope left open at the end
+/// of the input are reported as warnings (see [`DocumentSource::take_warnings`]).
+pub struct EnvelopeSplitter<R: BufRead> {
+    input: TextBuffer<R>,
+    start: StreamSearch,
+    end: StreamSearch,
+    // the buffer starts with an envelope whose end was not found yet
+    in_envelope: bool,
+    stray: Option<StrayText>,
+    warnings: Vec<String>,
+}
+
+impl<R: BufRead> EnvelopeSplitter<R> {
+    pub fn new(reader: R, start_reg_ex: &str, end_reg_ex: &str) -> Result<Self, regex::Error> {
+        Ok(EnvelopeSplitter {
+            input: TextBuffer::new(reader),
+            start: StreamSearch::new(start_reg_ex)?,
+            end: StreamSearch::new(end_reg_ex)?,
+            in_envelope: false,
+            stray: None,
+            warnings: Vec::new(),
+        })
+    }
+
+    // drop the first `len` bytes of the buffer, they are outside of any envelope
+    fn discard(&mut self, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let text = self.input.take(len);
+        let has_content = !text.text.trim().is_empty();
+        match &mut self.stray {
+            Some(stray) => {
+                stray.end_offset = text.end_offset;
+                stray.has_content |= has_content;
+            }
+            None => {
+                self.stray = Some(StrayText {
+                    start_offset: text.start_offset,
+                    end_offset: text.end_offset,
+                    start_line: text.start_line,
+                    has_content,
+                })
+            }
+        }
+    }
+
+    // report the discarded text since the last envelope, if it was not just whitespace
+    fn report_stray_text(&mut self) {
+        if let Some(stray) = self.stray.take() {
+            if stray.has_content {
+                self.warnings.push(format!(
+                    "stray text outside of envelopes at line {}, bytes {}-{}",
+                    stray.start_line, stray.start_offset, stray.end_offset
+                ));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for EnvelopeSplitter<R> {
+    type Item = io::Result<SplitDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if !self.in_envelope {
+                match self.start.find(&self.input.text, self.input.eof) {
+                    Err(e) => return Some(Err(e)),
+                    Ok(Some((start, end))) if end > start => {
+                        self.discard(start);
+                        self.report_stray_text();
+                        self.in_envelope = true;
+                        self.start.restart(0);
+                        self.end.restart(end - start);
+                        continue;
+                    }
+                    // an empty start marker does not open an envelope, search after it
+                    Ok(Some((_, end))) if end < self.input.text.len() => {
+                        let next = self.input.text[end..]
+                            .chars()
+                            .next()
+                            .map_or(0, char::len_utf8);
+                        self.start.restart(end + next);
+                        continue;
+                    }
+                    Ok(_) => {}
+                }
+                if self.input.eof {
+                    let len = self.input.text.len();
+                    self.discard(len);
+                    self.report_stray_text();
+                    self.start.restart(0);
+                    return None;
+                }
+                // keep only the text which may still be part of a start marker, the automaton
+                // sees the end of a match one byte late. Without an upper bound for the length
+                // of a marker the text is kept until the marker is found.
+                if let Some(max_len) = self.start.max_len {
+                    let mut keep_from = self.input.text.len().saturating_sub(max_len + 1);
+                    while !self.input.text.is_char_boundary(keep_from) {
+                        keep_from -= 1;
+                    }
+                    self.discard(keep_from);
+                    self.start.shift(keep_from);
+                }
+            } else {
+                match self.end.find(&self.input.text, self.input.eof) {
+                    Err(e) => return Some(Err(e)),
+                    Ok(Some((_, end))) => {
+                        self.in_envelope = false;
+                        return Some(Ok(self.input.take(end)));
+                    }
+                    Ok(None) => {}
+                }
+                if self.input.eof {
+                    self.in_envelope = false;
+                    self.warnings.push(format!(
+                        "envelope starting at line {}, byte {} is not closed before the end of the input",
+                        self.input.line, self.input.offset
+                    ));
+                    let len = self.input.text.len();
+                    self.input.take(len);
+                    return None;
+                }
+            }
+
+            // read more of the input, the search continues where it stopped
+            if let Err(e) = self.input.fill() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl<R: BufRead> DocumentSource for EnvelopeSplitter<R> {
+    fn bytes_consumed(&self) -> u64 {
+        self.input.bytes_consumed
+    }
+
+    fn take_warnings(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.warnings)
+    }
+}
+
+// BufRead adapter keeping a copy of every byte the xml reader consumes, so the raw text
+// of a document can be cut out of the input once its end has been found
+struct CapturingReader<R: BufRead> {
+    inner: R,
+    captured: Rc<RefCell<Vec<u8>>>,
+}
+
+impl<R: BufRead> Read for CapturingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.captured.borrow_mut().extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CapturingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // the data to consume is still in the inner buffer, fill_buf does not read again
+        if let Ok(data) = self.inner.fill_buf() {
+            self.captured.borrow_mut().extend_from_slice(&data[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Streaming document splitter based on the xml element structure.
+///
+/// Tracks the element depth while reading the input and emits a document each time a
+/// top-level element is closed. The xml declaration, comments and processing instructions
+/// before a top-level element are part of the document. If a root element name is given,
+/// only the outermost elements with that name are emitted (at any depth) and everything
+/// else, e.g. a wrapping batch element, is dropped. A root element name without prefix
+/// matches the local name of the elements, one with prefix only the elements with that
+/// prefix.
+///
+/// A malformed document does not end the split: the input is skipped up to the next xml
+/// declaration or start tag of the root element and the malformed part is emitted as a
+/// document of its own, which then fails the well-formedness check.
+pub struct XmlDocSplitter<R: BufRead> {
+    // the reader only returns `Eof` after an error, it is replaced by a new one reading
+    // on from the same position to get past the error
+    reader: Option<Reader<CapturingReader<R>>>,
+    // input position the current reader started at
+    reader_start: usize,
+    captured: Rc<RefCell<Vec<u8>>>,
+    // absolute input position and line number of the first byte in `captured`
+    captured_from: usize,
+    captured_line: u64,
+    root_element: Option<Vec<u8>>,
+    // names of the open elements, the end names are checked here instead of by the
+    // reader so a mismatch only makes the current document malformed
+    open_elements: Vec<Vec<u8>>,
+    // depth at which the currently open root element was found
+    root_depth: Option<usize>,
+    // name of the root element of the last document, the next document starts with it
+    doc_root: Option<Vec<u8>>,
+    // the current document is malformed, the input is skipped up to the next document
+    malformed: bool,
+    // a document found right after a malformed one, emitted by the next call
+    queued: Option<io::Result<SplitDocument>>,
+    doc_start: usize,
+    done: bool,
+}
+
+impl<R: BufRead> XmlDocSplitter<R> {
+    pub fn new(reader: R, root_element: Option<&str>) -> Self {
+        let captured = Rc::new(RefCell::new(Vec::new()));
+        let xml_reader = Self::xml_reader(CapturingReader {
+            inner: reader,
+            captured: Rc::clone(&captured),
+        });
+        XmlDocSplitter {
+            reader: Some(xml_reader),
+            reader_start: 0,
+            captured,
+            captured_from: 0,
+            captured_line: 1,
+            root_element: root_element.map(|name| name.as_bytes().to_vec()),
+            open_elements: Vec::new(),
+            root_depth: None,
+            doc_root: None,
+            malformed: false,
+            queued: None,
+            doc_start: 0,
+            done: false,
+        }
+    }
+
+    fn xml_reader(input: CapturingReader<R>) -> Reader<CapturingReader<R>> {
+        let mut reader = Reader::from_reader(input);
+        reader.check_comments(false);
+        reader.check_end_names(false);
+        reader
+    }
+
+    // absolute position of the reader in the input
+    fn input_position(&self) -> usize {
+        let reader = self
+            .reader
+            .as_ref()
+            .expect("xml reader is only replaced on errors");
+        self.reader_start + reader.buffer_position()
+    }
+
+    // check if an element name (given by its full name) matches the root element name
+    fn is_root_name(&self, name: &[u8]) -> bool {
+        match &self.root_element {
+            Some(root_name) if root_name.contains(&b':') => name == root_name.as_slice(),
+            Some(root_name) => {
+                let local_name = match name.iter().rposition(|&b| b == b':') {
+                    Some(pos) => &name[pos + 1..],
+                    None => name,
+                };
+                local_name == root_name.as_slice()
+            }
+            None => false,
+        }
+    }
+
+    // check if an element (given by its full name) is a document root at the current depth
+    fn is_root(&self, name: &[u8]) -> bool {
+        match &self.root_element {
+            Some(_) => self.root_depth.is_none() && self.is_root_name(name),
+            None => self.open_elements.is_empty(),
+        }
+    }
+
+    // check if a start tag found after a malformed document starts the next document
+    fn starts_next_document(&self, name: &[u8]) -> bool {
+        match (&self.root_element, &self.doc_root) {
+            (Some(_), _) => self.is_root_name(name),
+            (None, Some(doc_root)) => name == doc_root.as_slice(),
+            (None, None) => true,
+        }
+    }
+
+    // check if the reader is inside a document, and not between documents
+    fn in_document(&self) -> bool {
+        match &self.root_element {
+            Some(_) => self.root_depth.is_some(),
+            None => !self.open_elements.is_empty(),
+        }
+    }
+
+    // update the element structure with an event, returns the document it completes
+    fn handle_event(
+        &mut self,
+        event: &Event,
+        event_start: usize,
+        event_end: usize,
+    ) -> Option<io::Result<SplitDocument>> {
+        match event {
+            Event::Start(ref e) => {
+                if self.is_root(e.name()) {
+                    if self.root_element.is_some() && !self.open_elements.is_empty() {
+                        // a nested root starts at its own start tag
+                        self.discard_until(event_start);
+                    }
+                    self.root_depth = Some(self.open_elements.len());
+                    self.doc_root = Some(e.name().to_vec());
+                }
+                self.open_elements.push(e.name().to_vec());
+            }
+            Event::Empty(ref e) => {
+                if self.is_root(e.name()) {
+                    if self.root_element.is_some() && !self.open_elements.is_empty() {
+                        self.discard_until(event_start);
+                    }
+                    self.doc_root = Some(e.name().to_vec());
+                    return Some(self.take_document(event_end));
+                }
+            }
+            Event::End(ref e) => {
+                let matches_open =
+                    self.open_elements.last().map(|name| name.as_slice()) == Some(e.name());
+                if !matches_open && self.in_document() {
+                    self.malformed = true;
+                    return None;
+                }
+                // the end tags of wrapper elements are not checked
+                self.open_elements.pop();
+                if self.root_depth == Some(self.open_elements.len()) {
+                    self.root_depth = None;
+                    return Some(self.take_document(event_end));
+                }
+                if self.root_element.is_some() && self.root_depth.is_none() {
+                    // closing a wrapper element, nothing before is part of a document
+                    self.discard_until(event_end);
+                } else if self.root_element.is_none() && !matches_open {
+                    // an end tag without start tag between the documents
+                    self.malformed = true;
+                }
+            }
+            // whitespace between documents is not part of the next document
+            Event::Text(ref e)
+                if self.root_depth.is_none()
+                    && self.doc_start == event_start
+                    && e.iter().all(|b| b.is_ascii_whitespace()) =>
+            {
+                self.discard_until(event_end);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // the rest of the input at its end, a malformed or unclosed document is emitted as it
+    // is and anything else is dropped
+    fn take_rest(&mut self, end: usize) -> Option<io::Result<SplitDocument>> {
+        self.done = true;
+        match self.malformed || self.in_document() {
+            true => self.take_incomplete_document(end),
+            false => None,
+        }
+    }
+
+    // cut out a malformed or unclosed document ending before `end` without the whitespace
+    // up to `end`, nothing is returned for whitespace only
+    fn take_incomplete_document(&mut self, end: usize) -> Option<io::Result<SplitDocument>> {
+        let trailing_whitespace = self.captured.borrow()
+            [self.doc_start - self.captured_from..end - self.captured_from]
+            .iter()
+            .rev()
+            .take_while(|b| b.is_ascii_whitespace())
+            .count();
+        let doc = match end - trailing_whitespace > self.doc_start {
+            true => Some(self.take_document(end - trailing_whitespace)),
+            false => None,
+        };
+        self.discard_until(end);
+        doc
+    }
+
+    // cut the document between the stored start and the given end position out of the
+    // captured input and discard everything before the end position
+    fn take_document(&mut self, end: usize) -> io::Result<SplitDocument> {
+        let start = self.doc_start;
+        let start_line = self.captured_line;
+        let doc_bytes = self.discard_until(end);
+        let text = String::from_utf8(doc_bytes).map_err(|e| {
+            InvalidInput::new_io_error(
+                (start + e.utf8_error().valid_up_to()) as u64,
+                format!("document ending at byte {} is not valid UTF-8", end),
+            )
+        })?;
+        Ok(SplitDocument {
+            text,
+            start_offset: start as u64,
+            end_offset: end as u64,
+            start_line,
+        })
+    }
+
+    // drop captured input that can not be part of any document anymore, the dropped
+    // bytes are returned
+    fn discard_until(&mut self, pos: usize) -> Vec<u8> {
+        let mut captured = self.captured.borrow_mut();
+        let dropped: Vec<u8> = captured.drain(..pos - self.captured_from).collect();
+        self.captured_line += dropped.iter().filter(|&&b| b == b'\n').count() as u64;
+        self.captured_from = pos;
+        self.doc_start = pos;
+        dropped
+    }
+}
+
+impl<R: BufRead> Iterator for XmlDocSplitter<R> {
+    type Item = io::Result<SplitDocument>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(doc) = self.queued.take() {
+            return Some(doc);
+        }
+        if self.done {
+            return None;
+        }
+        let mut buf = Vec::new();
+        loop {
+            // the position before the event is the start of the event's markup
+            let event_start = self.input_position();
+            let reader = self
+                .reader
+                .as_mut()
+                .expect("xml reader is only replaced on errors");
+            let event = match reader.read_event(&mut buf) {
+                Ok(event) => event,
+                Err(_) => {
+                    // all input read so far is captured, the new reader starts behind it
+                    let position = self.captured_from + self.captured.borrow().len();
+                    if position == event_start {
+                        return self.take_rest(position);
+                    }
+                    if let Some(reader) = self.reader.take() {
+                        self.reader = Some(Self::xml_reader(reader.into_underlying_reader()));
+                        self.reader_start = position;
+                    }
+                    // the reader continues after the faulty markup, only markup between
+                    // the documents of a root element filter is dropped
+                    if self.root_element.is_some() && self.root_depth.is_none() {
+                        self.discard_until(position);
+                    } else {
+                        self.malformed = true;
+                    }
+                    buf.clear();
+                    continue;
+                }
+            };
+            let event_end = self.input_position();
+            if let Event::Eof = event {
+                return self.take_rest(event_end);
+            }
+            if self.malformed {
+                let next_document = match event {
+                    Event::Decl(_) => true,
+                    Event::Start(ref e) | Event::Empty(ref e) => {
+                        self.starts_next_document(e.name())
+                    }
+                    _ => false,
+                };
+                if next_document {
+                    let malformed_doc = self.take_incomplete_document(event_start);
+                    self.malformed = false;
+                    self.open_elements.clear();
+                    self.root_depth = None;
+                    self.queued = self.handle_event(&event, event_start, event_end);
+                    if malformed_doc.is_some() {
+                        return malformed_doc;
+                    }
+                    if let Some(doc) = self.queued.take() {
+                        return Some(doc);
+                    }
+                }
+            } else if let Some(doc) = self.handle_event(&event, event_start, event_end) {
+                return Some(doc);
+            }
+            buf.clear();
+        }
+    }
+}
+
+impl<R: BufRead> DocumentSource for XmlDocSplitter<R> {
+    fn bytes_consumed(&self) -> u64 {
+        self.input_position() as u64
+    }
+}
+
+/// Detect the encoding of an xml input from its first bytes.
+///
+/// A byte order mark takes precedence, then the layout of `<?xml` for UTF-16 input without
+/// BOM and finally the `encoding` pseudo attribute of the xml declaration. Defaults to
+/// UTF-8 if none of these is present.
+pub fn detect_encoding(head: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(head) {
+        return encoding;
+    }
+    if head.starts_with(&[0x3C, 0x00, 0x3F, 0x00]) {
+        return UTF_16LE;
+    }
+    if head.starts_with(&[0x00, 0x3C, 0x00, 0x3F]) {
+        return UTF_16BE;
+    }
+
+    // ascii compatible encoding: check the xml declaration
+    if head.starts_with(b"<?xml") {
+        let decl_end = head
+            .windows(2)
+            .position(|w| w == b"?>")
+            .unwrap_or(head.len());
+        let decl = String::from_utf8_lossy(&head[..decl_end]);
+        let label = ENCODING_DECLARATION
+            .get_or_init(|| {
+                Regex::new(r#"encoding\s*=\s*["']([A-Za-z0-9._:-]+)["']"#)
+                    .expect("the encoding declaration regex is valid")
+            })
+            .captures(&decl)
+            .map(|c| c[1].to_owned());
+        if let Some(encoding) = label.and_then(|l| Encoding::for_label(l.as_bytes())) {
+            // a declared UTF-16 can not be right, the declaration was readable as ascii
+            if encoding != UTF_16LE && encoding != UTF_16BE {
+                return encoding;
+            }
+        }
+    }
+    UTF_8
+}
+
+/// Positions of the byte sequences which could not be decoded by a [`DecodingReader`], as
+/// offsets in its UTF-8 output.
+///
+/// The reader decodes ahead of the documents split from its output, so the errors are
+/// counted per document by position and not by the number of errors found so far.
+#[derive(Debug, Default)]
+pub struct DecodeErrors {
+    // positions not yet passed by `count_between`
+    positions: VecDeque<u64>,
+    total: usize,
+}
+
+impl DecodeErrors {
+    /// Number of errors in the whole input decoded so far
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Number of errors between the `start` (inclusive) and `end` (exclusive) offsets of
+    /// the output. The errors before `end` are dropped, the ranges have to be passed in
+    /// input order.
+    pub fn count_between(&mut self, start: u64, end: u64) -> usize {
+        let mut count = 0;
+        while let Some(&position) = self.positions.front() {
+            if position >= end {
+                break;
+            }
+            if position >= start {
+                count += 1;
+            }
+            self.positions.pop_front();
+        }
+        count
+    }
+
+    fn add(&mut self, position: u64) {
+        self.positions.push_back(position);
+        self.total += 1;
+    }
+}
+
+/// Offsets in the input of a [`DecodingReader`] for offsets in its UTF-8 output.
+///
+/// The reader records runs of characters decoded with the same ratio of input to output
+/// bytes, e.g. the ASCII characters of UTF-16 input with two input bytes per output byte.
+/// The runs are dropped as the offsets are looked up, so only the runs of the output not
+/// yet split into documents are held in memory.
+#[derive(Debug, Default)]
+pub struct SourceOffsets {
+    // runs not yet passed by `source_offset`, in output order
+    runs: VecDeque<OffsetRun>,
+    // output and input offset after the last run
+    output_end: u64,
+    input_end: u64,
+    input_width: InputWidth,
+    // input bytes read without a character output for them yet
+    pending_input: u64,
+}
+
+// characters from `output_start` on taking `input_unit` input bytes per `output_unit`
+// output bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OffsetRun {
+    output_start: u64,
+    input_start: u64,
+    output_unit: u64,
+    input_unit: u64,
+}
+
+impl SourceOffsets {
+    /// The input offset of the character starting at the output offset `offset`, or of the
+    /// end of the input for the end of the output. The runs before `offset` are dropped,
+    /// the offsets have to be passed in output order.
+    pub fn source_offset(&mut self, offset: u64) -> u64 {
+        while self.runs.len() > 1 && self.runs[1].output_start <= offset {
+            self.runs.pop_front();
+        }
+        match self.runs.front() {
+            Some(run) if run.output_start <= offset => {
+                run.input_start + (offset - run.output_start) / run.output_unit * run.input_unit
+            }
+            _ => self.input_end,
+        }
+    }
+
+    // skip input which is not decoded, e.g. a byte order mark
+    fn skip_input(&mut self, len: u64) {
+        self.input_end += len;
+        self.runs.clear();
+    }
+
+    // record the input bytes of the characters decoded into `decoded`, `read` bytes of input
+    // were read for them
+    fn add_decoded(&mut self, decoded: &[u8], read: usize) {
+        if self.input_width == InputWidth::Utf8 {
+            self.add(decoded.len() as u64, decoded.len() as u64);
+            return;
+        }
+        self.pending_input += read as u64;
+        for c in String::from_utf8_lossy(decoded).chars() {
+            let input_len = match (self.input_width, c.len_utf8()) {
+                (InputWidth::Utf16, 4) => 4,
+                (InputWidth::Utf16, _) => 2,
+                (InputWidth::SingleByte, _) => 1,
+                _ => self.pending_input,
+            };
+            self.pending_input = self.pending_input.saturating_sub(input_len);
+            self.add(c.len_utf8() as u64, input_len);
+        }
+    }
+
+    // record the input bytes of a malformed sequence of `len` bytes replaced with U+FFFD,
+    // `extra` bytes following it were read as well
+    fn add_malformed(&mut self, len: u8, extra: u8) {
+        let input_len = match self.input_width {
+            InputWidth::Unknown => self.pending_input.saturating_sub(extra as u64),
+            _ => len as u64,
+        };
+        self.pending_input = self.pending_input.saturating_sub(input_len);
+        self.add('\u{FFFD}'.len_utf8() as u64, input_len);
+    }
+
+    // add `output_len` output bytes decoded from `input_len` input bytes
+    fn add(&mut self, output_len: u64, input_len: u64) {
+        if output_len == 0 {
+            self.input_end += input_len;
+            return;
+        }
+        let mut a = output_len;
+        let mut b = input_len;
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        let (output_unit, input_unit) = (output_len / a, input_len / a);
+        let extends_last = self.runs.back().is_some_and(|run| {
+            run.output_unit == output_unit
+                && run.input_unit == input_unit
+                && run.input_start + (self.output_end - run.output_start) / output_unit * input_unit
+                    == self.input_end
+        });
+        if !extends_last {
+            self.runs.push_back(OffsetRun {
+                output_start: self.output_end,
+                input_start: self.input_end,
+                output_unit,
+                input_unit,
+            });
+        }
+        self.output_end += output_len;
+        self.input_end += input_len;
+    }
+}
+
+// how the input bytes of the decoded characters are counted
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum InputWidth {
+    // as many input as output bytes
+    #[default]
+    Utf8,
+    Utf16,
+    SingleByte,
+    // other multi byte encodings: the input is decoded byte by byte, the bytes read until
+    // a character is output are the ones of this character
+    Unknown,
+}
+
+impl InputWidth {
+    fn of(encoding: &'static Encoding) -> Self {
+        if encoding == UTF_8 {
+            InputWidth::Utf8
+        } else if encoding == UTF_16LE || encoding == UTF_16BE {
+            InputWidth::Utf16
+        } else if encoding.is_single_byte() {
+            InputWidth::SingleByte
+        } else {
+            InputWidth::Unknown
+        }
+    }
+}
+
+/// Reader transcoding its input to UTF-8.
+///
+/// Byte sequences that are not valid in the input encoding are replaced with U+FFFD and
+/// recorded, see [`DecodingReader::decode_errors`].
+pub struct DecodingReader<R: BufRead> {
+    inner: io::Chain<io::Cursor<Vec<u8>>, R>,
+    encoding: &'static Encoding,
+    decoder: encoding_rs::Decoder,
+    out: Vec<u8>,
+    out_pos: usize,
+    // output offset of the start of `out`
+    out_offset: u64,
+    finished: bool,
+    decode_errors: Rc<RefCell<DecodeErrors>>,
+    source_offsets: Rc<RefCell<SourceOffsets>>,
+}
+
+impl<R: BufRead> DecodingReader<R> {
+    /// Create the reader, the encoding is detected from the input (see [`detect_encoding`])
+    /// unless an encoding label (e.g. "ISO-8859-1", "UTF-16") is given
+    pub fn new(mut inner: R, encoding: Option<&str>) -> io::Result<Self> {
+        // read the start of the input for the detection, it is put in front of the
+        // remaining input again
+        let mut head = Vec::new();
+        (&mut inner)
+            .take(ENCODING_DETECTION_BYTES)
+            .read_to_end(&mut head)?;
+        let encoding = match encoding {
+            Some(label) => match Encoding::for_label(label.as_bytes()) {
+                Some(encoding) => encoding,
+                None => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unknown encoding '{}'", label),
+                    ))
+                }
+            },
+            None => detect_encoding(&head),
+        };
+        // a BOM is removed if it matches the encoding
+        let mut source_offsets = SourceOffsets {
+            input_width: InputWidth::of(encoding),
+            ..Default::default()
+        };
+        if let Some((bom_encoding, bom_len)) = Encoding::for_bom(&head) {
+            if bom_encoding == encoding {
+                source_offsets.skip_input(bom_len as u64);
+            }
+        }
+        Ok(DecodingReader {
+            inner: io::Cursor::new(head).chain(inner),
+            encoding,
+            decoder: encoding.new_decoder_with_bom_removal(),
+            out: Vec::new(),
+            out_pos: 0,
+            out_offset: 0,
+            finished: false,
+            decode_errors: Rc::new(RefCell::new(DecodeErrors::default())),
+            source_offsets: Rc::new(RefCell::new(source_offsets)),
+        })
+    }
+
+    /// The encoding of the input
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// Shared record of the byte sequences which could not be decoded so far
+    pub fn decode_errors(&self) -> Rc<RefCell<DecodeErrors>> {
+        Rc::clone(&self.decode_errors)
+    }
+
+    /// Shared record of the input offsets of the output decoded so far
+    pub fn source_offsets(&self) -> Rc<RefCell<SourceOffsets>> {
+        Rc::clone(&self.source_offsets)
+    }
+
+    // decode the next block of the input into the output buffer
+    fn refill(&mut self) -> io::Result<()> {
+        self.out_offset += self.out.len() as u64;
+        self.out.clear();
+        self.out_pos = 0;
+        let mut chunk = [0u8; 8192];
+        while self.out.is_empty() && !self.finished {
+            let src = self.inner.fill_buf()?;
+            let last = src.is_empty();
+            let mut source_offsets = self.source_offsets.borrow_mut();
+            // the input of other multi byte encodings is decoded byte by byte to attribute
+            // the input bytes to the characters, the empty input at the end flushes the decoder
+            let step = match source_offsets.input_width {
+                InputWidth::Unknown => 1,
+                _ => src.len().max(1),
+            };
+            for piece in src.chunks(step).chain(last.then_some(src)) {
+                let mut total_read = 0;
+                loop {
+                    let (result, read, written) = self.decoder.decode_to_utf8_without_replacement(
+                        &piece[total_read..],
+                        &mut chunk,
+                        last,
+                    );
+                    total_read += read;
+                    self.out.extend_from_slice(&chunk[..written]);
+                    source_offsets.add_decoded(&chunk[..written], read);
+                    match result {
+                        DecoderResult::InputEmpty => break,
+                        DecoderResult::OutputFull => continue,
+                        DecoderResult::Malformed(len, extra) => {
+                            let position = self.out_offset + self.out.len() as u64;
+                            self.decode_errors.borrow_mut().add(position);
+                            self.out.extend_from_slice("\u{FFFD}".as_bytes());
+                            source_offsets.add_malformed(len, extra);
+                        }
+                    }
+                }
+            }
+            let consumed = src.len();
+            self.inner.consume(consumed);
+            self.finished = last;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for DecodingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for DecodingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.out_pos >= self.out.len() {
+            self.refill()?;
+        }
+        Ok(&self.out[self.out_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.out_pos = (self.out_pos + amt).min(self.out.len());
+    }
+}
+
+/// Resolve a directory, glob pattern or single file name into the sorted list of files
+/// to process. A directory is not searched recursively.
+pub fn resolve_input_files(path_or_pattern: &str) -> UtilrsResult<Vec<String>> {
+    let path = Path::new(path_or_pattern);
+    let io_error = |e| UtilrsError::input(path_or_pattern, None, e);
+    let mut files: Vec<String> = Vec::new();
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path).map_err(io_error)? {
+            let entry_path = entry.map_err(io_error)?.path();
+            if entry_path.is_file() {
+                files.push(entry_path.to_string_lossy().to_string());
+            }
+        }
+    } else if path.is_file() {
+        files.push(path_or_pattern.to_owned());
     } else {
-        for (index, matched) in text.match_indices(r) {
-            if last != index {
-                result.push(text[last..index].to_owned());
+        let entries = glob::glob(path_or_pattern).map_err(|e| {
+            UtilrsError::split(path_or_pattern, None, format!("invalid pattern: {}", e))
+        })?;
+        for entry in entries {
+            let entry_path = entry.map_err(|e| io_error(e.into()))?;
+            if entry_path.is_file() {
+                files.push(entry_path.to_string_lossy().to_string());
+            }
+        }
+    }
+    if files.is_empty() {
+        let not_found = io::Error::new(io::ErrorKind::NotFound, "no input files found");
+        return Err(io_error(not_found));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Calculate the SHA-256 checksum of a file as lower case hex string
+pub fn file_checksum(filename: &str) -> io::Result<String> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 checksum (lower case hex) of the canonicalised text of a document.
+///
+/// The canonical form ignores leading and trailing whitespace, whitespace between a tag end
+/// and the next tag start and the difference between CRLF and LF line endings, so copies of
+/// a document which were only re-indented or converted between platforms get the same hash.
+pub fn document_hash(text: &str) -> String {
+    let mut hasher = Sha256::new();
+    let mut update = |part: &str| match part.contains('\r') {
+        true => hasher.update(part.replace("\r\n", "\n").as_bytes()),
+        false => hasher.update(part.as_bytes()),
+    };
+    let mut rest = text.trim();
+    while let Some(pos) = rest.find('>') {
+        update(&rest[..=pos]);
+        rest = &rest[pos + 1..];
+        let next = rest.trim_start();
+        if next.starts_with('<') {
+            rest = next;
+        }
+    }
+    update(rest);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Split a file into single documents and collect them all into a vector.
+///
+/// This loads all documents into memory at once, use [`InputFile`] together with
+/// [`open_splitter`] to process large files document by document.
+pub fn split_file(filename: &str, split_mode: &SplitMode) -> UtilrsResult<Vec<String>> {
+    let mut documents: Vec<String> = Vec::new();
+    let input = InputFile::open(filename)?;
+    input.for_each_entry(|_, reader| {
+        let reader =
+            DecodingReader::new(reader, None).map_err(|e| UtilrsError::input(filename, None, e))?;
+        let splitter = open_splitter(reader, split_mode)
+            .map_err(|e| UtilrsError::split(filename, None, e.to_string()))?;
+        for doc in splitter {
+            documents.push(doc.map_err(|e| UtilrsError::input(filename, None, e))?.text);
+        }
+        Ok(())
+    })?;
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn split_streaming(text: &str, reg_ex: &str, capacity: usize) -> Vec<String> {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+        DocSplitter::new(reader, reg_ex, DelimiterPlacement::AttachToNext)
+            .unwrap()
+            .map(|doc| doc.unwrap().text)
+            .collect()
+    }
+
+    #[test]
+    fn streaming_split_keeps_separator_with_document() {
+        let text = "<?xml v?><A>1</A>\n<?xml v?><B>2</B>\n<?xml v?><C>3</C>";
+        // a tiny read buffer forces separators and documents across block boundaries
+        for capacity in [1, 3, 7, 64, 4096] {
+            let docs = split_streaming(text, r"(<\?xml .*?>)", capacity);
+            assert_eq!(
+                docs,
+                vec![
+                    "<?xml v?><A>1</A>\n",
+                    "<?xml v?><B>2</B>\n",
+                    "<?xml v?><C>3</C>"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn streaming_split_finds_separator_across_read_blocks() {
+        // an xml declaration longer than a few KiB straddling the end of the first block
+        let declaration = format!("<?xml version=\"1.0\" comment=\"{}\"?>", "c".repeat(5000));
+        let first = format!("<?xml v?><A>{}</A>", "a".repeat(READ_BLOCK_SIZE - 2500));
+        let second = format!("{}<B>2</B>", declaration);
+        let text = format!("{}{}", first, second);
+        for capacity in [4096, 2 * READ_BLOCK_SIZE] {
+            let docs = split_streaming(&text, r"(<\?xml .*?>)", capacity);
+            assert_eq!(
+                docs,
+                vec![first.clone(), second.clone()],
+                "capacity {}",
+                capacity
+            );
+        }
+    }
+
+    #[test]
+    fn streaming_split_waits_for_separator_to_be_complete() {
+        // the preferred alternative of the separator is only complete in the next block
+        let text = "<A>1</A>;END<B>2</B>;<C>3</C>";
+        for capacity in [1, 3, 10, 4096] {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+            let docs: Vec<String> =
+                DocSplitter::new(reader, ";(?:END)?", DelimiterPlacement::AttachToPrevious)
+                    .unwrap()
+                    .map(|doc| doc.unwrap().text)
+                    .collect();
+            assert_eq!(
+                docs,
+                vec!["<A>1</A>;END", "<B>2</B>;", "<C>3</C>"],
+                "capacity {}",
+                capacity
+            );
+        }
+    }
+
+    #[test]
+    fn streaming_split_keeps_whitespace_between_separators() {
+        let text = "\n <?xml v?><A>1</A>;\n;<?xml v?><B>2</B>;\n";
+        let placements = [
+            (
+                DelimiterPlacement::Drop,
+                vec!["\n <?xml v?><A>1</A>", "\n", "<?xml v?><B>2</B>", "\n"],
+            ),
+            (
+                DelimiterPlacement::AttachToNext,
+                vec!["\n <?xml v?><A>1</A>", ";\n", ";<?xml v?><B>2</B>", ";\n"],
+            ),
+            (
+                DelimiterPlacement::AttachToPrevious,
+                vec!["\n <?xml v?><A>1</A>;", "\n;", "<?xml v?><B>2</B>;", "\n"],
+            ),
+            (
+                DelimiterPlacement::Separate,
+                vec![
+                    "\n <?xml v?><A>1</A>",
+                    ";",
+                    "\n",
+                    ";",
+                    "<?xml v?><B>2</B>",
+                    ";",
+                    "\n",
+                ],
+            ),
+        ];
+        for (delimiter, expected) in placements {
+            for capacity in [1, 3, 4096] {
+                let reader =
+                    BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+                let docs: Vec<SplitDocument> = DocSplitter::new(reader, ";", delimiter)
+                    .unwrap()
+                    .map(|doc| doc.unwrap())
+                    .collect();
+                let texts: Vec<&str> = docs.iter().map(|doc| doc.text.as_str()).collect();
+                assert_eq!(texts, expected, "{:?} capacity {}", delimiter, capacity);
+                // the offsets of the documents and the dropped separators cover the input
+                let mut offset = 0;
+                for doc in docs.iter() {
+                    assert_eq!(
+                        &text[doc.start_offset as usize..doc.end_offset as usize],
+                        doc.text
+                    );
+                    if delimiter == DelimiterPlacement::Drop && doc.start_offset > offset {
+                        assert_eq!(&text[offset as usize..doc.start_offset as usize], ";");
+                    } else {
+                        assert_eq!(doc.start_offset, offset);
+                    }
+                    offset = doc.end_offset;
+                }
+                assert_eq!(offset, text.len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_split_handles_multibyte_characters() {
+        let text = "<D>Zürich €</D><D>Genève</D>";
+        let docs = split_streaming(text, r"<D>", 2);
+        assert_eq!(docs, vec!["<D>Zürich €</D>", "<D>Genève</D>"]);
+    }
+
+    #[test]
+    fn streaming_split_reports_invalid_utf8() {
+        let reader = BufReader::new(Cursor::new(vec![b'<', b'D', b'>', 0xff, b'<']));
+        let mut splitter =
+            DocSplitter::new(reader, r"<D>", DelimiterPlacement::AttachToNext).unwrap();
+        assert!(splitter.next().unwrap().is_err());
+    }
+
+    fn split_xml(text: &str, root_element: Option<&str>, capacity: usize) -> Vec<String> {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+        XmlDocSplitter::new(reader, root_element)
+            .map(|doc| doc.unwrap().text)
+            .collect()
+    }
+
+    #[test]
+    fn xml_split_emits_top_level_elements() {
+        let text = "<?xml version=\"1.0\"?>\n<Document><A>1</A></Document>\n\
+                    <Document><B><![CDATA[<?xml version=\"1.0\"?>]]></B></Document>\n\
+                    <!-- c --><Document/>\n";
+        for capacity in [1, 5, 4096] {
+            let docs = split_xml(text, None, capacity);
+            assert_eq!(
+                docs,
+                vec![
+                    "<?xml version=\"1.0\"?>\n<Document><A>1</A></Document>",
+                    "<Document><B><![CDATA[<?xml version=\"1.0\"?>]]></B></Document>",
+                    "<!-- c --><Document/>",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn xml_split_filters_root_element() {
+        let text = "<Batch><Hdr>x</Hdr><Document><Document>1</Document></Document>\n\
+                    <Other/><Document>2</Document></Batch>";
+        let docs = split_xml(text, Some("Document"), 3);
+        assert_eq!(
+            docs,
+            vec![
+                "<Document><Document>1</Document></Document>",
+                "<Document>2</Document>"
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_split_matches_root_local_name() {
+        let text = "<b:Batch xmlns:b=\"urn:b\"><ns2:Document>1</ns2:Document>\
+                    <Document>2</Document><x:Documents/></b:Batch>";
+        assert_eq!(
+            split_xml(text, Some("Document"), 3),
+            vec!["<ns2:Document>1</ns2:Document>", "<Document>2</Document>"]
+        );
+        assert_eq!(
+            split_xml(text, Some("ns2:Document"), 3),
+            vec!["<ns2:Document>1</ns2:Document>"]
+        );
+    }
+
+    #[test]
+    fn xml_split_resynchronises_after_malformed_input() {
+        let text = "<A><B></A>\n<A>2</A>\n<A><!x></A><?xml version=\"1.0\"?><A>3</A>\n<A><B>";
+        for capacity in [1, 4, 4096] {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+            let docs: Vec<(String, u64)> = XmlDocSplitter::new(reader, None)
+                .map(|doc| doc.unwrap())
+                .map(|doc| (doc.text, doc.start_offset))
+                .collect();
+            assert_eq!(
+                docs,
+                vec![
+                    ("<A><B></A>".to_string(), 0),
+                    ("<A>2</A>".to_string(), 11),
+                    ("<A><!x></A>".to_string(), 20),
+                    ("<?xml version=\"1.0\"?><A>3</A>".to_string(), 31),
+                    ("<A><B>".to_string(), 61),
+                ],
+                "capacity {}",
+                capacity
+            );
+        }
+
+        // with a root element filter the malformed part ends at the next root element
+        let text = "<Batch><Document><B></Document><Other/><Document>2</Document></Batch>";
+        assert_eq!(
+            split_xml(text, Some("Document"), 3),
+            vec!["<Document><B></Document><Other/>", "<Document>2</Document>"]
+        );
+    }
+
+    // write the same two entries as plain, gzip, tar, tar.gz and zip file and return
+    // the file names with the expected format
+    fn write_archives(prefix: &str) -> Vec<(String, InputFormat)> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let entries = [
+            ("a.xml", "<Doc>1</Doc>\n<Doc>2</Doc>\n"),
+            ("dir/b.xml", "<Doc>3</Doc>\n"),
+        ];
+        let temp = std::env::temp_dir();
+        let path = |ext: &str| {
+            temp.join(format!("{}.{}", prefix, ext))
+                .to_str()
+                .unwrap()
+                .to_owned()
+        };
+
+        let plain = path("xml");
+        std::fs::write(&plain, entries[0].1).unwrap();
+
+        let gzip = path("xml.gz");
+        let mut encoder = GzEncoder::new(File::create(&gzip).unwrap(), Compression::default());
+        encoder.write_all(entries[0].1.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let tar_data = {
+            let mut builder = tar::Builder::new(Vec::new());
+            for (name, content) in entries.iter() {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, content.as_bytes())
+                    .unwrap();
             }
-            result.push(matched.to_owned());
-            last = index + matched.len();
+            builder.into_inner().unwrap()
+        };
+        let tar = path("tar");
+        std::fs::write(&tar, &tar_data).unwrap();
+        let tar_gz = path("tar.gz");
+        let mut encoder = GzEncoder::new(File::create(&tar_gz).unwrap(), Compression::default());
+        encoder.write_all(&tar_data).unwrap();
+        encoder.finish().unwrap();
+
+        let zip = path("zip");
+        let mut writer = zip::ZipWriter::new(File::create(&zip).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.add_directory("dir/", options).unwrap();
+        for (name, content) in entries.iter() {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        vec![
+            (plain, InputFormat::Plain),
+            (gzip, InputFormat::Gzip),
+            (tar, InputFormat::Tar),
+            (tar_gz, InputFormat::TarGz),
+            (zip, InputFormat::Zip),
+        ]
+    }
+
+    #[test]
+    fn split_compressed_and_archived_files() {
+        let split_mode = SplitMode::XmlStructure { root_element: None };
+        for (filename, format) in write_archives("utilrs_filesplit_archive") {
+            let input = InputFile::open(&filename).unwrap();
+            assert_eq!(input.format(), format);
+
+            let mut entries: Vec<(String, usize)> = Vec::new();
+            input
+                .for_each_entry(|name, reader| {
+                    let docs = open_splitter(reader, &split_mode).unwrap().count();
+                    entries.push((name.to_owned(), docs));
+                    Ok(())
+                })
+                .unwrap();
+            let expected: Vec<(String, usize)> = match format {
+                InputFormat::Plain => vec![("".to_string(), 2)],
+                InputFormat::Gzip => vec![("utilrs_filesplit_archive.xml".to_string(), 2)],
+                _ => vec![("a.xml".to_string(), 2), ("dir/b.xml".to_string(), 1)],
+            };
+            assert_eq!(entries, expected, "{:?}", format);
         }
-        if last < text.len() {
-            result.push(text[last..].to_owned());
+    }
+
+    fn decode(bytes: &[u8], encoding: Option<&str>, capacity: usize) -> (String, usize) {
+        let reader = BufReader::with_capacity(capacity, Cursor::new(bytes.to_vec()));
+        let mut decoder = DecodingReader::new(reader, encoding).unwrap();
+        let errors = decoder.decode_errors();
+        let mut text = String::new();
+        decoder.read_to_string(&mut text).unwrap();
+        let error_count = errors.borrow().total();
+        (text, error_count)
+    }
+
+    #[test]
+    fn detect_and_transcode_encodings() {
+        let latin1 = b"<?xml version='1.0' encoding='ISO-8859-1'?><D>Z\xfcrich</D>";
+        assert_eq!(detect_encoding(latin1).name(), "windows-1252");
+        let (text, errors) = decode(latin1, None, 3);
+        assert_eq!(
+            text,
+            "<?xml version='1.0' encoding='ISO-8859-1'?><D>Zürich</D>"
+        );
+        assert_eq!(errors, 0);
+
+        let mut with_bom = vec![0xFF, 0xFE];
+        with_bom.extend_from_slice(&utf16_le_bytes("<D>Genève</D>"));
+        assert_eq!(detect_encoding(&with_bom), UTF_16LE);
+        assert_eq!(decode(&with_bom, None, 1).0, "<D>Genève</D>");
+        assert_eq!(
+            detect_encoding(&utf16_le_bytes("<?xml version='1.0'?>")),
+            UTF_16LE
+        );
+
+        let mut be_bom = vec![0xFE, 0xFF];
+        for unit in "<?xml?><D>€</D>".encode_utf16() {
+            be_bom.extend_from_slice(&unit.to_be_bytes());
         }
+        assert_eq!(decode(&be_bom, None, 4096).0, "<?xml?><D>€</D>");
+
+        // the override wins over the declaration
+        assert_eq!(decode(b"<D>\xe9</D>", Some("latin1"), 4096).0, "<D>é</D>");
+        assert!(DecodingReader::new(Cursor::new(vec![]), Some("no-such-enc")).is_err());
+    }
+
+    fn utf16_le_bytes(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn undecodable_bytes_are_replaced_and_counted() {
+        let (text, errors) = decode(b"<D>a\xffb</D><D>ok</D>", None, 2);
+        assert_eq!(text, "<D>a\u{FFFD}b</D><D>ok</D>");
+        assert_eq!(errors, 1);
+
+        // the errors are located in the output, also when decoding ahead of the reads
+        let input = b"<D>\xff</D><D>\xe2\x82</D><D>ok</D><D>\xff\xff</D>".to_vec();
+        for capacity in [1, 4096] {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(input.clone()));
+            let mut decoder = DecodingReader::new(reader, None).unwrap();
+            let errors = decoder.decode_errors();
+            let mut text = String::new();
+            decoder.read_to_string(&mut text).unwrap();
+            let mut errors = errors.borrow_mut();
+            assert_eq!(errors.total(), 4);
+            assert_eq!(errors.count_between(0, 11), 1);
+            assert_eq!(errors.count_between(11, 22), 1);
+            assert_eq!(errors.count_between(22, 31), 0);
+            assert_eq!(errors.count_between(31, 46), 2);
+        }
+    }
+
+    #[test]
+    fn source_offsets_of_transcoded_input() {
+        let check = |text: &str, bom: &[u8], encoding: &str| {
+            let input_encoding = Encoding::for_label(encoding.as_bytes()).unwrap();
+            let encode = |text: &str| -> Vec<u8> {
+                match input_encoding == UTF_16LE {
+                    true => utf16_le_bytes(text),
+                    false => input_encoding.encode(text).0.into_owned(),
+                }
+            };
+            let mut input = bom.to_vec();
+            input.extend(encode(text));
+            for capacity in [1, 3, 4096] {
+                let reader = BufReader::with_capacity(capacity, Cursor::new(input.clone()));
+                let mut decoder = DecodingReader::new(reader, Some(encoding)).unwrap();
+                let offsets = decoder.source_offsets();
+                let mut decoded = String::new();
+                decoder.read_to_string(&mut decoded).unwrap();
+                assert_eq!(decoded, text);
+                let mut offsets = offsets.borrow_mut();
+                for pos in text
+                    .match_indices("<D>")
+                    .map(|(pos, _)| pos)
+                    .chain([text.len()])
+                {
+                    let expected = bom.len() + encode(&text[..pos]).len();
+                    assert_eq!(
+                        offsets.source_offset(pos as u64),
+                        expected as u64,
+                        "{} capacity {} offset {}",
+                        encoding,
+                        capacity,
+                        pos
+                    );
+                }
+            }
+        };
+        let text = "<D>Zürich</D>\n<D>Genève €</D>\n<D>ok</D>";
+        check(text, b"", "UTF-8");
+        check(text, &[0xEF, 0xBB, 0xBF], "UTF-8");
+        check(text, b"", "ISO-8859-1");
+        check(
+            "<D>Zürich \u{1D11E}</D>\n<D>ok</D>",
+            &[0xFF, 0xFE],
+            "UTF-16LE",
+        );
+        let text = "<D>東京</D>\n<D>カタカナ</D>\n<D>ok</D>";
+        check(text, b"", "Shift_JIS");
+        check(text, b"", "ISO-2022-JP");
+
+        // an undecodable sequence keeps its length in the input
+        let reader = Cursor::new(b"<D>a\xffb</D><D>\xe2\x82</D><D>ok</D>".to_vec());
+        let mut decoder = DecodingReader::new(reader, None).unwrap();
+        let offsets = decoder.source_offsets();
+        let mut decoded = String::new();
+        decoder.read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "<D>a\u{FFFD}b</D><D>\u{FFFD}</D><D>ok</D>");
+        let mut offsets = offsets.borrow_mut();
+        let source: Vec<u64> = [0, 12, 22, 31]
+            .into_iter()
+            .map(|pos| offsets.source_offset(pos))
+            .collect();
+        assert_eq!(source, vec![0, 10, 19, 28]);
+    }
+
+    #[test]
+    fn resolve_directory_and_glob() {
+        let dir = std::env::temp_dir().join("utilrs_resolve_input_files");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["b.xml", "a.xml", "c.txt"] {
+            std::fs::write(dir.join(name), "<A/>").unwrap();
+        }
+        let dir_name = dir.to_str().unwrap();
+
+        let files = resolve_input_files(dir_name).unwrap();
+        let names: Vec<&str> = files
+            .iter()
+            .map(|f| f.rsplit(std::path::MAIN_SEPARATOR).next().unwrap())
+            .collect();
+        assert_eq!(names, vec!["a.xml", "b.xml", "c.txt"]);
+
+        let pattern = dir.join("*.xml");
+        let files = resolve_input_files(pattern.to_str().unwrap()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(resolve_input_files(dir.join("*.none").to_str().unwrap()).is_err());
+
+        assert_eq!(
+            file_checksum(&files[0]).unwrap(),
+            "4f6031c3735adbd4e37fdde023095b7a1335852aeff04f3b7435c5a07a00ed8e"
+        );
+    }
+
+    #[test]
+    fn split_documents_report_their_location() {
+        let text = "<?xml v?>\n<A>1</A>\n<?xml v?>\n<B>\n2\n</B>\n<?xml v?><C/>";
+        let location = |doc: SplitDocument| (doc.start_offset, doc.end_offset, doc.start_line);
+
+        let reader = BufReader::with_capacity(4, Cursor::new(text.as_bytes().to_vec()));
+        let docs: Vec<(u64, u64, u64)> =
+            DocSplitter::new(reader, r"<\?xml v\?>", DelimiterPlacement::AttachToNext)
+                .unwrap()
+                .map(|doc| location(doc.unwrap()))
+                .collect();
+        assert_eq!(docs, vec![(0, 19, 1), (19, 40, 3), (40, 53, 7)]);
+
+        let reader = BufReader::with_capacity(4, Cursor::new(text.as_bytes().to_vec()));
+        let docs: Vec<(u64, u64, u64)> = XmlDocSplitter::new(reader, None)
+            .map(|doc| location(doc.unwrap()))
+            .collect();
+        assert_eq!(docs, vec![(0, 18, 1), (19, 39, 3), (40, 53, 7)]);
+        assert_eq!(&text[19..39], "<?xml v?>\n<B>\n2\n</B>");
+    }
+
+    #[test]
+    fn delimiter_placement_preserves_bytes() {
+        use DelimiterPlacement::*;
+        let cases: Vec<(&str, &str, DelimiterPlacement, Vec<&str>)> = vec![
+            (
+                "HDR<?xml v?><A>1</A><?xml v?><B>2</B>",
+                r"<\?xml v\?>",
+                AttachToNext,
+                vec!["HDR", "<?xml v?><A>1</A>", "<?xml v?><B>2</B>"],
+            ),
+            (
+                "HDR<?xml v?><A>1</A><?xml v?><B>2</B>",
+                r"<\?xml v\?>",
+                AttachToPrevious,
+                vec!["HDR<?xml v?>", "<A>1</A><?xml v?>", "<B>2</B>"],
+            ),
+            (
+                "HDR<?xml v?><A>1</A><?xml v?><B>2</B>",
+                r"<\?xml v\?>",
+                Drop,
+                vec!["HDR", "<A>1</A>", "<B>2</B>"],
+            ),
+            (
+                "HDR<?xml v?><A>1</A><?xml v?><B>2</B>",
+                r"<\?xml v\?>",
+                Separate,
+                vec!["HDR", "<?xml v?>", "<A>1</A>", "<?xml v?>", "<B>2</B>"],
+            ),
+            // adjacent separators and a separator at the end of the input
+            (
+                "<A/>;;<B/>;",
+                ";",
+                AttachToNext,
+                vec!["<A/>", ";", ";<B/>", ";"],
+            ),
+            (
+                "<A/>;;<B/>;",
+                ";",
+                AttachToPrevious,
+                vec!["<A/>;", ";", "<B/>;"],
+            ),
+            ("<A/>;;<B/>;", ";", Drop, vec!["<A/>", "<B/>"]),
+            (
+                "<A/>;;<B/>;",
+                ";",
+                Separate,
+                vec!["<A/>", ";", ";", "<B/>", ";"],
+            ),
+        ];
+        for (text, reg_ex, delimiter, expected) in cases {
+            for capacity in [1, 2, 5, 4096] {
+                let reader =
+                    BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+                let docs: Vec<SplitDocument> = DocSplitter::new(reader, reg_ex, delimiter)
+                    .unwrap()
+                    .map(|doc| doc.unwrap())
+                    .collect();
+                let texts: Vec<&str> = docs.iter().map(|doc| doc.text.as_str()).collect();
+                assert_eq!(texts, expected, "{:?}, capacity {}", delimiter, capacity);
+                if delimiter != Drop {
+                    assert_eq!(texts.concat(), text, "{:?}", delimiter);
+                }
+                for doc in docs {
+                    let (start, end) = (doc.start_offset as usize, doc.end_offset as usize);
+                    assert_eq!(&text[start..end], doc.text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn envelope_split_finds_long_start_marker_across_read_blocks() {
+        let junk = "j".repeat(READ_BLOCK_SIZE - 2000);
+        let message = format!("<Message id=\"{}\">1</Message>", "7".repeat(3000));
+        let text = format!("{}{}junk", junk, message);
+        for capacity in [4096, 2 * READ_BLOCK_SIZE] {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+            let mut splitter =
+                EnvelopeSplitter::new(reader, r#"<Message id="[0-9]{1,4000}">"#, "</Message>")
+                    .unwrap();
+            let docs: Vec<SplitDocument> = splitter.by_ref().map(|doc| doc.unwrap()).collect();
+            assert_eq!(docs.len(), 1, "capacity {}", capacity);
+            assert_eq!(docs[0].text, message);
+            assert_eq!(docs[0].start_offset, junk.len() as u64);
+            assert_eq!(
+                splitter.take_warnings(),
+                vec![
+                    format!(
+                        "stray text outside of envelopes at line 1, bytes 0-{}",
+                        junk.len()
+                    ),
+                    format!(
+                        "stray text outside of envelopes at line 1, bytes {}-{}",
+                        text.len() - 4,
+                        text.len()
+                    ),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn envelope_split_extracts_enclosed_regions() {
+        let text = "junk\n<Message>1</Message>\n\n<Message>\n2\n</Message>noise\n<Message>3";
+        for capacity in [1, 3, 64, 4096] {
+            let reader = BufReader::with_capacity(capacity, Cursor::new(text.as_bytes().to_vec()));
+            let mut splitter = EnvelopeSplitter::new(reader, "<Message>", "</Message>").unwrap();
+            let docs: Vec<SplitDocument> = splitter.by_ref().map(|doc| doc.unwrap()).collect();
+            let texts: Vec<&str> = docs.iter().map(|doc| doc.text.as_str()).collect();
+            assert_eq!(
+                texts,
+                vec!["<Message>1</Message>", "<Message>\n2\n</Message>"]
+            );
+            assert_eq!((docs[1].start_offset, docs[1].end_offset), (27, 49));
+            assert_eq!(docs[1].start_line, 4);
+            // whitespace between envelopes is not reported
+            assert_eq!(
+                splitter.take_warnings(),
+                vec![
+                    "stray text outside of envelopes at line 1, bytes 0-5",
+                    "stray text outside of envelopes at line 6, bytes 49-55",
+                    "envelope starting at line 7, byte 55 is not closed before the end of the input",
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn document_hash_ignores_formatting() {
+        let hash = document_hash("<A><B>1</B></A>");
+        assert_eq!(hash, document_hash("\n<A>\r\n  <B>1</B>\r\n</A>  "));
+        assert_ne!(hash, document_hash("<A><B> 1</B></A>"));
+        assert_ne!(hash, document_hash("<A><B>2</B></A>"));
     }
-    result
 }
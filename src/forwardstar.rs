@@ -25,17 +25,17 @@ impl ForwardStar {
     // Internal functions
     // --------------------------
 
-    fn select_node_by_caption(&mut self, node_caption: &str) -> Result<usize, String> {
+    fn select_node_by_caption(&self, node_caption: &str) -> Result<usize, String> {
         let sel_node: Vec<usize> = self
             .node_caption
             .iter()
             .enumerate()
-            .filter(|&(_, nc)| nc == &node_caption)
+            .filter(|&(_, nc)| nc == node_caption)
             .map(|(i, _)| i)
             .collect();
 
         // println!("sel_node: {:?}", sel_node);
-        if sel_node.len() == 0 {
+        if sel_node.is_empty() {
             // return an error
             let msg = format!("parent node caption '{}' was not found", node_caption);
             Err(msg)
@@ -72,7 +72,7 @@ impl ForwardStar {
         let var_from = from_node + 1;
         let var_to = self.num_nodes + 1; // + 1 is needed as range works <from> inclusive to <to> exclusive
         for i in var_from..var_to {
-            self.first_link[i] = self.first_link[i] + 1;
+            self.first_link[i] += 1;
         }
     }
 
@@ -101,30 +101,25 @@ impl ForwardStar {
         self.num_nodes = 1;
     }
 
-    pub fn add_child(&mut self, parent_node_caption: &str, child_caption: &str) {
+    pub fn add_child(
+        &mut self,
+        parent_node_caption: &str,
+        child_caption: &str,
+    ) -> Result<(), String> {
         // select the parent node
-        match self.select_node_by_caption(parent_node_caption) {
-            Ok(x) => self.selected_node = x,
-            Err(e) => panic!("{}", e),
-        };
+        self.selected_node = self.select_node_by_caption(parent_node_caption)?;
         // create the new node
         let node = self.new_node(child_caption);
         // println!("parent: {parent_node_caption} | child: {child_caption}");
         // add the link from the parent to the new node
         self.add_link(self.selected_node, node);
+        Ok(())
     }
 
-    pub fn find_parent_by_caption(&mut self, node_caption: &str) -> (usize, usize) {
+    pub fn find_parent_by_caption(&self, node_caption: &str) -> Result<(usize, usize), String> {
         // get the node index
-        let node;
-        match self.select_node_by_caption(node_caption) {
-            Ok(x) => {
-                node = x;
-            }
-            Err(e) => panic!("{}", e),
-        };
-        // let node = self.select_node_by_caption(node_caption)?;
-        self.find_parent(node)
+        let node = self.select_node_by_caption(node_caption)?;
+        Ok(self.find_parent(node))
     }
 
     pub fn find_parent(&self, node: usize) -> (usize, usize) {
@@ -147,14 +142,14 @@ impl ForwardStar {
         out
     }
 
-    pub fn find_node_by_caption(&mut self, node_caption: &str) -> usize {
+    pub fn find_node_by_caption(&self, node_caption: &str) -> Result<usize, String> {
         // get the node index
-        match self.select_node_by_caption(node_caption) {
-            Ok(x) => {
-                return x;
-            }
-            Err(_e) => panic!("parent node must be unique is not unique!"),
-        };
+        self.select_node_by_caption(node_caption)
+    }
+
+    /// The child nodes of a node in the order they were added
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.to_node[self.first_link[node]..self.first_link[node + 1]]
     }
 
     pub fn display_tree(&self) {
@@ -184,11 +179,7 @@ impl ForwardStar {
     }
 
     pub fn has_root(&self) -> bool {
-        if self.num_nodes == 0 {
-            false
-        } else {
-            true
-        }
+        self.num_nodes != 0
     }
 
     pub fn get_node_captions(&self) -> &Vec<String> {
@@ -214,16 +205,34 @@ mod tests {
         // println!("has root: {}", fstar.has_root());
         fstar.add_root("Grandfather");
         // println!("has root: {}", fstar.has_root());
-        fstar.add_child("Grandfather", "Father");
-        fstar.add_child("Father", "Son of Father");
-        fstar.add_child("Father", "Daughter of Father");
-        fstar.add_child("Grandfather", "Daughter");
-        fstar.add_child("Daughter", "Son of Daughter");
-        fstar.add_child("Daughter", "Daughter of Daugther");
-        fstar.add_child("Son of Father", "Son of Son of Father");
-        fstar.add_child("Son of Father", "Daughter of Son of Father");
+        fstar.add_child("Grandfather", "Father").unwrap();
+        fstar.add_child("Father", "Son of Father").unwrap();
+        fstar.add_child("Father", "Daughter of Father").unwrap();
+        fstar.add_child("Grandfather", "Daughter").unwrap();
+        fstar.add_child("Daughter", "Son of Daughter").unwrap();
+        fstar.add_child("Daughter", "Daughter of Daugther").unwrap();
+        fstar
+            .add_child("Son of Father", "Son of Son of Father")
+            .unwrap();
+        fstar
+            .add_child("Son of Father", "Daughter of Son of Father")
+            .unwrap();
 
         println!("This is fstar:\n{:?}", fstar);
         fstar.display_tree();
     }
+
+    #[test]
+    fn add_child_to_unknown_parent_fails() {
+        let mut fstar = ForwardStar::new();
+        fstar.add_root("Root");
+        fstar.add_child("Root", "Child").unwrap();
+        fstar.add_child("Root", "Child").unwrap();
+
+        assert!(fstar.add_child("Unknown", "Orphan").is_err());
+        assert!(fstar.add_child("Child", "Grandchild").is_err());
+        assert!(fstar.find_node_by_caption("Unknown").is_err());
+        assert_eq!(fstar.find_parent_by_caption("Root").unwrap().0, usize::MAX);
+        assert_eq!(fstar.num_nodes, 3);
+    }
 }
@@ -1,33 +1,719 @@
+// the pymethods macro of pyo3 0.16 generates impl blocks nested inside a static
+#![allow(non_local_definitions)]
+use pyo3::exceptions::PyValueError;
 use pyo3::prelude::*;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
 
 mod fileprocessor;
 
 use crate::fileprocessor::*;
+
+// the python exceptions raised for the errors of the split, process and database functions,
+// the create_exception macro of pyo3 0.16 checks a cfg unknown to the current rustc
+#[allow(unexpected_cfgs)]
+mod exceptions {
+    use pyo3::create_exception;
+    use pyo3::exceptions::PyException;
+
+    create_exception!(
+        utilrs,
+        UtilrsError,
+        PyException,
+        "Base class of all utilrs errors"
+    );
+    create_exception!(
+        utilrs,
+        ConfigError,
+        UtilrsError,
+        "The database config file can not be read or is invalid, see `file`"
+    );
+    create_exception!(
+        utilrs,
+        IoError,
+        UtilrsError,
+        "An input file can not be opened or read, see `path` and `errno`"
+    );
+    create_exception!(
+        utilrs,
+        SplitError,
+        UtilrsError,
+        "An input file can not be split into documents, see `file` and `byte_offset`"
+    );
+    create_exception!(
+        utilrs,
+        XmlParseError,
+        UtilrsError,
+        "A document is not well-formed xml, see `doc_id` and `position`"
+    );
+    create_exception!(
+        utilrs,
+        DatabaseError,
+        UtilrsError,
+        "A database operation failed"
+    );
+    create_exception!(
+        utilrs,
+        SchemaError,
+        UtilrsError,
+        "An xml schema file can not be read or is not in the schema directory, see `file`"
+    );
+}
+
+// raise the python exception of an error, the fields of the error are set as attributes
+// of the exception next to the message
+impl From<UtilrsError> for PyErr {
+    fn from(err: UtilrsError) -> PyErr {
+        let message = err.to_string();
+        Python::with_gil(|py| {
+            let (py_err, fields): (PyErr, Vec<(&str, PyObject)>) = match err {
+                UtilrsError::Config { file, .. } => (
+                    exceptions::ConfigError::new_err(message.clone()),
+                    vec![("file", file.into_py(py))],
+                ),
+                UtilrsError::Io { path, source } => (
+                    exceptions::IoError::new_err(message.clone()),
+                    vec![
+                        ("path", path.into_py(py)),
+                        ("errno", source.raw_os_error().into_py(py)),
+                    ],
+                ),
+                UtilrsError::Split {
+                    file, byte_offset, ..
+                } => (
+                    exceptions::SplitError::new_err(message.clone()),
+                    vec![
+                        ("file", file.into_py(py)),
+                        ("byte_offset", byte_offset.into_py(py)),
+                    ],
+                ),
+                UtilrsError::XmlParse {
+                    doc_id, position, ..
+                } => (
+                    exceptions::XmlParseError::new_err(message.clone()),
+                    vec![
+                        ("doc_id", doc_id.into_py(py)),
+                        ("position", position.into_py(py)),
+                    ],
+                ),
+                UtilrsError::Database { .. } => (
+                    exceptions::DatabaseError::new_err(message.clone()),
+                    Vec::new(),
+                ),
+                UtilrsError::Schema { file, .. } => (
+                    exceptions::SchemaError::new_err(message.clone()),
+                    vec![("file", file.into_py(py))],
+                ),
+            };
+            let value = py_err.value(py);
+            for (name, field) in [("message", message.into_py(py))].into_iter().chain(fields) {
+                if let Err(attr_err) = value.setattr(name, field) {
+                    return attr_err;
+                }
+            }
+            py_err
+        })
+    }
+}
+
+// minimum time between two calls of a python progress callback
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// token to cancel a running split or process call, e.g. from another python thread
+#[pyclass]
+#[derive(Clone, Default)]
+struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+#[pymethods]
+impl CancelToken {
+    #[new]
+    fn new() -> Self {
+        CancelToken::default()
+    }
+
+    fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// build the run control of a call running without the GIL. At most every
+// PROGRESS_INTERVAL the GIL is taken to check for pending signals and to call the progress
+// callback with (done, total, errors). A KeyboardInterrupt or an exception raised by the
+// callback cancels the run and is stored to be raised once the run has stopped.
+fn get_run_control(
+    progress: Option<PyObject>,
+    cancel_token: Option<&CancelToken>,
+) -> (RunControl, Arc<Mutex<Option<PyErr>>>) {
+    let cancelled = cancel_token
+        .map(|token| token.cancelled.clone())
+        .unwrap_or_default();
+    let error: Arc<Mutex<Option<PyErr>>> = Arc::new(Mutex::new(None));
+    let last_call: Mutex<Option<Instant>> = Mutex::new(None);
+
+    let run_error = error.clone();
+    let control = RunControl::new()
+        .with_cancel_flag(cancelled.clone())
+        .with_progress(move |p| {
+            let is_final = p.total == Some(p.done);
+            {
+                // a panicking callback poisons the mutexes, the data is still usable
+                let mut last_call = last_call.lock().unwrap_or_else(PoisonError::into_inner);
+                let is_due = last_call.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+                if !is_final && !is_due {
+                    return;
+                }
+                *last_call = Some(Instant::now());
+            }
+            Python::with_gil(|py| {
+                let result = py.check_signals().and_then(|_| match &progress {
+                    Some(callback) => callback.call1(py, (p.done, p.total, p.errors)).map(|_| ()),
+                    None => Ok(()),
+                });
+                if let Err(err) = result {
+                    run_error
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get_or_insert(err);
+                    cancelled.store(true, Ordering::Relaxed);
+                }
+            });
+        });
+    (control, error)
+}
+
+// raise the error which cancelled a run, if any
+fn check_run_error(error: &Mutex<Option<PyErr>>) -> PyResult<()> {
+    match error.lock().unwrap_or_else(PoisonError::into_inner).take() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+// build the split mode from the python arguments
+fn get_split_mode(
+    split_mode: &str,
+    reg_ex: &str,
+    delimiter: &str,
+    end_reg_ex: Option<&str>,
+    root_element: Option<&str>,
+) -> PyResult<SplitMode> {
+    match split_mode {
+        "regex" => Ok(SplitMode::Regex {
+            reg_ex: reg_ex.to_owned(),
+            delimiter: get_delimiter_placement(delimiter)?,
+        }),
+        "xml" => Ok(SplitMode::XmlStructure {
+            root_element: root_element.map(|name| name.to_owned()),
+        }),
+        "envelope" => match end_reg_ex {
+            Some(end_reg_ex) => Ok(SplitMode::Envelope {
+                start_reg_ex: reg_ex.to_owned(),
+                end_reg_ex: end_reg_ex.to_owned(),
+            }),
+            None => Err(PyValueError::new_err(
+                "split_mode 'envelope' requires an end_reg_ex",
+            )),
+        },
+        _ => Err(PyValueError::new_err(format!(
+            "unknown split_mode '{}', expected 'regex', 'xml' or 'envelope'",
+            split_mode
+        ))),
+    }
+}
+
+// build the delimiter placement of the regex split mode from the python argument
+fn get_delimiter_placement(delimiter: &str) -> PyResult<DelimiterPlacement> {
+    match delimiter {
+        "next" => Ok(DelimiterPlacement::AttachToNext),
+        "previous" => Ok(DelimiterPlacement::AttachToPrevious),
+        "drop" => Ok(DelimiterPlacement::Drop),
+        "separate" => Ok(DelimiterPlacement::Separate),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown delimiter '{}', expected 'next', 'previous', 'drop' or 'separate'",
+            delimiter
+        ))),
+    }
+}
+
+// build the split options from the python arguments
+fn get_split_options(
+    expected_root: Option<&str>,
+    expected_namespace: Option<&str>,
+    encoding: Option<&str>,
+    duplicates: &str,
+    append: bool,
+) -> PyResult<SplitOptions> {
+    let duplicate_policy = match duplicates {
+        "keep" => DuplicatePolicy::Keep,
+        "flag" => DuplicatePolicy::Flag,
+        "skip" => DuplicatePolicy::Skip,
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown duplicates policy '{}', expected 'keep', 'flag' or 'skip'",
+                duplicates
+            )))
+        }
+    };
+    Ok(SplitOptions {
+        expected_root: expected_root.map(|name| name.to_owned()),
+        expected_namespace: expected_namespace.map(|name| name.to_owned()),
+        encoding: encoding.map(|name| name.to_owned()),
+        duplicate_policy,
+        append,
+    })
+}
+
 /// split all documents in a file
-#[pyfunction]
-fn split_file_content(dbname: &str, cfgname: &str, filename: &str, reg_ex: &str) -> PyResult<bool> {
-    Ok(split_file(dbname, cfgname, filename, reg_ex).unwrap())
+///
+/// split_mode "regex" cuts the file at every match of reg_ex, the matched text starts the
+/// next document, ends the previous one, is dropped or becomes a document of its own for
+/// delimiter "next", "previous", "drop" or "separate". split_mode "xml" cuts the
+/// file based on its element structure, optionally only at elements named root_element
+/// (without prefix any prefix matches), and stores a malformed part up to the next document
+/// as invalid document, split_mode "envelope" extracts the regions from a match of reg_ex up to the next match of
+/// end_reg_ex and logs a warning for any other text.
+/// Every document is checked for well-formedness and - if given - for the expected root
+/// element and namespace. The input is transcoded to UTF-8, its encoding is detected from a
+/// BOM or the xml declaration unless an encoding is given. Documents with the same content
+/// as an earlier document - in this run or in the database - are stored ("keep"), stored
+/// with a reference to the first copy ("flag") or not stored at all ("skip"), every
+/// duplicate is reported in the process log. With append the documents are added to the
+/// ones in the database, otherwise the database content is replaced. Returns the number of
+/// valid and invalid documents.
+///
+/// The GIL is released while splitting. progress is called with the number of documents
+/// done, the total (None while splitting) and the number of invalid documents. The run
+/// stops after the current document when cancel_token is cancelled or on a
+/// KeyboardInterrupt, which is raised once the documents split so far are committed.
+///
+/// Errors are raised as subclasses of UtilrsError: ConfigError, IoError, SplitError and
+/// DatabaseError.
+#[pyfunction(
+    reg_ex = "\"\"",
+    split_mode = "\"regex\"",
+    delimiter = "\"next\"",
+    end_reg_ex = "None",
+    root_element = "None",
+    expected_root = "None",
+    expected_namespace = "None",
+    encoding = "None",
+    duplicates = "\"keep\"",
+    append = "false",
+    progress = "None",
+    cancel_token = "None"
+)]
+#[allow(clippy::too_many_arguments)]
+fn split_file_content(
+    py: Python,
+    dbname: &str,
+    cfgname: &str,
+    filename: &str,
+    reg_ex: &str,
+    split_mode: &str,
+    delimiter: &str,
+    end_reg_ex: Option<&str>,
+    root_element: Option<&str>,
+    expected_root: Option<&str>,
+    expected_namespace: Option<&str>,
+    encoding: Option<&str>,
+    duplicates: &str,
+    append: bool,
+    progress: Option<PyObject>,
+    cancel_token: Option<CancelToken>,
+) -> PyResult<(usize, usize)> {
+    let split_mode = get_split_mode(split_mode, reg_ex, delimiter, end_reg_ex, root_element)?;
+    let options = get_split_options(
+        expected_root,
+        expected_namespace,
+        encoding,
+        duplicates,
+        append,
+    )?;
+    let (control, run_error) = get_run_control(progress, cancel_token.as_ref());
+    let result =
+        py.allow_threads(|| split_file(dbname, cfgname, filename, &split_mode, &options, &control));
+    check_run_error(&run_error)?;
+    let summary = result?;
+    Ok((summary.valid_docs, summary.invalid_docs))
+}
+
+/// split all documents in all files of a directory or matching a glob pattern
+///
+/// Takes the same arguments as split_file_content, the doc ids are unique over all files
+/// and the source file of every document is recorded.
+#[pyfunction(
+    reg_ex = "\"\"",
+    split_mode = "\"regex\"",
+    delimiter = "\"next\"",
+    end_reg_ex = "None",
+    root_element = "None",
+    expected_root = "None",
+    expected_namespace = "None",
+    encoding = "None",
+    duplicates = "\"keep\"",
+    append = "false",
+    progress = "None",
+    cancel_token = "None"
+)]
+#[allow(clippy::too_many_arguments)]
+fn split_files_content(
+    py: Python,
+    dbname: &str,
+    cfgname: &str,
+    path_or_pattern: &str,
+    reg_ex: &str,
+    split_mode: &str,
+    delimiter: &str,
+    end_reg_ex: Option<&str>,
+    root_element: Option<&str>,
+    expected_root: Option<&str>,
+    expected_namespace: Option<&str>,
+    encoding: Option<&str>,
+    duplicates: &str,
+    append: bool,
+    progress: Option<PyObject>,
+    cancel_token: Option<CancelToken>,
+) -> PyResult<(usize, usize)> {
+    let split_mode = get_split_mode(split_mode, reg_ex, delimiter, end_reg_ex, root_element)?;
+    let options = get_split_options(
+        expected_root,
+        expected_namespace,
+        encoding,
+        duplicates,
+        append,
+    )?;
+    let (control, run_error) = get_run_control(progress, cancel_token.as_ref());
+    let result = py.allow_threads(|| {
+        split_files(
+            dbname,
+            cfgname,
+            path_or_pattern,
+            &split_mode,
+            &options,
+            &control,
+        )
+    });
+    check_run_error(&run_error)?;
+    let summary = result?;
+    Ok((summary.valid_docs, summary.invalid_docs))
+}
+
+/// split a file without writing to the database and return a SplitPreview
+///
+/// Takes the split arguments of split_file_content. The preview holds the number of
+/// chunks, their size statistics, the heads of the first and last head_count chunks and all
+/// chunks which are not well-formed.
+#[pyfunction(
+    reg_ex = "\"\"",
+    split_mode = "\"regex\"",
+    delimiter = "\"next\"",
+    end_reg_ex = "None",
+    root_element = "None",
+    encoding = "None",
+    head_count = "3",
+    head_len = "80"
+)]
+#[allow(clippy::too_many_arguments)]
+fn preview_split_content(
+    filename: &str,
+    reg_ex: &str,
+    split_mode: &str,
+    delimiter: &str,
+    end_reg_ex: Option<&str>,
+    root_element: Option<&str>,
+    encoding: Option<&str>,
+    head_count: usize,
+    head_len: usize,
+) -> PyResult<SplitPreview> {
+    let split_mode = get_split_mode(split_mode, reg_ex, delimiter, end_reg_ex, root_element)?;
+    let preview = preview_split(filename, &split_mode, encoding, head_count, head_len)?;
+    Ok(preview)
 }
 
 /// processes all documents in a file
-#[pyfunction]
-fn process_file_content(dbname: &str, cfgname: &str) -> PyResult<String> {
-    Ok(process_file(dbname, cfgname).unwrap())
+///
+/// Only valid documents which have not been parsed by an earlier run are processed,
+/// documents which could not be parsed are not tried again. With reparse the stored
+/// parse results are removed and all valid documents - including the ones marked invalid
+/// for violating their schema - are parsed again, e.g. to rerun with other options. The
+/// outcome is stored as the parse_status of each Document. The documents are parsed by workers threads, 0 uses one thread per
+/// available CPU.
+/// The GIL is released while processing, progress and cancel_token work as for
+/// split_file_content. A cancelled run returns "cancelled", the remaining documents are
+/// processed by the next call. Documents which can not be parsed are logged, errors of
+/// the database or its config are raised as DatabaseError or ConfigError.
+/// namespaces selects the tag names of elements in a namespace as for XmlDoc: "prefixed",
+/// "local" or "clark". Elements without content get the value empty_value if given,
+/// mixed_content and ignore_whitespace select how text is stored as for XmlDoc.
+///
+/// With schema_dir every parsed document is validated against an xsd file of that local
+/// directory: the file given in the dict schemas for its namespace or document type, or
+/// else the one with the namespace of the document as target namespace. Each violation is
+/// logged with its path, what was expected and what was found, and a document violating
+/// its schema is marked invalid. A schema which can not be read raises SchemaError, the
+/// parts of a schema which are not checked - imports and patterns which can not be
+/// translated - are logged as warnings.
+#[pyfunction(
+    workers = "1",
+    progress = "None",
+    cancel_token = "None",
+    namespaces = "\"prefixed\"",
+    empty_value = "None",
+    mixed_content = "\"concatenate\"",
+    ignore_whitespace = "true",
+    schema_dir = "None",
+    schemas = "None",
+    reparse = "false"
+)]
+#[allow(clippy::too_many_arguments)]
+fn process_file_content(
+    py: Python,
+    dbname: &str,
+    cfgname: &str,
+    workers: usize,
+    progress: Option<PyObject>,
+    cancel_token: Option<CancelToken>,
+    namespaces: &str,
+    empty_value: Option<String>,
+    mixed_content: &str,
+    ignore_whitespace: bool,
+    schema_dir: Option<&str>,
+    schemas: Option<HashMap<String, String>>,
+    reparse: bool,
+) -> PyResult<String> {
+    let schemas = match (schema_dir, schemas) {
+        (Some(dir), schemas) => Some(SchemaStore::load(dir, &schemas.unwrap_or_default())?),
+        (None, Some(_)) => {
+            return Err(PyValueError::new_err("schemas needs a schema_dir"));
+        }
+        (None, None) => None,
+    };
+    let options = ParseOptions {
+        namespace_mode: get_namespace_mode(namespaces)?,
+        empty_value,
+        mixed_content: get_mixed_content(mixed_content)?,
+        ignore_whitespace,
+        ..ParseOptions::default()
+    };
+    let (control, run_error) = get_run_control(progress, cancel_token.as_ref());
+    let result = py.allow_threads(|| {
+        process_file_with_workers(
+            dbname,
+            cfgname,
+            workers,
+            &options,
+            schemas.as_ref(),
+            reparse,
+            &control,
+        )
+    });
+    check_run_error(&run_error)?;
+    Ok(result?)
 }
 #[pyfunction]
 fn process_single_doc(doc_content: &str) -> PyResult<Vec<Tag>> {
-    let result = process_single_document(doc_content).unwrap();
+    let result = process_single_document(doc_content)?;
     Ok(result)
 }
 
+// number of documents a DocIterator reads from the database at once
+const DOC_BATCH_SIZE: usize = 100;
+
+fn get_doc_validity(validity: Option<&str>) -> PyResult<Option<DocValidity>> {
+    match validity {
+        None => Ok(None),
+        Some(name) => match DocValidity::from_name(name) {
+            Some(validity) => Ok(Some(validity)),
+            None => Err(PyValueError::new_err(format!(
+                "unknown validity '{}', use 'valid' or 'invalid'",
+                name
+            ))),
+        },
+    }
+}
+
+/// read access to the database written by split_file_content and process_file_content
+///
+/// The database is locked exclusively while it is open, close it - or use it as a context
+/// manager - before running another split or process call on it. Errors of the database
+/// or its config are raised as DatabaseError or ConfigError.
+#[pyclass]
+struct Database {
+    db: Option<DataBase>,
+}
+
+impl Database {
+    fn db(&mut self) -> PyResult<&mut DataBase> {
+        self.db
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("the database is closed"))
+    }
+}
+
+#[pymethods]
+impl Database {
+    #[new]
+    fn py_new(dbname: &str, cfgname: &str) -> PyResult<Self> {
+        let db = DataBase::new(dbname, cfgname)?;
+        Ok(Database { db: Some(db) })
+    }
+
+    /// number of documents, all or only the "valid" or "invalid" ones
+    #[args(validity = "None")]
+    fn doc_count(&mut self, validity: Option<&str>) -> PyResult<usize> {
+        let validity = get_doc_validity(validity)?;
+        let db = self.db()?;
+        let count = match validity {
+            Some(validity) => db.get_doc_count(validity).map_err(UtilrsError::from)?,
+            None => {
+                db.get_doc_count(DocValidity::Valid)
+                    .map_err(UtilrsError::from)?
+                    + db.get_doc_count(DocValidity::Invalid)
+                        .map_err(UtilrsError::from)?
+            }
+        };
+        Ok(count)
+    }
+
+    /// the document with the id, None if there is no such document
+    fn get_doc(&mut self, doc_id: isize) -> PyResult<Option<TableDocList>> {
+        let doc = self.db()?.find_doc(doc_id).map_err(UtilrsError::from)?;
+        Ok(doc)
+    }
+
+    /// iterate over the documents in the order of their ids, all or only the "valid" or
+    /// "invalid" ones. The documents are read in batches while iterating.
+    #[args(validity = "None")]
+    fn docs(slf: PyRef<Self>, validity: Option<&str>) -> PyResult<DocIterator> {
+        Ok(DocIterator {
+            db: slf.into(),
+            validity: get_doc_validity(validity)?,
+            last_doc_id: -1,
+            buffer: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn __iter__(slf: PyRef<Self>) -> PyResult<DocIterator> {
+        Database::docs(slf, None)
+    }
+
+    /// the log entries of a level - "info", "warning", "error" or "all" - optionally only
+    /// the ones of a single document. The entries about a whole run, e.g. the summary of a
+    /// split, have the doc_id None.
+    #[args(level = "\"all\"", doc_id = "None")]
+    fn logs(&mut self, level: &str, doc_id: Option<isize>) -> PyResult<Vec<TableProcessLog>> {
+        let log_level = LogLevel::from_name(level).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "unknown log level '{}', use 'info', 'warning', 'error' or 'all'",
+                level
+            ))
+        })?;
+        let logs = self
+            .db()?
+            .get_log_entries(log_level, doc_id)
+            .map_err(UtilrsError::from)?;
+        Ok(logs)
+    }
+
+    /// the values of all parsed tags with the path as a list of (doc_id, value), optionally
+    /// only the ones of a single document
+    #[args(doc_id = "None")]
+    fn values(&mut self, path: &str, doc_id: Option<isize>) -> PyResult<Vec<(isize, String)>> {
+        let values = self
+            .db()?
+            .get_tag_values(path, doc_id)
+            .map_err(UtilrsError::from)?;
+        Ok(values)
+    }
+
+    /// close the database and release its lock, closing it again does nothing
+    fn close(&mut self) {
+        self.db = None;
+    }
+
+    fn __enter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<PyObject>,
+        _exc_value: Option<PyObject>,
+        _traceback: Option<PyObject>,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+/// iterator over the documents of a Database, see Database.docs
+#[pyclass]
+struct DocIterator {
+    db: Py<Database>,
+    validity: Option<DocValidity>,
+    last_doc_id: isize,
+    buffer: VecDeque<TableDocList>,
+    done: bool,
+}
+
+#[pymethods]
+impl DocIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self, py: Python) -> PyResult<Option<TableDocList>> {
+        if self.buffer.is_empty() && !self.done {
+            let mut db = self.db.borrow_mut(py);
+            let docs = db
+                .db()?
+                .get_docs_after(self.last_doc_id, DOC_BATCH_SIZE, self.validity)
+                .map_err(UtilrsError::from)?;
+            self.done = docs.len() < DOC_BATCH_SIZE;
+            if let Some(last) = docs.last() {
+                self.last_doc_id = last.doc_id;
+            }
+            self.buffer.extend(docs);
+        }
+        Ok(self.buffer.pop_front())
+    }
+}
+
 /// A Python module implemented in Rust. The name of this function must match
 /// the `lib.name` setting in the `Cargo.toml`, else Python will not be able to
 /// import the module.
 #[pymodule]
-fn utilrs(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn utilrs(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(split_file_content, m)?)?;
+    m.add_function(wrap_pyfunction!(split_files_content, m)?)?;
+    m.add_function(wrap_pyfunction!(preview_split_content, m)?)?;
     m.add_function(wrap_pyfunction!(process_file_content, m)?)?;
     m.add_function(wrap_pyfunction!(process_single_doc, m)?)?;
-    // m.add_class();
+    m.add_class::<SplitPreview>()?;
+    m.add_class::<CancelToken>()?;
+    m.add_class::<ChunkHead>()?;
+    m.add_class::<XmlDoc>()?;
+    m.add_class::<MessageDefinition>()?;
+    m.add_class::<Change>()?;
+    m.add_class::<Database>()?;
+    m.add_class::<DocIterator>()?;
+    m.add_class::<TableDocList>()?;
+    m.add_class::<TableProcessLog>()?;
+    m.add("UtilrsError", py.get_type::<exceptions::UtilrsError>())?;
+    m.add("ConfigError", py.get_type::<exceptions::ConfigError>())?;
+    m.add("IoError", py.get_type::<exceptions::IoError>())?;
+    m.add("SplitError", py.get_type::<exceptions::SplitError>())?;
+    m.add("XmlParseError", py.get_type::<exceptions::XmlParseError>())?;
+    m.add("DatabaseError", py.get_type::<exceptions::DatabaseError>())?;
+    m.add("SchemaError", py.get_type::<exceptions::SchemaError>())?;
     Ok(())
 }
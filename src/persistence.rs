@@ -1,14 +1,25 @@
 #![allow(dead_code, unused_imports, unused_variables)]
 
-use rusqlite::{named_params, Connection, Result, ToSql};
-use serde_json;
+use pyo3::prelude::*;
+use rusqlite::{named_params, Connection, OptionalExtension, Result, ToSql};
 use std::collections::HashMap;
 use std::fmt;
 use std::fs;
 use yaml_rust::{yaml, YamlLoader};
 
+use super::error::{UtilrsError, UtilrsResult};
 use super::xmlparser;
 
+// tables the database config file has to define
+const REQUIRED_TABLES: [&str; 6] = [
+    "ProcessLog",
+    "DocList",
+    "SourceFileList",
+    "ParsedXmlStore",
+    "XmlTagsAndValues",
+    "XmlFStarAttributes",
+];
+
 // See also https://stackoverflow.com/questions/40559931/vector-store-mixed-types-of-data-in-rust
 
 #[derive(Debug, Copy, Clone)]
@@ -16,9 +27,10 @@ pub enum IndexGroup {
     ProcessLog = 0,
     DocStore = 1,
     XmlStore = 2,
+    DocHash = 3,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum LogLevel {
     Info = 0,
     Warning = 1,
@@ -26,12 +38,57 @@ pub enum LogLevel {
     All = 4,
 }
 
+impl LogLevel {
+    /// The log level for its python name: "info", "warning", "error" or "all"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "info" => Some(LogLevel::Info),
+            "warning" => Some(LogLevel::Warning),
+            "error" => Some(LogLevel::Error),
+            "all" => Some(LogLevel::All),
+            _ => None,
+        }
+    }
+
+    /// The python name of a log level stored in the ProcessLog table
+    pub fn name_of(log_level: isize) -> &'static str {
+        match log_level {
+            0 => "info",
+            1 => "warning",
+            2 => "error",
+            _ => "unknown",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum DocValidity {
     Valid = 1,
     Invalid = 0,
 }
 
+impl DocValidity {
+    /// The validity for its python name: "valid" or "invalid"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "valid" => Some(DocValidity::Valid),
+            "invalid" => Some(DocValidity::Invalid),
+            _ => None,
+        }
+    }
+}
+
+/// Outcome of parsing a stored document, kept in the ParseStatus field of the DocList
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseStatus {
+    NotParsed = 0,
+    Parsed = 1,
+    /// the document could not be parsed
+    Failed = 2,
+    /// the document was parsed but violates its schema, it is marked invalid
+    SchemaInvalid = 3,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub enum XmlAttribute {
     DocID = 1,
@@ -63,21 +120,213 @@ impl fmt::Display for XmlAttribute {
 pub enum SQLDataType {
     Text(String),
     Integer(isize),
+    Null,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[pyclass(name = "LogEntry")]
 pub struct TableProcessLog {
-    pub doc_id: isize,
+    /// the document the entry is about, None for the entries about a whole run
+    #[pyo3(get)]
+    pub doc_id: Option<isize>,
+    #[pyo3(get)]
     pub log_level: isize,
+    #[pyo3(get)]
     pub log_entry: String,
 }
 
-#[derive(Debug)]
+#[pymethods]
+impl TableProcessLog {
+    /// name of the log level: "info", "warning" or "error"
+    #[getter]
+    fn level(&self) -> &'static str {
+        LogLevel::name_of(self.log_level)
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "LogEntry(doc_id={}, level='{}', log_entry={:?})",
+            self.doc_id
+                .map_or_else(|| "None".to_string(), |doc_id| doc_id.to_string()),
+            self.level(),
+            self.log_entry
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+#[pyclass(name = "Document")]
 pub struct TableDocList {
+    #[pyo3(get)]
     pub doc_id: isize,
+    #[pyo3(get)]
     pub doc_validity: isize,
+    #[pyo3(get)]
     pub doc_text: String,
+    #[pyo3(get)]
     pub doc_invalid_reason: String,
+    #[pyo3(get)]
+    pub archive_entry: String,
+    #[pyo3(get)]
+    pub file_id: isize,
+    #[pyo3(get)]
+    pub file_doc_ordinal: isize,
+    /// byte offsets in the source file, see [`DocSource`]
+    #[pyo3(get)]
+    pub start_offset: isize,
+    #[pyo3(get)]
+    pub end_offset: isize,
+    #[pyo3(get)]
+    pub start_line: isize,
+    #[pyo3(get)]
+    pub doc_hash: String,
+    #[pyo3(get)]
+    pub duplicate_of: isize,
+    /// outcome of the last process run, see [`ParseStatus`]
+    #[pyo3(get)]
+    pub parse_status: isize,
+}
+
+#[pymethods]
+impl TableDocList {
+    /// the document passed the checks of the split run
+    #[getter]
+    fn is_valid(&self) -> bool {
+        self.doc_validity == DocValidity::Valid as isize
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Document(doc_id={}, valid={}, file_id={}, start_line={})",
+            self.doc_id,
+            self.is_valid(),
+            self.file_id,
+            self.start_line
+        )
+    }
+}
+
+// the DocList rows of the documents of a validity which were not parsed yet, with the
+// equality on the parse status sqlite pages through them in DocID order on the
+// IdxDocListParseStatus index
+const UNPARSED_DOCS_CONDITION: &str = "DocValidity=:doc_validity AND ParseStatus=:not_parsed";
+// the same including the documents which could not be parsed
+const UNPARSED_OR_FAILED_DOCS_CONDITION: &str =
+    "DocValidity=:doc_validity AND ParseStatus IN (:not_parsed, :failed)";
+
+// the condition selecting the documents still to parse and the values of its parameters
+fn unparsed_docs_condition(
+    doc_validity: DocValidity,
+    include_failed: bool,
+) -> (&'static str, Vec<(&'static str, isize)>) {
+    let mut values = vec![
+        (":doc_validity", doc_validity as isize),
+        (":not_parsed", ParseStatus::NotParsed as isize),
+    ];
+    if include_failed {
+        values.push((":failed", ParseStatus::Failed as isize));
+        (UNPARSED_OR_FAILED_DOCS_CONDITION, values)
+    } else {
+        (UNPARSED_DOCS_CONDITION, values)
+    }
+}
+
+// the parse status of the documents stored before it was recorded, derived from their parse
+// results and the errors logged for them
+const BACKFILL_PARSE_STATUS: &str = "UPDATE DocList SET ParseStatus = CASE \
+    WHEN DocID IN (SELECT DocID FROM ParsedXmlStore) THEN \
+        CASE DocValidity WHEN :invalid THEN :schema_invalid ELSE :parsed END \
+    WHEN DocID IN (SELECT DocID FROM ProcessLog WHERE LogLevel=:error) THEN :failed \
+    ELSE :not_parsed END";
+
+// the DocList fields in the order expected by TableDocList::from_row
+const DOC_LIST_FIELDS: &str = "DocID, DocValidity, DocText, DocInvalidReason, ArchiveEntry, \
+    FileID, FileDocOrdinal, StartOffset, EndOffset, StartLine, DocHash, DuplicateOf, \
+    ParseStatus";
+
+impl TableDocList {
+    fn from_row(r: &rusqlite::Row) -> Result<Self> {
+        Ok(TableDocList {
+            doc_id: r.get(0)?,
+            doc_validity: r.get(1)?,
+            doc_text: r.get(2)?,
+            doc_invalid_reason: r.get(3)?,
+            // fields added after the table was created are NULL for older rows
+            archive_entry: r.get::<_, Option<String>>(4)?.unwrap_or_default(),
+            file_id: r.get::<_, Option<isize>>(5)?.unwrap_or_default(),
+            file_doc_ordinal: r.get::<_, Option<isize>>(6)?.unwrap_or_default(),
+            start_offset: r.get::<_, Option<isize>>(7)?.unwrap_or_default(),
+            end_offset: r.get::<_, Option<isize>>(8)?.unwrap_or_default(),
+            start_line: r.get::<_, Option<isize>>(9)?.unwrap_or_default(),
+            doc_hash: r.get::<_, Option<String>>(10)?.unwrap_or_default(),
+            duplicate_of: r.get::<_, Option<isize>>(11)?.unwrap_or(-1),
+            parse_status: r.get::<_, Option<isize>>(12)?.unwrap_or_default(),
+        })
+    }
+}
+
+/// Content hash of a document and - if it is flagged as duplicate - the id of the first
+/// document with the same content
+#[derive(Debug, Clone, Default)]
+pub struct DocHash {
+    pub hash: String,
+    pub duplicate_of: Option<isize>,
+}
+
+#[derive(Debug)]
+pub struct TableSourceFileList {
+    pub file_id: isize,
+    pub file_path: String,
+    pub file_checksum: String,
+}
+
+/// Where a document was found in the input
+#[derive(Debug, Clone, Default)]
+pub struct DocSource {
+    /// id of the source file in the SourceFileList table
+    pub file_id: isize,
+    /// name of the file inside an archive, empty for plain input files
+    pub archive_entry: String,
+    /// position of the document within the source file, starting at 0
+    pub file_doc_ordinal: isize,
+    /// byte offsets of the start and the end of the document in the source file, for an
+    /// archive entry in the unpacked entry, also for input transcoded to UTF-8
+    pub start_offset: isize,
+    pub end_offset: isize,
+    /// line number the document starts at in the source (entry), starting at 1
+    pub start_line: isize,
+}
+
+impl fmt::Display for DocSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.archive_entry.is_empty() {
+            write!(f, "entry '{}', ", self.archive_entry)?;
+        }
+        write!(
+            f,
+            "line {}, bytes {}-{}",
+            self.start_line, self.start_offset, self.end_offset
+        )
+    }
+}
+
+impl TableDocList {
+    /// The source of the document as stored in the DocList table
+    pub fn get_source(&self) -> DocSource {
+        DocSource {
+            file_id: self.file_id,
+            archive_entry: self.archive_entry.to_owned(),
+            file_doc_ordinal: self.file_doc_ordinal,
+            start_offset: self.start_offset,
+            end_offset: self.end_offset,
+            start_line: self.start_line,
+        }
+    }
+}
+
+// a parse result which can not be stored as json text
+fn json_error(e: serde_json::Error) -> UtilrsError {
+    rusqlite::Error::ToSqlConversionFailure(Box::new(e)).into()
 }
 
 #[derive(Debug)]
@@ -89,6 +338,12 @@ pub struct TableParsedXmlStore {
     pub source_no_of_tags: isize,
     pub tags: String,
     pub topnode: String,
+    pub namespace: String,
+    pub message_definition: String,
+    pub business_area: String,
+    pub message_functionality: String,
+    pub message_variant: String,
+    pub message_version: String,
 }
 
 #[derive(Debug)]
@@ -99,11 +354,10 @@ struct CachedTable {
 
 impl CachedTable {
     pub fn new(field_names: Vec<String>) -> Self {
-        let cached_table = CachedTable {
+        CachedTable {
             data: Vec::new(),
             fields: field_names,
-        };
-        cached_table
+        }
     }
 }
 
@@ -113,75 +367,93 @@ pub struct DataBase {
     config_file: String,
     conn: Connection,
     cache: HashMap<String, CachedTable>,
+    // the cached validity changes of stored documents: id, validity and reason
+    validity_updates: Vec<(isize, isize, String)>,
+    // the cached parse status changes of stored documents: id and status
+    parse_status_updates: Vec<(isize, isize)>,
     batch_size: usize,
     cache_size: usize,
 }
 
 impl DataBase {
-    pub fn new(db_name: &str, config_file_name: &str) -> Self {
-        let db_conn = Connection::open(db_name).unwrap();
+    pub fn new(db_name: &str, config_file_name: &str) -> UtilrsResult<Self> {
+        let db_conn = Connection::open(db_name)?;
         let mut db = DataBase {
             name: db_name.to_owned(),
             config_file: config_file_name.to_owned(),
             conn: db_conn,
             cache: HashMap::new(),
+            validity_updates: Vec::new(),
+            parse_status_updates: Vec::new(),
             batch_size: 50,
             cache_size: 0,
         };
 
-        db.conn
-            .execute_batch(
-                "PRAGMA journal_mode = OFF;
+        db.conn.execute_batch(
+            "PRAGMA journal_mode = OFF;
                   PRAGMA synchronous = 0;
                   PRAGMA temp_store = MEMORY;
                   PRAGMA cache_size = 1000000;
                   PRAGMA locking_mode = EXCLUSIVE;",
-            )
-            .expect("PRAGMA");
-
-        // db.create_tables().unwrap();
+        )?;
 
-        db.initialize_cache().unwrap();
+        db.initialize_cache()?;
+        db.create_tables()?;
 
-        db
+        Ok(db)
     }
 
     /*
     Database Management
     */
 
+    /// Create all tables defined in the database config file if they do not exist yet,
+    /// fields added to the config since an existing table was created are added to it
     fn create_tables(&mut self) -> Result<()> {
-        // let conn = Connection::open("cats.db")?;
-
-        // println!("Creating user table!");
-        self.conn.execute(
-            "create table if not exists user (
-                 name text,
-                 age integer,
-                 gender text
-             )",
-            [],
-        )?;
-
-        // println!("Creating scores table!");
-        self.conn.execute(
-            "create table if not exists scores (
-                 user_name text,
-                 score integer
-             )",
-            [],
-        )?;
-
-        // println!("Deleting data from both tables!");
-        self.conn.execute("delete from user", [])?;
-        self.conn.execute("delete from scores", [])?;
-
-        println!("Database tables created / cleaned!");
+        let mut parse_status_added = false;
+        for (table, cached_table) in self.cache.iter() {
+            let sql = format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                table,
+                cached_table.fields.join(", ")
+            );
+            self.conn.execute(&sql, [])?;
+
+            // get the fields of the existing table and add the missing ones
+            let sql = format!("PRAGMA table_info({})", table);
+            let mut stmt = self.conn.prepare(&sql)?;
+            let existing_fields = stmt
+                .query_map([], |r| r.get::<_, String>(1))?
+                .collect::<Result<Vec<String>>>()?;
+            for field in cached_table.fields.iter() {
+                if !existing_fields
+                    .iter()
+                    .any(|f| f.eq_ignore_ascii_case(field))
+                {
+                    let sql = format!("ALTER TABLE {} ADD COLUMN {}", table, field);
+                    self.conn.execute(&sql, [])?;
+                    parse_status_added |= table == "DocList" && field == "ParseStatus";
+                }
+            }
+        }
+        if parse_status_added {
+            self.conn.execute(
+                BACKFILL_PARSE_STATUS,
+                named_params! {
+                    ":invalid": DocValidity::Invalid as isize,
+                    ":error": LogLevel::Error as isize,
+                    ":not_parsed": ParseStatus::NotParsed as isize,
+                    ":parsed": ParseStatus::Parsed as isize,
+                    ":failed": ParseStatus::Failed as isize,
+                    ":schema_invalid": ParseStatus::SchemaInvalid as isize,
+                },
+            )?;
+        }
 
         Ok(())
     }
 
-    fn initialize_cache(&mut self) -> Result<()> {
+    fn initialize_cache(&mut self) -> UtilrsResult<()> {
         // db.cache.insert(
         //     String::from("user"),
         //     CachedTable {
@@ -195,17 +467,30 @@ impl DataBase {
         // );
 
         // get the config
-        let data =
-            fs::read_to_string(&self.config_file).expect("Unable to read database config file");
+        let config_error = |message: String| UtilrsError::config(&self.config_file, message);
+        let data = fs::read_to_string(&self.config_file)
+            .map_err(|e| config_error(format!("unable to read the file: {}", e)))?;
 
-        let docs = YamlLoader::load_from_str(&data).unwrap();
+        let docs = YamlLoader::load_from_str(&data)
+            .map_err(|e| config_error(format!("invalid yaml: {}", e)))?;
 
         // multi document support, doc is a yaml::Yaml
-        let doc = &docs[0];
+        let doc = docs
+            .first()
+            .ok_or_else(|| config_error("the file is empty".to_string()))?;
 
         // process the document
         let my_filter = "tables";
-        self.process_config(doc, Some(my_filter), None, 0);
+        self.process_config(doc, Some(my_filter), None)?;
+
+        for table in REQUIRED_TABLES {
+            if !self.cache.contains_key(table) {
+                return Err(UtilrsError::config(
+                    &self.config_file,
+                    format!("table '{}' is not defined", table),
+                ));
+            }
+        }
 
         Ok(())
     }
@@ -215,14 +500,25 @@ impl DataBase {
         doc: &yaml::Yaml,
         filter: Option<&str>,
         current_elem: Option<&str>,
-        level: usize,
-    ) {
+    ) -> UtilrsResult<()> {
         match *doc {
             yaml::Yaml::Array(ref v) => {
                 // this is the list of fields - create the field_list vector
                 let mut field_list: Vec<String> = Vec::new();
                 for elem in v {
-                    field_list.push(elem.as_str().unwrap().to_owned());
+                    match elem.as_str() {
+                        Some(field) => field_list.push(field.to_owned()),
+                        None => {
+                            return Err(UtilrsError::config(
+                                &self.config_file,
+                                format!(
+                                    "field {:?} of table '{}' is not a string",
+                                    elem,
+                                    current_elem.unwrap_or_default()
+                                ),
+                            ))
+                        }
+                    }
                 }
 
                 // create a new cashed table
@@ -230,47 +526,73 @@ impl DataBase {
                     let cached_table = CachedTable::new(field_list);
                     self.cache.insert(table_name.to_owned(), cached_table);
                 } else {
-                    panic!("No table name provided, cached table cannot be added to cache! Field list provided is: {:?}", field_list);
+                    return Err(UtilrsError::config(
+                        &self.config_file,
+                        format!("no table name provided for the fields {:?}", field_list),
+                    ));
                 }
             }
             yaml::Yaml::Hash(ref h) => {
                 for (k, v) in h {
+                    let key = k.as_str().ok_or_else(|| {
+                        UtilrsError::config(
+                            &self.config_file,
+                            format!("key {:?} is not a string", k),
+                        )
+                    })?;
                     // thesese are the tables if filtered and on level 2
                     match filter {
                         Some(f) => {
-                            if f.eq_ignore_ascii_case(k.as_str().unwrap()) {
+                            if f.eq_ignore_ascii_case(key) {
                                 // remove the filter and process the items in the filtered section
-                                self.process_config(v, None, Some(k.as_str().unwrap()), level + 1)
+                                self.process_config(v, None, Some(key))?
                             }
                         }
                         _ => {
                             // process the single table entries
-                            self.process_config(v, filter, Some(k.as_str().unwrap()), level + 1)
+                            self.process_config(v, filter, Some(key))?
                         }
                     };
                 }
             }
             _ => {
-                println!("process_config: Unhandled 'else case' in match *doc")
+                let message = match current_elem {
+                    Some(key) => format!("the value of '{}' is not a list of fields", key),
+                    None => "the file is not a map with a 'tables' section".to_string(),
+                };
+                return Err(UtilrsError::config(&self.config_file, message));
             }
         }
+        Ok(())
     }
 
-    pub fn add_to_cache(&mut self, table_name: &str, record: Vec<SQLDataType>) {
+    pub fn add_to_cache(&mut self, table_name: &str, record: Vec<SQLDataType>) -> UtilrsResult<()> {
         if let Some(chached_table) = self.cache.get_mut(table_name) {
             chached_table.data.push(record);
         } else {
-            panic!("Cached table '{}' not found in cache!", table_name);
+            return Err(UtilrsError::config(
+                &self.config_file,
+                format!("table '{}' is not defined", table_name),
+            ));
         }
+        self.count_cached_write()?;
+        Ok(())
+    }
 
+    // count a cached write, the cache is committed when it holds a batch of writes
+    fn count_cached_write(&mut self) -> Result<()> {
         self.cache_size += 1;
 
         if self.cache_size == self.batch_size {
-            self.commit_writes();
+            self.commit_writes()?;
         }
+        Ok(())
     }
 
-    pub fn commit_writes(&mut self) {
+    /// Write the cached records and then apply the cached validity and parse status changes,
+    /// all in one transaction
+    pub fn commit_writes(&mut self) -> Result<()> {
+        let tx = self.conn.transaction()?;
         // collect all keys to then iterate over the cache
         // collecting all keys avoids the "move issue" of iterators
         // over a mutable reference to the 'cache' HashMap
@@ -297,7 +619,7 @@ impl DataBase {
                     table, field_list, params_string
                 );
                 // println!("SQL: {sql_ins}");
-                let mut stmt = self.conn.prepare_cached(sql_ins.as_str()).unwrap();
+                let mut stmt = tx.prepare_cached(sql_ins.as_str())?;
 
                 // create the param values vector
                 let mut param_values: Vec<rusqlite::types::Value> = Vec::new();
@@ -310,30 +632,103 @@ impl DataBase {
                             SQLDataType::Text(v) => {
                                 param_values.push(v.clone().into());
                             }
+                            SQLDataType::Null => {
+                                param_values.push(rusqlite::types::Value::Null);
+                            }
                         }
                     }
                 }
 
                 // fianlly executed the batch of inserts
-                stmt.execute(rusqlite::params_from_iter(param_values))
-                    .unwrap();
+                stmt.execute(rusqlite::params_from_iter(param_values))?;
 
                 // now clear the cached table's data
-                self.cache.get_mut(table).unwrap().data.clear();
+                if let Some(cached_table) = self.cache.get_mut(table) {
+                    cached_table.data.clear();
+                }
+            }
+        }
+        // the documents whose validity changes may have been inserted above
+        if !self.validity_updates.is_empty() {
+            let mut stmt = tx.prepare_cached(
+                "UPDATE DocList SET DocValidity=:doc_validity, DocInvalidReason=:reason \
+                WHERE DocID=:id",
+            )?;
+            for (doc_id, doc_validity, reason) in self.validity_updates.drain(..) {
+                stmt.execute(named_params! {
+                    ":doc_validity": doc_validity,
+                    ":reason": reason,
+                    ":id": doc_id,
+                })?;
+            }
+        }
+        if !self.parse_status_updates.is_empty() {
+            let mut stmt =
+                tx.prepare_cached("UPDATE DocList SET ParseStatus=:parse_status WHERE DocID=:id")?;
+            for (doc_id, parse_status) in self.parse_status_updates.drain(..) {
+                stmt.execute(named_params! { ":parse_status": parse_status, ":id": doc_id })?;
             }
         }
+        tx.commit()?;
         // reset the cach_size
         self.cache_size = 0;
+        Ok(())
     }
 
     /// Log an event (Success, Warning, Error) into the ProcessLog table of the database.
-    pub fn log_event(&mut self, doc_id: isize, log_entry_text: &str, log_level: LogLevel) {
+    pub fn log_event(
+        &mut self,
+        doc_id: isize,
+        log_entry_text: &str,
+        log_level: LogLevel,
+    ) -> UtilrsResult<()> {
         let record: Vec<SQLDataType> = vec![
             SQLDataType::Integer(doc_id),
             SQLDataType::Integer(log_level as isize),
             SQLDataType::Text(log_entry_text.to_string()),
         ];
-        self.add_to_cache("ProcessLog", record);
+        self.add_to_cache("ProcessLog", record)
+    }
+
+    /// Log an event about a whole run, e.g. a summary, into the ProcessLog table. Its DocID
+    /// is NULL, so it is never taken for an entry about a document.
+    pub fn log_run_event(&mut self, log_entry_text: &str, log_level: LogLevel) -> UtilrsResult<()> {
+        let record: Vec<SQLDataType> = vec![
+            SQLDataType::Null,
+            SQLDataType::Integer(log_level as isize),
+            SQLDataType::Text(log_entry_text.to_string()),
+        ];
+        self.add_to_cache("ProcessLog", record)
+    }
+
+    /// Get the log entries of a level - all entries for `LogLevel::All` - optionally only
+    /// the ones of a single document, in the order they were written
+    pub fn get_log_entries(
+        &mut self,
+        log_level: LogLevel,
+        doc_id: Option<isize>,
+    ) -> Result<Vec<TableProcessLog>> {
+        self.commit_writes()?;
+        let sql = "SELECT DocID, LogLevel, LogEntry FROM ProcessLog \
+            WHERE (:log_level IS NULL OR LogLevel = :log_level) \
+            AND (:doc_id IS NULL OR DocID = :doc_id) ORDER BY rowid";
+        let log_level = match log_level {
+            LogLevel::All => None,
+            level => Some(level as isize),
+        };
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let row_iter = stmt.query_map(
+            named_params! { ":log_level": log_level, ":doc_id": doc_id },
+            |r| {
+                Ok(TableProcessLog {
+                    doc_id: r.get(0)?,
+                    log_level: r.get(1)?,
+                    log_entry: r.get(2)?,
+                })
+            },
+        )?;
+
+        row_iter.collect()
     }
 
     pub fn truncate_process_log(&mut self) -> Result<()> {
@@ -345,98 +740,336 @@ impl DataBase {
     Document Management
     */
 
-    /// Store a raw xml document string to the database
+    /// Store a raw xml document string to the database together with its source and content
+    /// hash, DuplicateOf is -1 for documents not flagged as duplicate. The document is not
+    /// parsed yet.
     pub fn store_doc(
         &mut self,
         doc_id: isize,
         doc_validity: DocValidity,
         doc_text: &str,
         doc_invalid_reason: &str,
-    ) {
+        doc_source: &DocSource,
+        doc_hash: &DocHash,
+    ) -> UtilrsResult<()> {
         let record: Vec<SQLDataType> = vec![
             SQLDataType::Integer(doc_id),
             SQLDataType::Integer(doc_validity as isize),
             SQLDataType::Text(doc_text.to_string()),
             SQLDataType::Text(doc_invalid_reason.to_string()),
+            SQLDataType::Text(doc_source.archive_entry.to_owned()),
+            SQLDataType::Integer(doc_source.file_id),
+            SQLDataType::Integer(doc_source.file_doc_ordinal),
+            SQLDataType::Integer(doc_source.start_offset),
+            SQLDataType::Integer(doc_source.end_offset),
+            SQLDataType::Integer(doc_source.start_line),
+            SQLDataType::Text(doc_hash.hash.to_owned()),
+            SQLDataType::Integer(doc_hash.duplicate_of.unwrap_or(-1)),
+            SQLDataType::Integer(ParseStatus::NotParsed as isize),
         ];
-        self.add_to_cache("DocList", record);
+        self.add_to_cache("DocList", record)
+    }
+
+    /// Change the validity of a stored document, e.g. when it violates its schema. The
+    /// change is cached like the other writes and applied after the cached documents are
+    /// written.
+    pub fn set_doc_validity(
+        &mut self,
+        doc_id: isize,
+        doc_validity: DocValidity,
+        doc_invalid_reason: &str,
+    ) -> Result<()> {
+        self.validity_updates
+            .push((doc_id, doc_validity as isize, doc_invalid_reason.to_owned()));
+        self.count_cached_write()
+    }
+
+    /// Record the outcome of parsing a stored document, the change is cached like the
+    /// validity changes
+    pub fn set_parse_status(&mut self, doc_id: isize, parse_status: ParseStatus) -> Result<()> {
+        self.parse_status_updates
+            .push((doc_id, parse_status as isize));
+        self.count_cached_write()
+    }
+
+    /// Mark all documents as not parsed, e.g. after the parse results were removed. The
+    /// documents marked invalid for violating their schema are valid again until they are
+    /// checked by the next process run.
+    pub fn reset_parse_status(&mut self) -> Result<()> {
+        self.commit_writes()?;
+        self.conn.execute(
+            "UPDATE DocList SET DocValidity=:valid, DocInvalidReason='none' \
+            WHERE ParseStatus=:schema_invalid",
+            named_params! {
+                ":valid": DocValidity::Valid as isize,
+                ":schema_invalid": ParseStatus::SchemaInvalid as isize,
+            },
+        )?;
+        self.conn.execute(
+            "UPDATE DocList SET ParseStatus=:not_parsed WHERE ParseStatus<>:not_parsed",
+            named_params! { ":not_parsed": ParseStatus::NotParsed as isize },
+        )?;
+        Ok(())
     }
 
     /// Get a single document by its document id
-    pub fn get_single_doc(&mut self, doc_id: isize) -> TableDocList {
+    pub fn get_single_doc(&mut self, doc_id: isize) -> Result<TableDocList> {
         // create the SQL statement and prepare it
-        let sql = "SELECT * FROM DocList where DocID=:id";
-        let mut stmt = self.conn.prepare_cached(sql).unwrap();
-        let row: TableDocList = stmt
-            .query_row(named_params! { ":name": doc_id }, |r| {
-                Ok(TableDocList {
-                    doc_id: r.get(0).unwrap(),
-                    doc_validity: r.get(1).unwrap(),
-                    doc_text: r.get(2).unwrap(),
-                    doc_invalid_reason: r.get(3).unwrap(),
-                })
-            })
-            .unwrap();
+        let sql = format!("SELECT {} FROM DocList where DocID=:id", DOC_LIST_FIELDS);
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        stmt.query_row(named_params! { ":id": doc_id }, TableDocList::from_row)
+    }
+
+    /// Get a single document by its document id, None if there is no such document
+    pub fn find_doc(&mut self, doc_id: isize) -> Result<Option<TableDocList>> {
+        self.get_single_doc(doc_id).optional()
+    }
 
-        row
+    /// Get up to `limit` documents with an id greater than `after_doc_id` ordered by their
+    /// id, all documents or the ones of the given validity. Used to page through the
+    /// documents without loading all of them.
+    pub fn get_docs_after(
+        &mut self,
+        after_doc_id: isize,
+        limit: usize,
+        doc_validity: Option<DocValidity>,
+    ) -> Result<Vec<TableDocList>> {
+        let sql = format!(
+            "SELECT {} FROM DocList where DocID > :after \
+            AND (:doc_validity IS NULL OR DocValidity = :doc_validity) \
+            ORDER BY DocID LIMIT :limit",
+            DOC_LIST_FIELDS
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let row_iter = stmt.query_map(
+            named_params! {
+                ":after": after_doc_id,
+                ":doc_validity": doc_validity.map(|v| v as isize),
+                ":limit": limit as isize,
+            },
+            TableDocList::from_row,
+        )?;
+
+        row_iter.collect()
     }
 
-    pub fn get_all_docs(&mut self, doc_validity: DocValidity) -> Vec<TableDocList> {
+    pub fn get_all_docs(&mut self, doc_validity: DocValidity) -> Result<Vec<TableDocList>> {
         // create the SQL statement and prepare it
-        let sql = "SELECT * FROM DocList where DocValidity=:doc_validity";
-        let mut stmt = self.conn.prepare_cached(sql).unwrap();
-        let row_iter = stmt
-            .query_map(
-                named_params! { ":doc_validity": doc_validity as isize },
-                |row| {
-                    Ok(TableDocList {
-                        doc_id: row.get(0).unwrap(),
-                        doc_validity: row.get(1).unwrap(),
-                        doc_text: row.get(2).unwrap(),
-                        doc_invalid_reason: row.get(3).unwrap(),
-                    })
-                },
-            )
-            .unwrap();
+        let sql = format!(
+            "SELECT {} FROM DocList where DocValidity=:doc_validity",
+            DOC_LIST_FIELDS
+        );
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let row_iter = stmt.query_map(
+            named_params! { ":doc_validity": doc_validity as isize },
+            TableDocList::from_row,
+        )?;
 
-        let mut rows: Vec<TableDocList> = Vec::new();
-        for table_row in row_iter {
-            rows.push(table_row.unwrap());
-        }
-        rows
+        row_iter.collect()
+    }
+
+    /// Get all documents of the given validity which were not parsed yet. A document which
+    /// could not be parsed by an earlier run is only returned with `include_failed`.
+    pub fn get_unparsed_docs(
+        &mut self,
+        doc_validity: DocValidity,
+        include_failed: bool,
+    ) -> Result<Vec<TableDocList>> {
+        self.query_unparsed_docs(-1, None, doc_validity, include_failed)
+    }
+
+    /// Get up to `limit` of the unparsed documents (see [`DataBase::get_unparsed_docs`])
+    /// with an id greater than `after_doc_id` ordered by their id, like
+    /// [`DataBase::get_docs_after`]
+    pub fn get_unparsed_docs_after(
+        &mut self,
+        after_doc_id: isize,
+        limit: usize,
+        doc_validity: DocValidity,
+        include_failed: bool,
+    ) -> Result<Vec<TableDocList>> {
+        self.query_unparsed_docs(after_doc_id, Some(limit), doc_validity, include_failed)
+    }
+
+    /// Get the number of unparsed documents, see [`DataBase::get_unparsed_docs`]
+    pub fn get_unparsed_doc_count(
+        &mut self,
+        doc_validity: DocValidity,
+        include_failed: bool,
+    ) -> Result<usize> {
+        self.commit_writes()?;
+        let (condition, values) = unparsed_docs_condition(doc_validity, include_failed);
+        let sql = format!("SELECT COUNT(*) FROM DocList WHERE {}", condition);
+        let params: Vec<(&str, &dyn ToSql)> = values
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn ToSql))
+            .collect();
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        stmt.query_row(&*params, |r| r.get(0))
+    }
+
+    fn query_unparsed_docs(
+        &mut self,
+        after_doc_id: isize,
+        limit: Option<usize>,
+        doc_validity: DocValidity,
+        include_failed: bool,
+    ) -> Result<Vec<TableDocList>> {
+        self.commit_writes()?;
+        let (condition, values) = unparsed_docs_condition(doc_validity, include_failed);
+        let sql = format!(
+            "SELECT {} FROM DocList WHERE {} AND DocID > :after ORDER BY DocID LIMIT :limit",
+            DOC_LIST_FIELDS, condition
+        );
+        // a negative limit is no limit for sqlite
+        let limit = limit.map_or(-1, |limit| limit as isize);
+        let mut params: Vec<(&str, &dyn ToSql)> = values
+            .iter()
+            .map(|(name, value)| (*name, value as &dyn ToSql))
+            .collect();
+        params.push((":after", &after_doc_id));
+        params.push((":limit", &limit));
+        let mut stmt = self.conn.prepare_cached(&sql)?;
+        let row_iter = stmt.query_map(&*params, TableDocList::from_row)?;
+
+        row_iter.collect()
+    }
+
+    /// Get the id the next document stored to the database gets
+    pub fn get_next_doc_id(&mut self) -> Result<isize> {
+        self.commit_writes()?;
+        let sql = "SELECT IFNULL(MAX(DocID) + 1, 0) FROM DocList";
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.query_row([], |r| r.get(0))
     }
 
-    pub fn get_doc_count(&mut self, doc_validity: DocValidity) -> usize {
+    pub fn get_doc_count(&mut self, doc_validity: DocValidity) -> Result<usize> {
         //add condition to handle DocValidity::All
         // create the SQL statement and prepare it
         let sql = "SELECT COUNT(*) AS NoOfDocs FROM DocList where DocValidity=:doc_validity";
-        let mut stmt = self.conn.prepare_cached(sql).unwrap();
-        let doc_count: usize = stmt
-            .query_row(
-                named_params! { ":doc_validity": doc_validity as isize },
-                |r| Ok(r.get(0).unwrap()),
-            )
-            .unwrap();
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.query_row(
+            named_params! { ":doc_validity": doc_validity as isize },
+            |r| r.get(0),
+        )
+    }
 
-        doc_count
+    /// Get the id of the first valid document with the given content hash, the documents
+    /// not written yet included. The stored documents are looked up on the DocHash index.
+    pub fn find_valid_doc_with_hash(&mut self, hash: &str) -> Result<Option<isize>> {
+        let sql = "SELECT MIN(DocID) FROM DocList \
+            WHERE DocHash=:doc_hash AND DocValidity=:doc_validity";
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let stored: Option<isize> = stmt.query_row(
+            named_params! {
+                ":doc_hash": hash,
+                ":doc_validity": DocValidity::Valid as isize,
+            },
+            |r| r.get(0),
+        )?;
+        if stored.is_some() {
+            return Ok(stored);
+        }
+        // the cached documents come after the stored ones, in the order of their ids
+        let doc_list = match self.cache.get("DocList") {
+            Some(doc_list) => doc_list,
+            None => return Ok(None),
+        };
+        let position = |name: &str| doc_list.fields.iter().position(|f| f == name);
+        let (id_pos, validity_pos, hash_pos) = match (
+            position("DocID"),
+            position("DocValidity"),
+            position("DocHash"),
+        ) {
+            (Some(id_pos), Some(validity_pos), Some(hash_pos)) => (id_pos, validity_pos, hash_pos),
+            _ => return Ok(None),
+        };
+        let valid = DocValidity::Valid as isize;
+        let cached = doc_list.data.iter().find_map(|record| {
+            match (&record[id_pos], &record[validity_pos], &record[hash_pos]) {
+                (
+                    SQLDataType::Integer(doc_id),
+                    SQLDataType::Integer(validity),
+                    SQLDataType::Text(h),
+                ) if *validity == valid && h == hash => Some(*doc_id),
+                _ => None,
+            }
+        });
+        Ok(cached)
     }
 
     pub fn truncate_doc_store(&mut self) -> Result<()> {
         self.conn.execute("DELETE FROM DocList", [])?;
+        self.conn.execute("DELETE FROM SourceFileList", [])?;
         Ok(())
     }
 
+    /// Store a source file the documents are read from
+    pub fn store_source_file(
+        &mut self,
+        file_id: isize,
+        file_path: &str,
+        file_checksum: &str,
+    ) -> UtilrsResult<()> {
+        let record: Vec<SQLDataType> = vec![
+            SQLDataType::Integer(file_id),
+            SQLDataType::Text(file_path.to_string()),
+            SQLDataType::Text(file_checksum.to_string()),
+        ];
+        self.add_to_cache("SourceFileList", record)
+    }
+
+    /// Get the id the next source file stored to the database gets
+    pub fn get_next_file_id(&mut self) -> Result<isize> {
+        self.commit_writes()?;
+        let sql = "SELECT IFNULL(MAX(FileID) + 1, 0) FROM SourceFileList";
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.query_row([], |r| r.get(0))
+    }
+
+    pub fn get_all_source_files(&mut self) -> Result<Vec<TableSourceFileList>> {
+        let sql = "SELECT FileID, FilePath, FileChecksum FROM SourceFileList ORDER BY FileID";
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let row_iter = stmt.query_map([], |r| {
+            Ok(TableSourceFileList {
+                file_id: r.get(0)?,
+                file_path: r.get(1)?,
+                file_checksum: r.get(2)?,
+            })
+        })?;
+
+        row_iter.collect()
+    }
+
     /*
     XML Management
      */
-    pub fn get_xml_count(&mut self) -> usize {
+    pub fn get_xml_count(&mut self) -> Result<usize> {
         //add condition to handle DocValidity::All
         // create the SQL statement and prepare it
         let sql = "SELECT COUNT(*) AS NoOfXMLs FROM ParsedXmlStore";
-        let mut stmt = self.conn.prepare_cached(sql).unwrap();
-        let xml_count: usize = stmt.query_row([], |r| Ok(r.get(0).unwrap())).unwrap();
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.query_row([], |r| r.get(0))
+    }
 
-        xml_count
+    /// Get the values of all tags with the given path as (doc id, value), optionally only
+    /// the ones of a single document, in document order
+    pub fn get_tag_values(
+        &mut self,
+        tag_path: &str,
+        doc_id: Option<isize>,
+    ) -> Result<Vec<(isize, String)>> {
+        self.commit_writes()?;
+        let sql = "SELECT DocID, Value FROM XmlTagsAndValues \
+            WHERE Tag = :tag AND (:doc_id IS NULL OR DocID = :doc_id) \
+            ORDER BY DocID, TagID";
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let row_iter = stmt
+            .query_map(named_params! { ":tag": tag_path, ":doc_id": doc_id }, |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?;
+
+        row_iter.collect()
     }
 
     pub fn truncate_xml_store(&mut self) -> Result<()> {
@@ -447,7 +1080,11 @@ impl DataBase {
     }
 
     /// Store the parsed xml document to the database
-    pub fn store_xml_parsed(&mut self, doc_id: isize, parsed_xml: &xmlparser::XmlDoc) {
+    pub fn store_xml_parsed(
+        &mut self,
+        doc_id: isize,
+        parsed_xml: &xmlparser::XmlDoc,
+    ) -> UtilrsResult<()> {
         // unused attributes - needed for backwards compatibility with DB structure
         let soup_no_of_tags: isize = 0;
         let source_no_of_tags: isize = 0;
@@ -456,7 +1093,13 @@ impl DataBase {
 
         // used attributes
         let doc_type = &parsed_xml.doc_type;
-        let tags_and_values = serde_json::to_string(&parsed_xml.xml_parsed).unwrap();
+        let namespace = parsed_xml.default_namespace.to_owned().unwrap_or_default();
+        // documents which are no ISO 20022 message get empty message definition fields
+        let message_definition = parsed_xml.message_definition.as_ref();
+        let message_field = |field: fn(&xmlparser::MessageDefinition) -> &str| {
+            SQLDataType::Text(message_definition.map_or("", field).to_owned())
+        };
+        let tags_and_values = serde_json::to_string(&parsed_xml.xml_parsed).map_err(json_error)?;
 
         // write the xml data to the cache
         let record: Vec<SQLDataType> = vec![
@@ -467,13 +1110,21 @@ impl DataBase {
             SQLDataType::Integer(source_no_of_tags),
             SQLDataType::Text(tags),
             SQLDataType::Text(top_node),
+            SQLDataType::Text(namespace),
+            message_field(|message| &message.identifier),
+            message_field(|message| &message.business_area),
+            message_field(|message| &message.functionality),
+            message_field(|message| &message.variant),
+            message_field(|message| &message.version),
         ];
-        self.add_to_cache("ParsedXmlStore", record);
+        self.add_to_cache("ParsedXmlStore", record)?;
 
         // write the forward star data to the cache
-        let first_link = serde_json::to_string(parsed_xml.fstar.get_first_links()).unwrap();
-        let to_node = serde_json::to_string(parsed_xml.fstar.get_to_nodes()).unwrap();
-        let node_caption = serde_json::to_string(parsed_xml.fstar.get_node_captions()).unwrap();
+        let first_link =
+            serde_json::to_string(parsed_xml.fstar.get_first_links()).map_err(json_error)?;
+        let to_node = serde_json::to_string(parsed_xml.fstar.get_to_nodes()).map_err(json_error)?;
+        let node_caption =
+            serde_json::to_string(parsed_xml.fstar.get_node_captions()).map_err(json_error)?;
 
         let record: Vec<SQLDataType> = vec![
             SQLDataType::Integer(doc_id),
@@ -484,7 +1135,7 @@ impl DataBase {
             SQLDataType::Text(to_node),
             SQLDataType::Text(node_caption),
         ];
-        self.add_to_cache("XmlFStarAttributes", record);
+        self.add_to_cache("XmlFStarAttributes", record)?;
 
         // write the tags and value data to the cache
         let mut tag_idx: isize = 0;
@@ -511,9 +1162,10 @@ impl DataBase {
                     SQLDataType::Integer(idx as isize),
                     SQLDataType::Text(tag_value.to_owned()),
                 ];
-                self.add_to_cache("XmlTagsAndValues", record);
+                self.add_to_cache("XmlTagsAndValues", record)?;
             }
         }
+        Ok(())
     }
 
     /*
@@ -544,6 +1196,24 @@ impl DataBase {
                     "CREATE INDEX IF NOT EXISTS IdxDocListDocValidity ON DocList (DocValidity)",
                     [],
                 )?;
+
+                self.conn.execute(
+                    "CREATE INDEX IF NOT EXISTS IdxDocListFileID ON DocList (FileID)",
+                    [],
+                )?;
+
+                self.conn.execute(
+                    "CREATE INDEX IF NOT EXISTS IdxDocListParseStatus \
+                    ON DocList (DocValidity, ParseStatus, DocID)",
+                    [],
+                )?;
+            }
+            IndexGroup::DocHash => {
+                // the DocList content hash index, kept while splitting to look up duplicates
+                self.conn.execute(
+                    "CREATE INDEX IF NOT EXISTS IdxDocListDocHash ON DocList (DocHash)",
+                    [],
+                )?;
             }
             IndexGroup::XmlStore => {
                 // the ParsedXmlStore indices
@@ -557,6 +1227,11 @@ impl DataBase {
                     [],
                 )?;
 
+                self.conn.execute(
+                    "CREATE INDEX IF NOT EXISTS IdxParsedXmlStoreMessageDefinition ON ParsedXmlStore (MessageDefinition)",
+                    [],
+                )?;
+
                 // the XmlTagsAndValues indices
                 self.conn.execute(
                     "CREATE INDEX IF NOT EXISTS IdxXmlTagsAndValuesDocID ON XmlTagsAndValues (DocID)",
@@ -601,6 +1276,17 @@ impl DataBase {
 
                 self.conn
                     .execute("DROP INDEX IF EXISTS IdxDocListDocValidity", [])?;
+
+                self.conn
+                    .execute("DROP INDEX IF EXISTS IdxDocListFileID", [])?;
+
+                self.conn
+                    .execute("DROP INDEX IF EXISTS IdxDocListParseStatus", [])?;
+            }
+            IndexGroup::DocHash => {
+                // the DocList content hash index
+                self.conn
+                    .execute("DROP INDEX IF EXISTS IdxDocListDocHash", [])?;
             }
             IndexGroup::XmlStore => {
                 // the ParsedXmlStore indices
@@ -610,6 +1296,11 @@ impl DataBase {
                 self.conn
                     .execute("DROP INDEX IF EXISTS IdxParsedXmlStoreType", [])?;
 
+                self.conn.execute(
+                    "DROP INDEX IF EXISTS IdxParsedXmlStoreMessageDefinition",
+                    [],
+                )?;
+
                 // the XmlTagsAndValues indices
                 self.conn
                     .execute("DROP INDEX IF EXISTS IdxXmlTagsAndValuesDocID", [])?;
@@ -636,36 +1327,178 @@ mod tests {
 
     #[test]
     fn create_db() {
-        let mut db = DataBase::new("test.db", "C:/LocalData/Rust/yaml/tabledef.yaml");
+        let db_name = std::env::temp_dir().join("utilrs_create_db.db");
+        let mut db = DataBase::new(db_name.to_str().unwrap(), "tabledef.yaml").unwrap();
+        db.truncate_process_log().unwrap();
         let record: Vec<SQLDataType> = vec![
-            SQLDataType::Text("John Doe".to_string()),
-            SQLDataType::Integer(35),
-            SQLDataType::Text("male".to_string()),
+            SQLDataType::Integer(1),
+            SQLDataType::Integer(LogLevel::Info as isize),
+            SQLDataType::Text("first entry".to_string()),
         ];
-        db.add_to_cache("user", record);
+        db.add_to_cache("ProcessLog", record).unwrap();
 
         let record_1: Vec<SQLDataType> = vec![
-            SQLDataType::Text("Peter Parker".to_string()),
-            SQLDataType::Integer(20),
-            SQLDataType::Text("male".to_string()),
-        ];
-        db.add_to_cache("user", record_1);
-
-        let record_2: Vec<SQLDataType> = vec![
-            SQLDataType::Text("Lois Lane".to_string()),
-            SQLDataType::Integer(30),
-            SQLDataType::Text("female".to_string()),
+            SQLDataType::Integer(2),
+            SQLDataType::Integer(LogLevel::Warning as isize),
+            SQLDataType::Text("second entry".to_string()),
         ];
-        db.add_to_cache("user", record_2);
-
-        let record_scores: Vec<SQLDataType> = vec![
-            SQLDataType::Text("Peter Parker".to_string()),
-            SQLDataType::Integer(8),
+        db.add_to_cache("ProcessLog", record_1).unwrap();
+
+        let record_doc: Vec<SQLDataType> = vec![
+            SQLDataType::Integer(1),
+            SQLDataType::Integer(DocValidity::Valid as isize),
+            SQLDataType::Text("<Document/>".to_string()),
+            SQLDataType::Text("none".to_string()),
+            SQLDataType::Text("".to_string()),
+            SQLDataType::Integer(0),
+            SQLDataType::Integer(0),
+            SQLDataType::Integer(0),
+            SQLDataType::Integer(11),
+            SQLDataType::Integer(1),
+            SQLDataType::Text("".to_string()),
+            SQLDataType::Integer(-1),
+            SQLDataType::Integer(ParseStatus::NotParsed as isize),
         ];
-        db.add_to_cache("scores", record_scores);
+        db.truncate_doc_store().unwrap();
+        db.add_to_cache("DocList", record_doc).unwrap();
 
-        db.commit_writes();
+        db.commit_writes().unwrap();
 
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 1);
         println!("-- Records inserted into database --");
     }
+
+    #[test]
+    fn validity_updates_are_batched() {
+        let db_name = std::env::temp_dir().join("utilrs_validity_updates_are_batched.db");
+        let mut db = DataBase::new(db_name.to_str().unwrap(), "tabledef.yaml").unwrap();
+        db.truncate_doc_store().unwrap();
+        let hash = DocHash {
+            hash: String::new(),
+            duplicate_of: None,
+        };
+        for doc_id in 1..=2 {
+            db.store_doc(
+                doc_id,
+                DocValidity::Valid,
+                "<A/>",
+                "",
+                &DocSource::default(),
+                &hash,
+            )
+            .unwrap();
+        }
+        // the update of a document which is not written yet is applied after it is written
+        db.set_doc_validity(2, DocValidity::Invalid, "schema violation")
+            .unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 0);
+        db.commit_writes().unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Valid).unwrap(), 1);
+        assert_eq!(
+            db.get_single_doc(2).unwrap().doc_invalid_reason,
+            "schema violation"
+        );
+
+        // a full batch is committed without a commit by the caller
+        for _ in 0..db.batch_size - 1 {
+            db.log_event(1, "entry", LogLevel::Info).unwrap();
+        }
+        db.set_doc_validity(1, DocValidity::Invalid, "schema violation")
+            .unwrap();
+        assert_eq!(db.get_doc_count(DocValidity::Invalid).unwrap(), 2);
+    }
+
+    #[test]
+    fn parse_status_of_older_databases_is_derived() {
+        let db_name = std::env::temp_dir().join("utilrs_parse_status_backfill.db");
+        let _ = std::fs::remove_file(&db_name);
+        let conn = Connection::open(&db_name).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE DocList (DocID, DocValidity, DocText, DocInvalidReason);
+            CREATE TABLE ParsedXmlStore (DocID);
+            CREATE TABLE ProcessLog (DocID, LogLevel, LogEntry);
+            INSERT INTO DocList VALUES (1, 1, '<A/>', 'none'), (2, 1, '<A', 'none'),
+                (3, 1, '<B/>', 'none'), (4, 0, '<C/>', 'schema violation');
+            INSERT INTO ParsedXmlStore VALUES (1), (4);
+            INSERT INTO ProcessLog VALUES (2, 2, 'parse error'), (4, 2, 'schema violation');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let mut db = DataBase::new(db_name.to_str().unwrap(), "tabledef.yaml").unwrap();
+        let parse_status: Vec<isize> = (1..=4)
+            .map(|doc_id| db.get_single_doc(doc_id).unwrap().parse_status)
+            .collect();
+        assert_eq!(
+            parse_status,
+            vec![
+                ParseStatus::Parsed as isize,
+                ParseStatus::Failed as isize,
+                ParseStatus::NotParsed as isize,
+                ParseStatus::SchemaInvalid as isize,
+            ]
+        );
+    }
+
+    #[test]
+    fn unparsed_docs_are_paged_on_the_parse_status_index() {
+        let db_name = std::env::temp_dir().join("utilrs_unparsed_docs_paging.db");
+        let mut db = DataBase::new(db_name.to_str().unwrap(), "tabledef.yaml").unwrap();
+        db.create_indices(IndexGroup::DocStore).unwrap();
+        // each page is a range of the index in DocID order, no page sorts or scans the rest
+        let sql = format!(
+            "EXPLAIN QUERY PLAN SELECT {} FROM DocList WHERE {} \
+            AND DocID > :after ORDER BY DocID LIMIT :limit",
+            DOC_LIST_FIELDS, UNPARSED_DOCS_CONDITION
+        );
+        let mut stmt = db.conn.prepare(&sql).unwrap();
+        let plan: Vec<String> = stmt
+            .query_map(
+                named_params! {
+                    ":doc_validity": 1, ":not_parsed": 0, ":after": -1, ":limit": 256
+                },
+                |r| r.get(3),
+            )
+            .unwrap()
+            .map(|detail| detail.unwrap())
+            .collect();
+        assert_eq!(
+            plan,
+            vec![
+                "SEARCH DocList USING INDEX IdxDocListParseStatus \
+                (DocValidity=? AND ParseStatus=? AND DocID>?)"
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_config_is_a_config_error() {
+        let db_name = std::env::temp_dir().join("utilrs_invalid_config.db");
+        let db_name = db_name.to_str().unwrap();
+        let config = std::env::temp_dir().join("utilrs_invalid_config.yaml");
+        let config = config.to_str().unwrap();
+
+        match DataBase::new(db_name, "no_such_config.yaml") {
+            Err(UtilrsError::Config { file, .. }) => assert_eq!(file, "no_such_config.yaml"),
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        std::fs::write(
+            config,
+            "tables:\n  ProcessLog: [DocID, LogLevel, LogEntry]\n",
+        )
+        .unwrap();
+        match DataBase::new(db_name, config) {
+            Err(UtilrsError::Config { message, .. }) => {
+                assert_eq!(message, "table 'DocList' is not defined")
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+        std::fs::write(config, "tables:\n  ProcessLog: DocID\n").unwrap();
+        match DataBase::new(db_name, config) {
+            Err(UtilrsError::Config { message, .. }) => {
+                assert_eq!(message, "the value of 'ProcessLog' is not a list of fields")
+            }
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
 }
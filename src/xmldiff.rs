@@ -0,0 +1,446 @@
+use pyo3::prelude::*;
+
+use super::xmlwriter::{WriteOptions, XmlWriter};
+use super::xpath::QueryTree;
+use super::XmlDoc;
+
+/// The kind of a difference between two documents
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+impl ChangeKind {
+    /// The python name of the change kind: "added", "removed" or "changed"
+    pub fn name(self) -> &'static str {
+        match self {
+            ChangeKind::Added => "added",
+            ChangeKind::Removed => "removed",
+            ChangeKind::Changed => "changed",
+        }
+    }
+}
+
+/// Options of the document diff
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// match repeated elements by their content instead of their position
+    pub ignore_order: bool,
+    /// compare the values without leading and trailing whitespace and with any other run
+    /// of whitespace as a single space
+    pub ignore_whitespace: bool,
+}
+
+/// A difference between two documents.
+///
+/// The path names the elements from the root, elements which occur more than once below
+/// their parent in one of the documents with their 1-based repetition index, e.g.
+/// `/Document/CstmrDrctDbtInitn/PmtInf/DrctDbtTxInf[2]/InstdAmt/@Ccy`. The index is the
+/// one of the old document, for added elements the one of the new document. Added and
+/// removed elements carry their content as xml.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[pyclass]
+pub struct Change {
+    pub kind: ChangeKind,
+    #[pyo3(get)]
+    pub path: String,
+    #[pyo3(get)]
+    pub old_value: Option<String>,
+    #[pyo3(get)]
+    pub new_value: Option<String>,
+}
+
+#[pymethods]
+impl Change {
+    /// "added", "removed" or "changed"
+    #[getter]
+    fn kind(&self) -> &'static str {
+        self.kind.name()
+    }
+
+    fn __repr__(&self) -> String {
+        let repr = |value: &Option<String>| match value {
+            Some(value) => format!("{:?}", value),
+            None => "None".to_string(),
+        };
+        format!(
+            "Change(kind='{}', path='{}', old_value={}, new_value={})",
+            self.kind.name(),
+            self.path,
+            repr(&self.old_value),
+            repr(&self.new_value)
+        )
+    }
+}
+
+// one of the compared documents
+struct Side<'a> {
+    doc: &'a XmlDoc,
+    tree: QueryTree<'a>,
+    // writes the added or removed elements, built for the first of them
+    writer: Option<XmlWriter<'a>>,
+}
+
+impl<'a> Side<'a> {
+    fn new(doc: &'a XmlDoc) -> Self {
+        Side {
+            doc,
+            tree: QueryTree::new(doc),
+            writer: None,
+        }
+    }
+
+    // an element and its content as compact xml
+    fn element_to_xml(&mut self, tag_id: usize) -> String {
+        let doc = self.doc;
+        self.writer
+            .get_or_insert_with(|| XmlWriter::new(doc, &WriteOptions::default()))
+            .element_to_xml(tag_id)
+    }
+
+    // the child elements of an element in document order
+    fn elements(&self, tag_id: usize) -> Vec<usize> {
+        self.tree
+            .content(tag_id)
+            .into_iter()
+            .filter(|&id| !self.tree.is_text(id))
+            .collect()
+    }
+
+    // the value of an element followed by its text values, None for a node
+    fn text(&self, tag_id: usize) -> Option<String> {
+        let mut text: Option<String> = match self.tree.value(tag_id) {
+            "__node__" => None,
+            value => Some(value.to_owned()),
+        };
+        for id in self.tree.content(tag_id) {
+            if self.tree.is_text(id) {
+                text.get_or_insert_with(String::new)
+                    .push_str(self.tree.value(id));
+            }
+        }
+        text
+    }
+
+    fn attributes(&self, tag_id: usize) -> Vec<(&'a str, &'a str)> {
+        self.tree
+            .attributes(tag_id, None)
+            .into_iter()
+            .map(|id| (self.tree.name(id), self.tree.value(id)))
+            .collect()
+    }
+}
+
+// compares the elements of two documents from the root down
+struct Differ<'a> {
+    old: Side<'a>,
+    new: Side<'a>,
+    options: &'a DiffOptions,
+    changes: Vec<Change>,
+}
+
+impl<'a> Differ<'a> {
+    fn normalized(&self, value: &str) -> String {
+        match self.options.ignore_whitespace {
+            true => value.split_whitespace().collect::<Vec<&str>>().join(" "),
+            false => value.to_owned(),
+        }
+    }
+
+    fn push(&mut self, kind: ChangeKind, path: String, old: Option<String>, new: Option<String>) {
+        self.changes.push(Change {
+            kind,
+            path,
+            old_value: old,
+            new_value: new,
+        });
+    }
+
+    // the content of an element in a form which is equal for equal elements, the child
+    // elements are sorted if their order is ignored
+    fn signature(&self, side: &Side, tag_id: usize) -> String {
+        let mut attributes: Vec<String> = side
+            .attributes(tag_id)
+            .into_iter()
+            .map(|(name, value)| format!("{}={:?}", name, self.normalized(value)))
+            .collect();
+        attributes.sort();
+        let text = side.text(tag_id).map(|text| self.normalized(&text));
+        let mut children: Vec<String> = side
+            .elements(tag_id)
+            .into_iter()
+            .map(|id| self.signature(side, id))
+            .collect();
+        if self.options.ignore_order {
+            children.sort();
+        }
+        format!(
+            "<{} {}>{:?}{}</>",
+            side.tree.name(tag_id),
+            attributes.join(" "),
+            text,
+            children.concat()
+        )
+    }
+
+    // pairs of the old and new elements which are compared, an element without partner was
+    // removed or added
+    fn pair_elements(&self, old: &[usize], new: &[usize]) -> Vec<(Option<usize>, Option<usize>)> {
+        let mut pairs = Vec::new();
+        let mut old_left: Vec<usize> = (0..old.len()).collect();
+        let mut new_left: Vec<usize> = (0..new.len()).collect();
+        if self.options.ignore_order {
+            // equal elements first, wherever they are
+            let new_signatures: Vec<String> = new
+                .iter()
+                .map(|&id| self.signature(&self.new, id))
+                .collect();
+            old_left.retain(|&i| {
+                let signature = self.signature(&self.old, old[i]);
+                match new_left
+                    .iter()
+                    .position(|&j| new_signatures[j] == signature)
+                {
+                    Some(pos) => {
+                        pairs.push((Some(i), Some(new_left.remove(pos))));
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+        for pos in 0..old_left.len().max(new_left.len()) {
+            pairs.push((old_left.get(pos).copied(), new_left.get(pos).copied()));
+        }
+        pairs
+    }
+
+    // the values are compared normalized and reported as they are
+    fn compare_values(&mut self, path: String, old: Option<&str>, new: Option<&str>) {
+        let kind = match (old, new) {
+            (Some(_), None) => ChangeKind::Removed,
+            (None, Some(_)) => ChangeKind::Added,
+            (Some(old), Some(new)) if self.normalized(old) != self.normalized(new) => {
+                ChangeKind::Changed
+            }
+            _ => return,
+        };
+        self.push(kind, path, old.map(str::to_owned), new.map(str::to_owned));
+    }
+
+    fn compare_elements(&mut self, old_id: usize, new_id: usize, path: &str) {
+        let old_text = self.old.text(old_id);
+        let new_text = self.new.text(new_id);
+        self.compare_values(path.to_owned(), old_text.as_deref(), new_text.as_deref());
+
+        let old_attributes = self.old.attributes(old_id);
+        let new_attributes = self.new.attributes(new_id);
+        for (name, value) in old_attributes.iter() {
+            let new_value = new_attributes
+                .iter()
+                .find(|(new_name, _)| new_name == name)
+                .map(|(_, value)| *value);
+            self.compare_values(format!("{}/@{}", path, name), Some(value), new_value);
+        }
+        for (name, value) in new_attributes.iter() {
+            if !old_attributes.iter().any(|(old_name, _)| old_name == name) {
+                self.compare_values(format!("{}/@{}", path, name), None, Some(value));
+            }
+        }
+
+        // the child elements are compared by name in the order the names first occur
+        let old_children = self.old.elements(old_id);
+        let new_children = self.new.elements(new_id);
+        let mut names: Vec<&str> = Vec::new();
+        for &id in old_children.iter() {
+            let name = self.old.tree.name(id);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        for &id in new_children.iter() {
+            let name = self.new.tree.name(id);
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        for name in names {
+            let old: Vec<usize> = old_children
+                .iter()
+                .copied()
+                .filter(|&id| self.old.tree.name(id) == name)
+                .collect();
+            let new: Vec<usize> = new_children
+                .iter()
+                .copied()
+                .filter(|&id| self.new.tree.name(id) == name)
+                .collect();
+            let repeated = old.len() > 1 || new.len() > 1;
+            let child_path = |pos: usize| match repeated {
+                true => format!("{}/{}[{}]", path, name, pos + 1),
+                false => format!("{}/{}", path, name),
+            };
+            for pair in self.pair_elements(&old, &new) {
+                match pair {
+                    (Some(i), Some(j)) => self.compare_elements(old[i], new[j], &child_path(i)),
+                    (Some(i), None) => {
+                        let xml = self.old.element_to_xml(old[i]);
+                        self.push(ChangeKind::Removed, child_path(i), Some(xml), None);
+                    }
+                    (None, Some(j)) => {
+                        let xml = self.new.element_to_xml(new[j]);
+                        self.push(ChangeKind::Added, child_path(j), None, Some(xml));
+                    }
+                    (None, None) => {}
+                }
+            }
+        }
+    }
+}
+
+impl XmlDoc {
+    /// The differences of another document to this one: added, removed and changed element
+    /// values and attributes. Attributes are compared as such with the attribute usage
+    /// `AddSeparateTag`, with the other usages they are part of the element names or values.
+    pub fn diff(&self, other: &XmlDoc, options: &DiffOptions) -> Vec<Change> {
+        let mut differ = Differ {
+            old: Side::new(self),
+            new: Side::new(other),
+            options,
+            changes: Vec::new(),
+        };
+        match (differ.old.tree.root(), differ.new.tree.root()) {
+            (Some(old_root), Some(new_root))
+                if differ.old.tree.name(old_root) == differ.new.tree.name(new_root) =>
+            {
+                let path = format!("/{}", differ.old.tree.name(old_root));
+                differ.compare_elements(old_root, new_root, &path);
+            }
+            (old_root, new_root) => {
+                if let Some(root) = old_root {
+                    let path = format!("/{}", differ.old.tree.name(root));
+                    let xml = differ.old.element_to_xml(root);
+                    differ.push(ChangeKind::Removed, path, Some(xml), None);
+                }
+                if let Some(root) = new_root {
+                    let path = format!("/{}", differ.new.tree.name(root));
+                    let xml = differ.new.element_to_xml(root);
+                    differ.push(ChangeKind::Added, path, None, Some(xml));
+                }
+            }
+        }
+        differ.changes
+    }
+}
+
+/// A unified report of the changes between two documents: a `---` and `+++` line naming the
+/// old and the new document and per change a `@@ path @@` line followed by the old value
+/// as `-` and the new value as `+` lines.
+pub fn diff_report(old_name: &str, new_name: &str, changes: &[Change]) -> String {
+    let mut report = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for change in changes {
+        report.push_str(&format!("@@ {} @@\n", change.path));
+        for (marker, value) in [('-', &change.old_value), ('+', &change.new_value)] {
+            if let Some(value) = value {
+                for line in value.lines() {
+                    report.push_str(&format!("{}{}\n", marker, line));
+                }
+                if value.is_empty() {
+                    report.push_str(&format!("{}\n", marker));
+                }
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ParseOptions;
+    use super::*;
+
+    fn diff(old: &str, new: &str, options: &DiffOptions) -> Vec<(&'static str, String)> {
+        let old = XmlDoc::with_options(1, old, &ParseOptions::default()).unwrap();
+        let new = XmlDoc::with_options(2, new, &ParseOptions::default()).unwrap();
+        old.diff(&new, options)
+            .into_iter()
+            .map(|change| (change.kind.name(), change.path))
+            .collect()
+    }
+
+    #[test]
+    fn diff_values_attributes_and_elements() {
+        let old = r#"<Doc><Hdr><Id>1</Id></Hdr><Tx><Amt Ccy="EUR">10</Amt></Tx><Tx><Amt Ccy="EUR">20</Amt><Nm>A</Nm></Tx></Doc>"#;
+        let new = r#"<Doc><Hdr><Id>1</Id><Dt>x</Dt></Hdr><Tx><Amt Ccy="USD" Dt="1">10</Amt></Tx><Tx><Amt Ccy="EUR">25</Amt></Tx></Doc>"#;
+        assert!(diff(old, old, &DiffOptions::default()).is_empty());
+
+        let old_doc = XmlDoc::with_options(1, old, &ParseOptions::default()).unwrap();
+        let new_doc = XmlDoc::with_options(2, new, &ParseOptions::default()).unwrap();
+        let changes = old_doc.diff(&new_doc, &DiffOptions::default());
+        let summary: Vec<(&str, &str)> = changes
+            .iter()
+            .map(|change| (change.kind.name(), change.path.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("added", "/Doc/Hdr/Dt"),
+                ("changed", "/Doc/Tx[1]/Amt/@Ccy"),
+                ("added", "/Doc/Tx[1]/Amt/@Dt"),
+                ("changed", "/Doc/Tx[2]/Amt"),
+                ("removed", "/Doc/Tx[2]/Nm"),
+            ]
+        );
+        assert_eq!(changes[0].new_value.as_deref(), Some("<Dt>x</Dt>"));
+        assert_eq!(changes[1].old_value.as_deref(), Some("EUR"));
+        assert_eq!(changes[1].new_value.as_deref(), Some("USD"));
+        assert_eq!(changes[4].old_value.as_deref(), Some("<Nm>A</Nm>"));
+
+        let report = diff_report("doc 1", "doc 2", &changes[..2]);
+        assert_eq!(
+            report,
+            "--- doc 1\n+++ doc 2\n@@ /Doc/Hdr/Dt @@\n+<Dt>x</Dt>\n\
+            @@ /Doc/Tx[1]/Amt/@Ccy @@\n-EUR\n+USD\n"
+        );
+    }
+
+    #[test]
+    fn diff_ignoring_order_and_whitespace() {
+        let old = "<Doc><Tx><Id>1</Id></Tx><Tx><Id>2</Id></Tx><Nm>A  B</Nm></Doc>";
+        let new = "<Doc><Tx><Id>3</Id></Tx><Tx><Id>1</Id></Tx><Nm> A B</Nm></Doc>";
+        assert_eq!(
+            diff(old, new, &DiffOptions::default()),
+            vec![
+                ("changed", "/Doc/Tx[1]/Id".to_string()),
+                ("changed", "/Doc/Tx[2]/Id".to_string()),
+                ("changed", "/Doc/Nm".to_string()),
+            ]
+        );
+        let options = DiffOptions {
+            ignore_order: true,
+            ignore_whitespace: true,
+        };
+        assert_eq!(
+            diff(old, new, &options),
+            vec![("changed", "/Doc/Tx[2]/Id".to_string())]
+        );
+
+        // the values are compared without the whitespace but reported as they are
+        let old =
+            XmlDoc::with_options(1, "<Doc><Nm>A  B</Nm></Doc>", &ParseOptions::default()).unwrap();
+        let new =
+            XmlDoc::with_options(2, "<Doc><Nm>A   C</Nm></Doc>", &ParseOptions::default()).unwrap();
+        let changes = old.diff(&new, &options);
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].old_value.as_deref(), Some("A  B"));
+        assert_eq!(changes[0].new_value.as_deref(), Some("A   C"));
+
+        // a different root is a removed and an added document
+        assert_eq!(
+            diff("<A/>", "<B/>", &options),
+            vec![("removed", "/A".to_string()), ("added", "/B".to_string())]
+        );
+    }
+}
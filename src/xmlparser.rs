@@ -1,22 +1,39 @@
 #![allow(dead_code, unused_imports, unused_variables)]
 use fxhash::FxBuildHasher;
 use indexmap::IndexMap;
+use pyo3::exceptions::{PyKeyError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
 use quick_xml::events::Event;
 use quick_xml::Reader;
+use std::collections::HashMap;
 use std::fmt;
 
+use super::error::{UtilrsError, UtilrsResult};
+
 #[path = "forwardstar.rs"]
 mod forwardstar;
 use forwardstar::*;
 
+#[path = "xpath.rs"]
+mod xpath;
+pub use xpath::*;
+
+#[path = "xmlwriter.rs"]
+mod xmlwriter;
+pub use xmlwriter::*;
+
+#[path = "xmldiff.rs"]
+mod xmldiff;
+pub use xmldiff::*;
+
 #[derive(Debug, Copy, Clone)]
 pub enum TagType {
     Node = 0,
     DataTag = 1,
     Unknown = 99,
 }
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum AttributeUsage {
     AddToTagName = 1,
     AddToTagValue = 2,
@@ -24,6 +41,178 @@ pub enum AttributeUsage {
     Ignore = 4,
 }
 
+impl AttributeUsage {
+    /// The attribute usage for its python name: "tag_name", "tag_value", "separate_tag"
+    /// or "ignore"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tag_name" => Some(AttributeUsage::AddToTagName),
+            "tag_value" => Some(AttributeUsage::AddToTagValue),
+            "separate_tag" => Some(AttributeUsage::AddSeparateTag),
+            "ignore" => Some(AttributeUsage::Ignore),
+            _ => None,
+        }
+    }
+}
+
+/// How the names of elements and attributes in a namespace are used in the tag paths
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NamespaceMode {
+    /// the names as written in the document, e.g. `ns2:Document`, namespace declarations
+    /// are ordinary attributes
+    Prefixed,
+    /// the local names without prefix, e.g. `Document`
+    LocalName,
+    /// the local names qualified by the namespace uri in Clark notation, e.g.
+    /// `{urn:iso:std:iso:20022:tech:xsd:pain.008.001.02}Document`
+    Clark,
+}
+
+impl NamespaceMode {
+    /// The namespace mode for its python name: "prefixed", "local" or "clark"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "prefixed" => Some(NamespaceMode::Prefixed),
+            "local" => Some(NamespaceMode::LocalName),
+            "clark" => Some(NamespaceMode::Clark),
+            _ => None,
+        }
+    }
+
+    // the name of an element or attribute in the tag paths, `namespace` is the resolved
+    // namespace of the name
+    fn tag_name(self, qualified_name: &[u8], namespace: Option<&[u8]>) -> String {
+        let local_name = match qualified_name.iter().position(|&b| b == b':') {
+            Some(pos) => &qualified_name[pos + 1..],
+            None => qualified_name,
+        };
+        match (self, namespace) {
+            (NamespaceMode::Prefixed, _) => String::from_utf8_lossy(qualified_name).to_string(),
+            (NamespaceMode::Clark, Some(namespace)) => format!(
+                "{{{}}}{}",
+                String::from_utf8_lossy(namespace),
+                String::from_utf8_lossy(local_name)
+            ),
+            _ => String::from_utf8_lossy(local_name).to_string(),
+        }
+    }
+}
+
+/// How the text of an element with several text values is stored, e.g. of an element with
+/// text before and after a child element or text split by a comment
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MixedContent {
+    /// the text values are concatenated into a single value
+    Concatenate,
+    /// every text value is a tag of its own in document order
+    Separate,
+}
+
+impl MixedContent {
+    /// The mixed content policy for its python name: "concatenate" or "separate"
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "concatenate" => Some(MixedContent::Concatenate),
+            "separate" => Some(MixedContent::Separate),
+            _ => None,
+        }
+    }
+}
+
+/// Name of the tags holding the text of an element with mixed content, e.g. `P.#text` for
+/// the text of `<P>see <B>this</B> note</P>`
+pub const TEXT_TAG_NAME: &str = "#text";
+
+/// Options of the xml parser
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    pub attribute_usage: AttributeUsage,
+    /// tree level of the top node, elements above it are skipped
+    pub top_node_tree_level: usize,
+    /// number of levels below the top node of the element naming the document type
+    pub type_distance_to_top: usize,
+    pub namespace_mode: NamespaceMode,
+    /// value of elements without content, e.g. `<BtchBookg/>`, None keeps them as node tags
+    pub empty_value: Option<String>,
+    pub mixed_content: MixedContent,
+    /// trim the text values and ignore whitespace between elements
+    pub ignore_whitespace: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            attribute_usage: AttributeUsage::AddSeparateTag,
+            top_node_tree_level: 0,
+            type_distance_to_top: 1,
+            namespace_mode: NamespaceMode::Prefixed,
+            empty_value: None,
+            mixed_content: MixedContent::Concatenate,
+            ignore_whitespace: true,
+        }
+    }
+}
+
+// the namespace prefix of the ISO 20022 message schemas
+const ISO_20022_URN: &str = "urn:iso:std:iso:20022:tech:xsd:";
+
+/// The ISO 20022 message definition of a document, identified from the namespace of its
+/// root element, e.g. `urn:iso:std:iso:20022:tech:xsd:pain.008.001.02`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[pyclass]
+pub struct MessageDefinition {
+    /// the message definition identifier, e.g. "pain.008.001.02"
+    #[pyo3(get)]
+    pub identifier: String,
+    /// the business area, e.g. "pain" for payments initiation
+    #[pyo3(get)]
+    pub business_area: String,
+    /// the message functionality within the business area, e.g. "008"
+    #[pyo3(get)]
+    pub functionality: String,
+    /// the variant of the message, "001" for the global message
+    #[pyo3(get)]
+    pub variant: String,
+    /// the version of the message, e.g. "02"
+    #[pyo3(get)]
+    pub version: String,
+}
+
+impl MessageDefinition {
+    /// The message definition of an ISO 20022 namespace, None for other namespaces
+    pub fn from_namespace(namespace: &str) -> Option<Self> {
+        let identifier = namespace.strip_prefix(ISO_20022_URN)?;
+        let parts: Vec<&str> = identifier.split('.').collect();
+        let is_number =
+            |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+        match parts[..] {
+            [business_area, functionality, variant, version]
+                if business_area.len() == 4
+                    && business_area.bytes().all(|b| b.is_ascii_lowercase())
+                    && is_number(functionality, 3)
+                    && is_number(variant, 3)
+                    && is_number(version, 2) =>
+            {
+                Some(MessageDefinition {
+                    identifier: identifier.to_owned(),
+                    business_area: business_area.to_owned(),
+                    functionality: functionality.to_owned(),
+                    variant: variant.to_owned(),
+                    version: version.to_owned(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[pymethods]
+impl MessageDefinition {
+    fn __repr__(&self) -> String {
+        format!("MessageDefinition('{}')", self.identifier)
+    }
+}
+
 #[derive(Debug, Clone)]
 #[pyclass]
 pub struct Tag {
@@ -149,42 +338,81 @@ impl Attribute {
     }
 }
 
+// an element of the document being parsed which has not been closed yet
+struct OpenElement {
+    path: String,
+    tag_id: usize,
+    level: usize,
+    // the element is stored as a node tag, it has child elements
+    stored: bool,
+    // the text values of the element which are not stored yet
+    segments: Vec<String>,
+}
+
+/// The parsed tags of a document: for each tag path the list of
+/// (tag_id, level, value, tag_type) entries in document order
+pub type XmlParsedMap = IndexMap<String, Vec<(usize, usize, String, usize)>, FxBuildHasher>;
+
+/// A parsed xml document.
+///
+/// Every element, text and - depending on the attribute usage - attribute gets a tag id.
+/// `xml_parsed` maps the tag paths to their values, `fstar` holds the tree of tag ids.
 #[derive(Debug, Clone)]
+#[pyclass]
 pub struct XmlDoc {
+    #[pyo3(get)]
     pub doc_id: usize,
+    #[pyo3(get)]
     pub doc_type: String,
+    /// namespace uri of the root element, None if it is in no namespace
+    #[pyo3(get)]
+    pub default_namespace: Option<String>,
+    /// the ISO 20022 message definition, None if the namespace is no ISO 20022 namespace
+    #[pyo3(get)]
+    pub message_definition: Option<MessageDefinition>,
     pub tags_n_values: Option<Vec<Tag>>,
     pub fstar: ForwardStar,
-    pub xml_parsed: IndexMap<String, Vec<(usize, usize, String, usize)>, FxBuildHasher>,
-    attribute_usage: AttributeUsage,
+    pub xml_parsed: XmlParsedMap,
+    /// the ids of the attribute tags of an element by the element's tag id, attributes
+    /// are tags of their own with the attribute usage `AddSeparateTag` only
+    pub attribute_ids: HashMap<usize, Vec<usize>>,
+    options: ParseOptions,
     curr_tag_id: usize,
-    top_node_tree_level: usize,
-    type_distance_to_top: usize,
 }
 
 impl fmt::Display for XmlDoc {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "** XmlDoc: [")?;
         if let Some(tnv) = &self.tags_n_values {
-            write!(f, "## Tag Vector: {:?}\n", tnv)?;
+            writeln!(f, "## Tag Vector: {:?}", tnv)?;
         } else {
-            write!(f, "## Tag Vector: None\n")?;
+            writeln!(f, "## Tag Vector: None")?;
         }
-        write!(f, "## ForwardStar: {:?}\n", self.fstar)?;
-        write!(f, "## XmlParsed (IndexMap): {:?}\n", self.xml_parsed)?;
-        write!(f, "## AttributeUsage: {:?}\n", self.attribute_usage)?;
-        write!(f, "## curr_tag_id (usize): {:?}\n", self.curr_tag_id)?;
-        write!(
+        writeln!(f, "## ForwardStar: {:?}", self.fstar)?;
+        writeln!(f, "## XmlParsed (IndexMap): {:?}", self.xml_parsed)?;
+        writeln!(f, "## AttributeUsage: {:?}", self.options.attribute_usage)?;
+        writeln!(f, "## curr_tag_id (usize): {:?}", self.curr_tag_id)?;
+        writeln!(
             f,
-            "## top_node_tree_level (usize): {:?}\n",
-            self.top_node_tree_level
+            "## top_node_tree_level (usize): {:?}",
+            self.options.top_node_tree_level
         )?;
-        write!(
+        writeln!(
             f,
-            "## type_distance_to_top (usize): {:?}\n",
-            self.type_distance_to_top
+            "## type_distance_to_top (usize): {:?}",
+            self.options.type_distance_to_top
+        )?;
+        writeln!(f, "## DocType (String): {:?}", self.doc_type)?;
+        writeln!(
+            f,
+            "## DefaultNamespace (Option<String>): {:?}",
+            self.default_namespace
+        )?;
+        writeln!(
+            f,
+            "## MessageDefinition (Option<MessageDefinition>): {:?}",
+            self.message_definition
         )?;
-        write!(f, "## DocType (String): {:?}\n", self.doc_type)?;
         write!(f, "] End XmlDoc **")
     }
 }
@@ -196,40 +424,61 @@ impl XmlDoc {
         attribute_usage: AttributeUsage,
         top_node_tree_level: Option<usize>,
         type_distance_to_top: Option<usize>,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
+    ) -> UtilrsResult<Self> {
+        let options = ParseOptions {
+            attribute_usage,
+            top_node_tree_level: top_node_tree_level.unwrap_or(0),
+            type_distance_to_top: type_distance_to_top.unwrap_or(1),
+            ..ParseOptions::default()
+        };
+        XmlDoc::with_options(doc_id, xml, &options)
+    }
+
+    /// Parse a document with the given parse options
+    pub fn with_options(doc_id: usize, xml: &str, options: &ParseOptions) -> UtilrsResult<Self> {
         let hash_builder = FxBuildHasher::default();
         let mut xml_doc = XmlDoc {
             doc_id,
             doc_type: String::new(),
+            default_namespace: None,
+            message_definition: None,
             tags_n_values: None,
             fstar: ForwardStar::new(),
             xml_parsed: IndexMap::with_hasher(hash_builder),
-            attribute_usage,
+            attribute_ids: HashMap::new(),
+            options: options.clone(),
             curr_tag_id: 0,
-            top_node_tree_level: top_node_tree_level.unwrap_or(0),
-            type_distance_to_top: type_distance_to_top.unwrap_or(1),
         };
-        if let Err(e) = xml_doc.parse_xml(xml) {
-            Err(e)
-        } else {
-            Ok(xml_doc)
-        }
+        xml_doc.parse_xml(xml)?;
+        Ok(xml_doc)
     }
 
-    fn parse_xml(&mut self, xml: &str) -> Result<(), Box<dyn std::error::Error>> {
+    fn parse_xml(&mut self, xml: &str) -> UtilrsResult<()> {
         let mut reader = Reader::from_str(xml);
-        reader.trim_text(true);
+        // whitespace is trimmed from the text of an element once all its text is known
+        reader.trim_text(false);
+        let doc_id = self.doc_id as isize;
+        let parse_error = |position: usize, message: String| UtilrsError::XmlParse {
+            doc_id,
+            position,
+            message,
+        };
 
         // let mut count = 0;
-        let doc_type_level: isize = (self.top_node_tree_level + self.type_distance_to_top) as isize;
-        let top_node_level: isize = self.top_node_tree_level as isize;
+        let doc_type_level: isize =
+            (self.options.top_node_tree_level + self.options.type_distance_to_top) as isize;
+        let top_node_level: isize = self.options.top_node_tree_level as isize;
         let mut tag_level: isize = -1;
         let mut skip_tag = false;
 
         let mut dom = Vec::new();
         let mut dom_ids: Vec<usize> = Vec::new();
+        let mut open_elements: Vec<OpenElement> = Vec::new();
         let mut tags_n_vals = Vec::new();
         let mut buf = Vec::new();
+        let mut ns_buf = Vec::new();
+        let namespace_mode = self.options.namespace_mode;
+        let mixed_content = self.options.mixed_content;
         let mut elname: String;
         let mut curr_tag: Tag = Tag::new();
         let mut curr_attr: Attribute = Attribute::new();
@@ -237,47 +486,49 @@ impl XmlDoc {
 
         // The `Reader` does not implement `Iterator` because it outputs borrowed data (`Cow`s)
         loop {
-            match reader.read_event(&mut buf) {
-                Ok(Event::Start(ref e)) => {
+            let event = reader.read_namespaced_event(&mut buf, &mut ns_buf);
+            // a self-closing element is a start and an end of an element
+            let is_empty_event = matches!(event, Ok((_, Event::Empty(_))));
+            let mut end_of_element = is_empty_event;
+            match event {
+                Ok((namespace, Event::Start(ref e))) | Ok((namespace, Event::Empty(ref e))) => {
+                    // the namespace of the root element is the namespace of the document
+                    if tag_level == -1 {
+                        self.default_namespace =
+                            namespace.map(|ns| String::from_utf8_lossy(ns).to_string());
+                        self.message_definition = self
+                            .default_namespace
+                            .as_deref()
+                            .and_then(MessageDefinition::from_namespace);
+                    }
                     // check if we need to skip this tag
                     tag_level += 1;
-                    if tag_level < top_node_level {
-                        skip_tag = true;
-                    } else {
-                        skip_tag = false;
-                    }
-                    /*
-                    println!(
-                        "skip_tag: {} | level: {} | top_node_level: {}",
-                        skip_tag,
-                        dom.len(),
-                        top_node_level
-                    );
-                    */
+                    skip_tag = tag_level < top_node_level;
                     // process only if not to be skipped
                     if !skip_tag {
-                        // if we have data in the tag, the previous tag had attributes but no Text/CData
-                        // process the attributes of the previous tag
-                        // println!("curr_tag: {:?}", curr_tag);
-                        if curr_tag.has_data {
-                            // println!("process_tag invoked (has_data) for tag {}", curr_tag.name);
-                            // add the tag to the document tags
-                            tags_n_vals.push(curr_tag.clone());
-                            // process the tag into the parsed xml index map
-                            self.process_tag(&mut curr_tag);
-
-                            curr_tag.clear_attributes();
-                            curr_tag.clear_tag_and_value();
-                        } else {
-                            if curr_tag.tag_id > 0 {
-                                // this is a node tag without attributes
-                                // println!("process_tag invoked for tag {}", curr_tag.name);
-                                self.process_tag(&mut curr_tag);
+                        // the parent element has a child element, so it is a node tag. Its
+                        // text so far is mixed content.
+                        if let Some(parent) = open_elements.last_mut() {
+                            if !parent.stored {
+                                tags_n_vals.push(curr_tag.clone());
+                                self.process_tag(&mut curr_tag)
+                                    .map_err(|e| parse_error(reader.buffer_position(), e))?;
+                                parent.stored = true;
+                                if mixed_content == MixedContent::Separate {
+                                    for segment in std::mem::take(&mut parent.segments) {
+                                        self.add_text_tag(parent, segment, &mut tags_n_vals)
+                                            .map_err(|e| {
+                                                parse_error(reader.buffer_position(), e)
+                                            })?;
+                                    }
+                                }
                             }
                         }
+                        curr_tag.clear_attributes();
+                        curr_tag.clear_tag_and_value();
 
                         // get the element name
-                        elname = String::from_utf8_lossy(e.name()).to_string();
+                        elname = namespace_mode.tag_name(e.name(), namespace);
 
                         // check if this is the document type level - if yes, assign the document type
                         if tag_level == doc_type_level {
@@ -302,6 +553,13 @@ impl XmlDoc {
                         } else {
                             parent_tag_id = dom_ids[dom_ids.len() - 2];
                         }
+                        open_elements.push(OpenElement {
+                            path: curr_name.to_owned(),
+                            tag_id: self.curr_tag_id,
+                            level: dom.len(),
+                            stored: false,
+                            segments: Vec::new(),
+                        });
                         curr_tag.update_tag_and_value(
                             curr_name,
                             "__node__".to_string(),
@@ -313,76 +571,86 @@ impl XmlDoc {
 
                         // println!("Start of element {}", elname);
                         for att_result in e.attributes() {
-                            let att_value = att_result?; //expect("There was an error getting the attributes!");
-                            let att_inner_value = att_value.unescape_and_decode_value(&reader)?;
-                            // .expect("Could not get the Attribute::value!");
+                            let att_value = att_result.map_err(|e| {
+                                parse_error(reader.buffer_position(), format!("{:?}", e))
+                            })?;
+                            // namespace declarations are resolved, not attributes, unless
+                            // the names are used as written
+                            if namespace_mode != NamespaceMode::Prefixed
+                                && (att_value.key == b"xmlns"
+                                    || att_value.key.starts_with(b"xmlns:"))
+                            {
+                                continue;
+                            }
+                            let att_inner_value =
+                                att_value.unescape_and_decode_value(&reader).map_err(|e| {
+                                    parse_error(reader.buffer_position(), format!("{:?}", e))
+                                })?;
+                            let (att_namespace, _) =
+                                reader.attribute_namespace(att_value.key, &ns_buf);
                             curr_attr.update_values(
-                                String::from_utf8_lossy(att_value.key).to_string(),
+                                namespace_mode.tag_name(att_value.key, att_namespace),
                                 att_inner_value,
                             );
                             curr_tag.update_attributes(curr_attr.clone());
                         }
                     }
                 }
-                Ok(Event::Text(ref e)) | Ok(Event::CData(ref e)) => {
-                    // process only if not to be skipped
-                    if !skip_tag {
-                        let curr_name = dom.join(".");
-                        let curr_value = e.unescape_and_decode(&reader)?;
-                        // .expect("Error while getting element text!");
-
-                        // set the parent tag id: to the tag id itself if this is the 1st element
-                        // in the dom tree, else to the 2nd last id in the dom_id tree
-                        if dom_ids.len() == 1 {
-                            parent_tag_id = self.curr_tag_id;
+                Ok((_, Event::Text(ref e))) | Ok((_, Event::CData(ref e))) => {
+                    // process only if not to be skipped, text outside of the elements is no
+                    // content of the document
+                    if let (false, Some(element)) = (skip_tag, open_elements.last_mut()) {
+                        let curr_value = e.unescape_and_decode(&reader).map_err(|e| {
+                            parse_error(reader.buffer_position(), format!("{:?}", e))
+                        })?;
+                        if curr_value.is_empty()
+                            || self.options.ignore_whitespace && curr_value.trim().is_empty()
+                        {
+                            // no text or whitespace between elements
+                        } else if element.stored && mixed_content == MixedContent::Separate {
+                            // text after a child element
+                            self.add_text_tag(element, curr_value, &mut tags_n_vals)
+                                .map_err(|e| parse_error(reader.buffer_position(), e))?;
                         } else {
-                            parent_tag_id = dom_ids[dom_ids.len() - 2];
+                            element.segments.push(curr_value);
                         }
-
-                        curr_tag.update_tag_and_value(
-                            curr_name,
-                            curr_value,
-                            self.curr_tag_id,
-                            parent_tag_id,
-                            dom.len(),
-                            TagType::DataTag,
-                        );
-                        // add the tag to the document tags
-                        tags_n_vals.push(curr_tag.clone());
-                        // process the tag into the parsed xml index map
-                        self.process_tag(&mut curr_tag);
                     }
                 }
-                Ok(Event::Empty(_e)) => {} //no need to process empty elements
-                Ok(Event::Comment(_e)) => {} //no need to process empty elements
+                Ok((_, Event::Comment(_e))) => {} //no need to process empty elements
                 // Ok(Event::CData(_e)) => {}
-                Ok(Event::Decl(_e)) => {}
-                Ok(Event::PI(_e)) => {} //no need to process processing instructions
-                Ok(Event::DocType(_e)) => {}
-                Ok(Event::End(_e)) => {
-                    // process only if not to be skipped
-                    if !skip_tag {
-                        // do clean-up work at tag closure
-                        // re-initiate the attr fields with empty strings
-                        curr_attr.clear_values();
-                        // re-initiage the current tag
-                        curr_tag.clear_tag_and_value();
-                        curr_tag.clear_attributes();
-
-                        // go one item back in tag_level, the dom tree and the dom_ids tree
-                        tag_level -= 1;
-                        let _last = dom.pop();
-                        let _last_id = dom_ids.pop();
-                    }
-                }
-                Ok(Event::Eof) => break, // exits the loop when reaching end of file
+                Ok((_, Event::Decl(_e))) => {}
+                Ok((_, Event::PI(_e))) => {} //no need to process processing instructions
+                Ok((_, Event::DocType(_e))) => {}
+                Ok((_, Event::End(_e))) => end_of_element = true,
+                Ok((_, Event::Eof)) => break, // exits the loop when reaching end of file
                 Err(e) => {
                     // return an error
-                    let msg = format!("Error at position {}: {:?}", reader.buffer_position(), e);
-                    return Err(msg)?;
+                    return Err(parse_error(reader.buffer_position(), format!("{:?}", e)));
                 } // _ => (), // All `Event`s are handled above
             }
 
+            if end_of_element && !skip_tag {
+                if let Some(element) = open_elements.pop() {
+                    self.close_element(element, &mut curr_tag, &mut tags_n_vals)
+                        .map_err(|e| parse_error(reader.buffer_position(), e))?;
+                }
+
+                // do clean-up work at tag closure
+                // re-initiate the attr fields with empty strings
+                curr_attr.clear_values();
+                // re-initiage the current tag
+                curr_tag.clear_tag_and_value();
+                curr_tag.clear_attributes();
+
+                // go one item back in tag_level, the dom tree and the dom_ids tree
+                tag_level -= 1;
+                let _last = dom.pop();
+                let _last_id = dom_ids.pop();
+            } else if is_empty_event {
+                // a self-closing element above the top node has no end event
+                tag_level -= 1;
+            }
+
             // if we don't keep a borrow elsewhere, we can clear the buffer to keep memory usage low
             buf.clear();
         }
@@ -391,14 +659,93 @@ impl XmlDoc {
         Ok(())
     }
 
-    fn process_tag(&mut self, tag: &mut Tag) {
+    // store the text of an element at its end. An element without child elements and a
+    // single text value - or concatenated text values - is a single tag with that value,
+    // without any text an empty element. The text of an element with child elements or
+    // separate text values is stored in text tags of its own.
+    fn close_element(
+        &mut self,
+        mut element: OpenElement,
+        tag: &mut Tag,
+        tags_n_vals: &mut Vec<Tag>,
+    ) -> Result<(), String> {
+        let trim = self.options.ignore_whitespace;
+        let concatenate = |segments: &[String]| {
+            let value = segments.concat();
+            match trim {
+                true => value.trim().to_owned(),
+                false => value,
+            }
+        };
+
+        if element.stored {
+            // text after the last child element of an element with mixed content
+            if !element.segments.is_empty() {
+                let value = concatenate(&element.segments);
+                self.add_text_tag(&element, value, tags_n_vals)?;
+            }
+            return Ok(());
+        }
+
+        if element.segments.len() > 1 && self.options.mixed_content == MixedContent::Separate {
+            // the element is a node with a text tag per value
+            tags_n_vals.push(tag.clone());
+            self.process_tag(tag)?;
+            element.stored = true;
+            for segment in std::mem::take(&mut element.segments) {
+                self.add_text_tag(&element, segment, tags_n_vals)?;
+            }
+            return Ok(());
+        }
+
+        if element.segments.is_empty() {
+            // an element without content: a node tag or a tag with the value marking it as
+            // present but empty
+            if let Some(empty_value) = &self.options.empty_value {
+                tag.update_tag_value(empty_value.to_owned());
+                tag.tag_type = TagType::DataTag;
+            }
+        } else {
+            tag.update_tag_value(concatenate(&element.segments));
+            tag.tag_type = TagType::DataTag;
+        }
+        tags_n_vals.push(tag.clone());
+        self.process_tag(tag)
+    }
+
+    // add a text value of an element with mixed content as a tag of its own below the element
+    fn add_text_tag(
+        &mut self,
+        element: &OpenElement,
+        value: String,
+        tags_n_vals: &mut Vec<Tag>,
+    ) -> Result<(), String> {
+        let value = match self.options.ignore_whitespace {
+            true => value.trim().to_owned(),
+            false => value,
+        };
+        self.curr_tag_id += 1;
+        let mut tag = Tag::new();
+        tag.update_tag_and_value(
+            format!("{}.{}", element.path, TEXT_TAG_NAME),
+            value,
+            self.curr_tag_id,
+            element.tag_id,
+            element.level + 1,
+            TagType::DataTag,
+        );
+        tags_n_vals.push(tag.clone());
+        self.process_tag(&mut tag)
+    }
+
+    fn process_tag(&mut self, tag: &mut Tag) -> Result<(), String> {
         // process attributes first - if any
         if let Some(attrs) = tag.attributes.as_ref() {
             let mut attribs: String = String::new();
             let key: String;
             let value: String;
 
-            match self.attribute_usage {
+            match self.options.attribute_usage {
                 AttributeUsage::AddToTagName => {
                     // add the attributes to the tag name
                     for att in attrs {
@@ -419,17 +766,14 @@ impl XmlDoc {
                     // process the tag itself - this is needed to ensure
                     // the forward star is not missing an element
                     // add the Tag Name to the long name
-                    let (_, tag_name_short) = tag
-                        .name
-                        .rsplit_once(".")
-                        .unwrap_or_else(|| ("n/a", tag.name.as_str()));
+                    let tag_name_short = tag_name(&tag.name);
                     let tag_name_long = format!("{}.{}", tag.name, tag_name_short);
                     let mut copy_tag = tag.derive_new_without_attributes(
                         tag_name_long,
                         tag.value.to_owned(),
                         tag.tag_id,
                     );
-                    self.process_tag(&mut copy_tag);
+                    self.process_tag(&mut copy_tag)?;
 
                     // add the attributes as new tags and
                     // process the new tags
@@ -442,11 +786,15 @@ impl XmlDoc {
                             att.value.to_owned(),
                             self.curr_tag_id,
                         );
-                        self.process_tag(&mut tmp_tag);
+                        self.process_tag(&mut tmp_tag)?;
+                        self.attribute_ids
+                            .entry(tag.tag_id)
+                            .or_default()
+                            .push(self.curr_tag_id);
                     }
 
                     // exit here to prevent duplicate insertion of the tag itself
-                    return;
+                    return Ok(());
                 }
                 AttributeUsage::Ignore => {
                     // ignore the attributes, i.e. do nothing
@@ -461,10 +809,14 @@ impl XmlDoc {
             //     tag.name, tag.parent_tag_id, tag.tag_id
             // );
 
-            self.fstar.add_child(
-                tag.parent_tag_id.to_string().as_str(),
-                tag.tag_id.to_string().as_str(),
-            );
+            self.fstar
+                .add_child(
+                    tag.parent_tag_id.to_string().as_str(),
+                    tag.tag_id.to_string().as_str(),
+                )
+                .map_err(|e| {
+                    format!("element '{}' can not be added to the tree: {}", tag.name, e)
+                })?;
         } else {
             // println!(
             //     "calling add_root for tag '{}' with tag_id {}",
@@ -475,25 +827,564 @@ impl XmlDoc {
         }
 
         // process the tag part 2 - add to indexmap
-        if self.xml_parsed.contains_key(&tag.name) {
-            let values = self.xml_parsed.get_mut(&tag.name).unwrap();
-            values.push((
+        self.xml_parsed
+            .entry(tag.name.to_owned())
+            .or_default()
+            .push((
                 tag.tag_id,
                 tag.level,
                 tag.value.to_owned(),
                 tag.tag_type as usize,
             ));
-            // self.xml_parsed.insert(tag.name.to_owned(), values);
-        } else {
-            let values = vec![(
-                tag.tag_id,
-                tag.level,
-                tag.value.to_owned(),
-                tag.tag_type as usize,
-            )];
-            self.xml_parsed.insert(tag.name.to_owned(), values);
+        Ok(())
+    }
+}
+
+// name of a tag within its parent, the last part of the tag path. Dots within the
+// namespace of a name in Clark notation do not separate the parts of the path.
+fn tag_name(path: &str) -> &str {
+    let mut depth = 0;
+    for (pos, c) in path.char_indices().rev() {
+        match c {
+            '}' => depth += 1,
+            '{' => depth -= 1,
+            '.' if depth == 0 => return &path[pos + 1..],
+            _ => {}
+        }
+    }
+    path
+}
+
+/*
+Navigation over the parsed tags
+*/
+impl XmlDoc {
+    /// Number of tags of the document
+    pub fn tag_count(&self) -> usize {
+        self.xml_parsed.values().map(|values| values.len()).sum()
+    }
+
+    /// The values of all tags with the given path in document order
+    pub fn values(&self, path: &str) -> Vec<&str> {
+        match self.xml_parsed.get(path) {
+            Some(values) => values.iter().map(|v| v.2.as_str()).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The ids of all tags with the given path in document order
+    pub fn tag_ids(&self, path: &str) -> Vec<usize> {
+        match self.xml_parsed.get(path) {
+            Some(values) => values.iter().map(|v| v.0).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Path and value of a tag
+    pub fn find_tag(&self, tag_id: usize) -> Option<(&str, &str)> {
+        self.xml_parsed.iter().find_map(|(path, values)| {
+            values
+                .iter()
+                .find(|v| v.0 == tag_id)
+                .map(|v| (path.as_str(), v.2.as_str()))
+        })
+    }
+
+    /// Replace the value of a tag, false for an unknown tag id. The parsed tags follow,
+    /// an attribute stored as tag of its own changes the attribute of its element.
+    pub fn set_value(&mut self, tag_id: usize, value: &str) -> bool {
+        let tag = self
+            .xml_parsed
+            .values_mut()
+            .flat_map(|values| values.iter_mut())
+            .find(|v| v.0 == tag_id);
+        match tag {
+            Some(tag) => tag.2 = value.to_owned(),
+            None => return false,
+        }
+
+        let element_attribute = self.attribute_ids.iter().find_map(|(&element_id, ids)| {
+            ids.iter()
+                .position(|&id| id == tag_id)
+                .map(|pos| (element_id, pos))
+        });
+        let attribute_usage = self.options.attribute_usage;
+        let mut tags = self.tags_n_values.iter_mut().flatten();
+        match element_attribute {
+            Some((element_id, pos)) => {
+                let attribute = tags
+                    .filter(|tag| tag.tag_id == element_id)
+                    .find_map(|tag| tag.attributes.as_mut()?.get_mut(pos));
+                if let Some(attribute) = attribute {
+                    attribute.value = value.to_owned();
+                }
+            }
+            None => {
+                if let Some(tag) = tags.find(|tag| tag.tag_id == tag_id) {
+                    // the parsed value starts with the attribute values, the tag value not
+                    let prefix: String = match attribute_usage {
+                        AttributeUsage::AddToTagValue => tag
+                            .attributes
+                            .iter()
+                            .flatten()
+                            .map(|att| format!("{}-", att.value))
+                            .collect(),
+                        _ => String::new(),
+                    };
+                    tag.value = value
+                        .strip_prefix(prefix.as_str())
+                        .unwrap_or(value)
+                        .to_owned();
+                }
+            }
+        }
+        true
+    }
+
+    // the tag id of a node of the forward star tree, the node captions are the tag ids
+    fn node_tag_id(&self, node: usize) -> Option<usize> {
+        self.fstar.get_node_captions().get(node)?.parse().ok()
+    }
+
+    /// The ids of the child tags of a tag, None for an unknown tag id
+    pub fn child_ids(&self, tag_id: usize) -> Option<Vec<usize>> {
+        let node = self.fstar.find_node_by_caption(&tag_id.to_string()).ok()?;
+        let children = self.fstar.children(node);
+        Some(
+            children
+                .iter()
+                .filter_map(|&c| self.node_tag_id(c))
+                .collect(),
+        )
+    }
+
+    /// The id of the parent tag of a tag, None for the root and for an unknown tag id
+    pub fn parent_id(&self, tag_id: usize) -> Option<usize> {
+        let (parent, _) = self
+            .fstar
+            .find_parent_by_caption(&tag_id.to_string())
+            .ok()?;
+        self.node_tag_id(parent)
+    }
+
+    // convert the tree below a node into python objects: a tag without children becomes its
+    // value, a tag with children a dict of the child names, the values of repeated child
+    // names are collected in a list
+    fn node_to_py(
+        &self,
+        py: Python,
+        node: usize,
+        tags: &HashMap<usize, (&str, &str)>,
+    ) -> PyResult<PyObject> {
+        let children = self.fstar.children(node);
+        if children.is_empty() {
+            let value = self
+                .node_tag_id(node)
+                .and_then(|tag_id| tags.get(&tag_id))
+                .map(|(_, value)| *value);
+            return Ok(match value {
+                Some("__node__") | None => py.None(),
+                Some(value) => value.into_py(py),
+            });
+        }
+        let dict = PyDict::new(py);
+        for &child in children {
+            let name = match self.node_tag_id(child).and_then(|tag_id| tags.get(&tag_id)) {
+                Some((path, _)) => tag_name(path),
+                None => continue,
+            };
+            let value = self.node_to_py(py, child, tags)?;
+            // a tag is never converted into a list, so a list is a repeated child name
+            match dict.get_item(name) {
+                None => dict.set_item(name, value)?,
+                Some(existing) => match existing.downcast::<PyList>() {
+                    Ok(list) => list.append(value)?,
+                    Err(_) => {
+                        dict.set_item(name, PyList::new(py, [existing.into_py(py), value]))?
+                    }
+                },
+            }
+        }
+        Ok(dict.into_py(py))
+    }
+}
+
+#[pymethods]
+impl XmlDoc {
+    /// Parse a document. attribute_usage is "separate_tag" (attributes become tags of their
+    /// own), "tag_name" or "tag_value" (attribute values are added to the tag name or value)
+    /// or "ignore". Elements above top_node_tree_level are skipped, the doc type is the name
+    /// of the element type_distance_to_top levels below the top node. namespaces is
+    /// "prefixed" (names as written), "local" (local names) or "clark" ({namespace}name).
+    /// Elements without content are node tags, or tags with the value empty_value if given.
+    /// The text values of an element with mixed content are stored "concatenated" or
+    /// "separate" in tags named #text, ignore_whitespace trims the text values and ignores
+    /// whitespace between elements.
+    #[new]
+    #[args(
+        doc_id = "0",
+        attribute_usage = "\"separate_tag\"",
+        top_node_tree_level = "0",
+        type_distance_to_top = "1",
+        namespaces = "\"prefixed\"",
+        empty_value = "None",
+        mixed_content = "\"concatenate\"",
+        ignore_whitespace = "true"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn py_new(
+        xml: &str,
+        doc_id: usize,
+        attribute_usage: &str,
+        top_node_tree_level: usize,
+        type_distance_to_top: usize,
+        namespaces: &str,
+        empty_value: Option<String>,
+        mixed_content: &str,
+        ignore_whitespace: bool,
+    ) -> PyResult<Self> {
+        let attribute_usage = AttributeUsage::from_name(attribute_usage).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "unknown attribute_usage '{}', expected 'separate_tag', 'tag_name', \
+                'tag_value' or 'ignore'",
+                attribute_usage
+            ))
+        })?;
+        let options = ParseOptions {
+            attribute_usage,
+            top_node_tree_level,
+            type_distance_to_top,
+            namespace_mode: get_namespace_mode(namespaces)?,
+            empty_value,
+            mixed_content: get_mixed_content(mixed_content)?,
+            ignore_whitespace,
+        };
+        Ok(XmlDoc::with_options(doc_id, xml, &options)?)
+    }
+
+    /// number of tags of the document
+    #[getter(tag_count)]
+    fn py_tag_count(&self) -> usize {
+        self.tag_count()
+    }
+
+    /// all tag paths in document order
+    #[getter]
+    fn paths(&self) -> Vec<String> {
+        self.xml_parsed.keys().cloned().collect()
+    }
+
+    /// value of the first tag with the path, None if there is no such tag
+    fn get(&self, path: &str) -> Option<String> {
+        self.values(path).first().map(|value| value.to_string())
+    }
+
+    /// values of all tags with the path
+    fn get_all(&self, path: &str) -> Vec<String> {
+        self.values(path)
+            .iter()
+            .map(|value| value.to_string())
+            .collect()
+    }
+
+    /// ids of all tags with the path
+    #[pyo3(name = "tag_ids")]
+    fn py_tag_ids(&self, path: &str) -> Vec<usize> {
+        self.tag_ids(path)
+    }
+
+    /// (path, value) of a tag
+    fn tag(&self, tag_id: usize) -> PyResult<(String, String)> {
+        match self.find_tag(tag_id) {
+            Some((path, value)) => Ok((path.to_owned(), value.to_owned())),
+            None => Err(unknown_tag_id(tag_id)),
+        }
+    }
+
+    /// ids of the child tags of a tag
+    fn children(&self, tag_id: usize) -> PyResult<Vec<usize>> {
+        self.child_ids(tag_id).ok_or_else(|| unknown_tag_id(tag_id))
+    }
+
+    /// id of the parent tag of a tag, None for the root
+    fn parent(&self, tag_id: usize) -> PyResult<Option<usize>> {
+        match self.find_tag(tag_id) {
+            Some(_) => Ok(self.parent_id(tag_id)),
+            None => Err(unknown_tag_id(tag_id)),
+        }
+    }
+
+    /// values of the tags selected by a query in a subset of XPath, see query_ids
+    #[pyo3(name = "query")]
+    fn py_query(&self, xpath: &str) -> PyResult<Vec<String>> {
+        let selected = self.query(xpath).map_err(PyValueError::new_err)?;
+        Ok(selected
+            .into_iter()
+            .map(|(_, value)| value.to_owned())
+            .collect())
+    }
+
+    /// ids of the tags selected by a query in a subset of XPath: the child (/) and
+    /// descendant (//) axes, element names and *, text() and @name or @* as the last step
+    /// and the predicates [n], [last()], [@name], [@name='value'], [text()='value'], [name]
+    /// and [name='value']. Attributes can be selected with attribute_usage "separate_tag"
+    /// only. An invalid query raises a ValueError.
+    fn query_ids(&self, xpath: &str) -> PyResult<Vec<usize>> {
+        let selected = self.query(xpath).map_err(PyValueError::new_err)?;
+        Ok(selected.into_iter().map(|(tag_id, _)| tag_id).collect())
+    }
+
+    /// replace the value of a tag, e.g. to mask it before the document is written again
+    #[pyo3(name = "set_value")]
+    fn py_set_value(&mut self, tag_id: usize, value: &str) -> PyResult<()> {
+        match self.set_value(tag_id, value) {
+            true => Ok(()),
+            false => Err(unknown_tag_id(tag_id)),
+        }
+    }
+
+    /// the document as xml, indented by `indent` spaces per level or compact if None
+    #[pyo3(name = "to_xml")]
+    #[args(indent = "None", declaration = "false")]
+    fn py_to_xml(&self, indent: Option<usize>, declaration: bool) -> String {
+        self.to_xml(&WriteOptions {
+            indent,
+            declaration,
+        })
+    }
+
+    /// the differences of another document to this one as a list of Change records with the
+    /// kind "added", "removed" or "changed", the path of the element or attribute and the
+    /// old and new value. ignore_order matches repeated elements by their content instead
+    /// of their position, ignore_whitespace compares values with normalized whitespace.
+    #[pyo3(name = "diff")]
+    #[args(ignore_order = "false", ignore_whitespace = "false")]
+    fn py_diff(
+        &self,
+        other: PyRef<XmlDoc>,
+        ignore_order: bool,
+        ignore_whitespace: bool,
+    ) -> Vec<Change> {
+        let options = DiffOptions {
+            ignore_order,
+            ignore_whitespace,
+        };
+        self.diff(&other, &options)
+    }
+
+    /// the differences of another document to this one as a unified report, see diff
+    #[pyo3(name = "diff_report")]
+    #[args(ignore_order = "false", ignore_whitespace = "false")]
+    fn py_diff_report(
+        &self,
+        other: PyRef<XmlDoc>,
+        ignore_order: bool,
+        ignore_whitespace: bool,
+    ) -> String {
+        let options = DiffOptions {
+            ignore_order,
+            ignore_whitespace,
+        };
+        diff_report(
+            &format!("doc {}", self.doc_id),
+            &format!("doc {}", other.doc_id),
+            &self.diff(&other, &options),
+        )
+    }
+
+    /// the document as nested dicts, {root name: {child name: value or dict or list}}
+    fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        if self.fstar.has_root() {
+            let tags: HashMap<usize, (&str, &str)> = self
+                .xml_parsed
+                .iter()
+                .flat_map(|(path, values)| {
+                    values
+                        .iter()
+                        .map(move |v| (v.0, (path.as_str(), v.2.as_str())))
+                })
+                .collect();
+            let root_name = self
+                .node_tag_id(0)
+                .and_then(|tag_id| tags.get(&tag_id))
+                .map_or("", |(path, _)| tag_name(path));
+            dict.set_item(root_name, self.node_to_py(py, 0, &tags)?)?;
+        }
+        Ok(dict.into_py(py))
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "XmlDoc(doc_id={}, doc_type='{}', tag_count={})",
+            self.doc_id,
+            self.doc_type,
+            self.tag_count()
+        )
+    }
+}
+
+/// The namespace mode for its python name, a ValueError for an unknown name
+pub fn get_namespace_mode(name: &str) -> PyResult<NamespaceMode> {
+    NamespaceMode::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "unknown namespaces '{}', expected 'prefixed', 'local' or 'clark'",
+            name
+        ))
+    })
+}
+
+/// The mixed content policy for its python name, a ValueError for an unknown name
+pub fn get_mixed_content(name: &str) -> PyResult<MixedContent> {
+    MixedContent::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "unknown mixed_content '{}', expected 'concatenate' or 'separate'",
+            name
+        ))
+    })
+}
+
+fn unknown_tag_id(tag_id: usize) -> PyErr {
+    PyKeyError::new_err(format!("unknown tag id {}", tag_id))
+}
+
+/// Check that a document is well-formed xml with exactly one root element.
+///
+/// Optionally the (local) name of the root element and the namespace the root element
+/// belongs to are checked as well. The error holds a readable reason why the document
+/// is not valid.
+pub fn check_well_formed(
+    xml: &str,
+    root_element: Option<&str>,
+    namespace: Option<&str>,
+) -> Result<(), String> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut buf = Vec::new();
+    let mut depth: usize = 0;
+    let mut root_found = false;
+
+    loop {
+        let event = match reader.read_event(&mut buf) {
+            Ok(event) => event,
+            Err(e) => {
+                return Err(format!(
+                    "not well-formed: error at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))
+            }
+        };
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                // check the attributes for syntax errors and duplicates
+                for att_result in e.attributes() {
+                    if let Err(err) = att_result {
+                        return Err(format!(
+                            "not well-formed: invalid attribute at position {}: {}",
+                            reader.buffer_position(),
+                            err
+                        ));
+                    }
+                }
+                if depth == 0 {
+                    if root_found {
+                        return Err(format!(
+                            "not well-formed: more than one root element, second root element found at position {}",
+                            reader.buffer_position()
+                        ));
+                    }
+                    root_found = true;
+                    check_root_element(e, &reader, root_element, namespace)?;
+                }
+                if let Event::Start(_) = event {
+                    depth += 1;
+                }
+            }
+            Event::End(_) => depth -= 1,
+            Event::Text(ref e) | Event::CData(ref e) => {
+                if depth == 0 {
+                    return Err(format!(
+                        "not well-formed: text content outside of the root element at position {}",
+                        reader.buffer_position()
+                    ));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    if !root_found {
+        Err("not well-formed: no root element found".to_string())
+    } else if depth > 0 {
+        Err(format!(
+            "not well-formed: unexpected end of document, {} element(s) not closed",
+            depth
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+// check the name and namespace of the root element against the expected values
+fn check_root_element(
+    e: &quick_xml::events::BytesStart,
+    reader: &Reader<&[u8]>,
+    root_element: Option<&str>,
+    namespace: Option<&str>,
+) -> Result<(), String> {
+    let name = String::from_utf8_lossy(e.name()).to_string();
+    let (prefix, local_name) = match name.split_once(':') {
+        Some((prefix, local_name)) => (Some(prefix), local_name),
+        None => (None, name.as_str()),
+    };
+
+    if let Some(expected) = root_element {
+        if local_name != expected {
+            return Err(format!(
+                "unexpected root element '{}', expected '{}'",
+                local_name, expected
+            ));
+        }
+    }
+
+    if let Some(expected) = namespace {
+        // the namespace of the root element is declared by xmlns (no prefix)
+        // or xmlns:<prefix> on the root element itself
+        let ns_attribute = match prefix {
+            Some(prefix) => format!("xmlns:{}", prefix),
+            None => "xmlns".to_string(),
+        };
+        let mut found: Option<String> = None;
+        for att in e.attributes().flatten() {
+            if att.key == ns_attribute.as_bytes() {
+                found = Some(
+                    att.unescape_and_decode_value(reader)
+                        .map_err(|err| err.to_string())?,
+                );
+            }
+        }
+        match found {
+            Some(ns) if ns == expected => {}
+            Some(ns) => {
+                return Err(format!(
+                    "unexpected namespace '{}' on root element '{}', expected '{}'",
+                    ns, local_name, expected
+                ))
+            }
+            None => {
+                return Err(format!(
+                    "no namespace declared on root element '{}', expected '{}'",
+                    local_name, expected
+                ))
+            }
         }
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -554,4 +1445,245 @@ mod tests {
         // print the end of the processing
         println!("-----------------------------------");
     }
+
+    #[test]
+    fn check_doc_validity() {
+        let ns = "urn:iso:std:iso:20022:tech:xsd:pain.008.001.02";
+        let valid = r#"<?xml version="1.0"?><Document xmlns="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02"><A x="1"/></Document>"#;
+        assert!(check_well_formed(valid, None, None).is_ok());
+        assert!(check_well_formed(valid, Some("Document"), Some(ns)).is_ok());
+        let prefixed = r#"<n:Document xmlns:n="urn:iso:std:iso:20022:tech:xsd:pain.008.001.02"/>"#;
+        assert!(check_well_formed(prefixed, Some("Document"), Some(ns)).is_ok());
+
+        let reason = check_well_formed(valid, Some("AppHdr"), None).unwrap_err();
+        assert!(reason.contains("unexpected root element 'Document'"));
+        let reason = check_well_formed(valid, None, Some("urn:other")).unwrap_err();
+        assert!(reason.contains("unexpected namespace"));
+        let reason = check_well_formed("<A><B></A>", None, None).unwrap_err();
+        assert!(reason.starts_with("not well-formed"));
+        let reason = check_well_formed("<A></A><B/>", None, None).unwrap_err();
+        assert!(reason.contains("more than one root element"));
+        let reason = check_well_formed("<A><B>", None, None).unwrap_err();
+        assert!(reason.contains("not closed"));
+        let reason = check_well_formed("junk<A/>", None, None).unwrap_err();
+        assert!(reason.contains("outside of the root element"));
+        let reason = check_well_formed("  ", None, None).unwrap_err();
+        assert!(reason.contains("no root element"));
+    }
+
+    #[test]
+    fn navigate_parsed_tags() {
+        let xml = r#"<Doc><Hdr><Id>1</Id></Hdr><Tx><Amt Ccy="EUR">10</Amt></Tx><Tx><Amt>20</Amt></Tx></Doc>"#;
+        let doc = XmlDoc::with_options(1, xml, &ParseOptions::default()).unwrap();
+
+        assert_eq!(doc.doc_type, "Tx");
+        assert_eq!(doc.tag_count(), 8);
+        assert_eq!(doc.values("Doc.Tx.Amt"), vec!["20"]);
+        assert_eq!(doc.values("Doc.Tx.Amt.Amt"), vec!["10"]);
+        assert_eq!(doc.values("Doc.Tx.Amt.Ccy"), vec!["EUR"]);
+        assert!(doc.values("Doc.Nothing").is_empty());
+
+        let tx_ids = doc.tag_ids("Doc.Tx");
+        assert_eq!(tx_ids.len(), 2);
+        let amt_id = doc.tag_ids("Doc.Tx.Amt")[0];
+        assert_eq!(doc.find_tag(amt_id), Some(("Doc.Tx.Amt", "20")));
+        assert_eq!(doc.parent_id(amt_id), Some(tx_ids[1]));
+        assert_eq!(doc.child_ids(tx_ids[1]), Some(vec![amt_id]));
+
+        let root_id = doc.tag_ids("Doc")[0];
+        assert_eq!(doc.parent_id(root_id), None);
+        assert_eq!(doc.child_ids(root_id).unwrap().len(), 3);
+        assert_eq!(doc.child_ids(999), None);
+    }
+
+    #[test]
+    fn resolve_namespaces() {
+        let ns = "urn:iso:std:iso:20022:tech:xsd:pain.008.001.02";
+        let prefixed = format!(
+            r#"<n:Document xmlns:n="{}" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:type="T"><n:Tx><n:Amt Ccy="EUR">10</n:Amt></n:Tx></n:Document>"#,
+            ns
+        );
+        let default = format!(
+            r#"<Document xmlns="{}"><Tx><Amt Ccy="EUR">10</Amt></Tx></Document>"#,
+            ns
+        );
+        let parse = |xml: &str, namespace_mode| {
+            let options = ParseOptions {
+                namespace_mode,
+                ..ParseOptions::default()
+            };
+            XmlDoc::with_options(1, xml, &options).unwrap()
+        };
+
+        // the names as written differ, the local names are the same
+        let doc = parse(&prefixed, NamespaceMode::Prefixed);
+        assert_eq!(doc.values("n:Document.n:Tx.n:Amt.Ccy"), vec!["EUR"]);
+        assert_eq!(doc.values("n:Document.xmlns:n"), vec![ns]);
+        assert_eq!(doc.default_namespace.as_deref(), Some(ns));
+        for xml in [&prefixed, &default] {
+            let doc = parse(xml, NamespaceMode::LocalName);
+            assert_eq!(doc.doc_type, "Tx");
+            assert_eq!(doc.values("Document.Tx.Amt.Amt"), vec!["10"]);
+            assert_eq!(doc.values("Document.Tx.Amt.Ccy"), vec!["EUR"]);
+            assert_eq!(doc.default_namespace.as_deref(), Some(ns));
+            assert!(doc.paths().iter().all(|path| !path.contains("xmlns")));
+        }
+        assert_eq!(
+            parse(&prefixed, NamespaceMode::LocalName).values("Document.type"),
+            vec!["T"]
+        );
+
+        // the dots of the namespace do not split the path, attributes without prefix
+        // are in no namespace
+        let doc = parse(&default, NamespaceMode::Clark);
+        let amt = format!("{{{0}}}Document.{{{0}}}Tx.{{{0}}}Amt", ns);
+        assert_eq!(doc.values(&format!("{}.{{{}}}Amt", amt, ns)), vec!["10"]);
+        assert_eq!(doc.values(&format!("{}.Ccy", amt)), vec!["EUR"]);
+        assert_eq!(tag_name(&amt), format!("{{{}}}Amt", ns));
+
+        let doc = parse("<A><B>1</B></A>", NamespaceMode::Clark);
+        assert_eq!(doc.values("A.B"), vec!["1"]);
+        assert_eq!(doc.default_namespace, None);
+    }
+
+    #[test]
+    fn identify_iso_20022_messages() {
+        let message =
+            MessageDefinition::from_namespace("urn:iso:std:iso:20022:tech:xsd:pain.008.001.02")
+                .unwrap();
+        assert_eq!(message.identifier, "pain.008.001.02");
+        assert_eq!(message.business_area, "pain");
+        assert_eq!(message.functionality, "008");
+        assert_eq!(message.variant, "001");
+        assert_eq!(message.version, "02");
+        for namespace in [
+            "urn:x",
+            "urn:iso:std:iso:20022:tech:xsd:pain.008.001",
+            "urn:iso:std:iso:20022:tech:xsd:pain.008.001.02.ch",
+            "urn:iso:std:iso:20022:tech:xsd:PAIN.008.001.02",
+            "urn:iso:std:iso:20022:tech:xsd:pain.8.1.2",
+        ] {
+            assert_eq!(MessageDefinition::from_namespace(namespace), None);
+        }
+
+        // the namespace of the root element identifies the message, whatever its prefix
+        let xml = r#"<h:AppHdr xmlns:h="urn:iso:std:iso:20022:tech:xsd:head.001.001.01"><Fr/></h:AppHdr>"#;
+        let doc = XmlDoc::with_options(1, xml, &ParseOptions::default()).unwrap();
+        let message = doc.message_definition.unwrap();
+        assert_eq!(message.identifier, "head.001.001.01");
+        assert_eq!(message.business_area, "head");
+        let doc =
+            XmlDoc::with_options(1, "<Document><A>1</A></Document>", &ParseOptions::default())
+                .unwrap();
+        assert_eq!(doc.message_definition, None);
+    }
+
+    #[test]
+    fn keep_empty_elements() {
+        let xml = r#"<Doc><Hdr/><Amt Ccy="EUR"/><Flg></Flg><Id>1</Id></Doc>"#;
+        let doc = XmlDoc::with_options(1, xml, &ParseOptions::default()).unwrap();
+        assert_eq!(doc.values("Doc.Hdr"), vec!["__node__"]);
+        assert_eq!(doc.values("Doc.Flg"), vec!["__node__"]);
+        assert_eq!(doc.values("Doc.Amt.Amt"), vec!["__node__"]);
+        assert_eq!(doc.values("Doc.Amt.Ccy"), vec!["EUR"]);
+        assert_eq!(doc.values("Doc.Id"), vec!["1"]);
+        let root_id = doc.tag_ids("Doc")[0];
+        assert_eq!(doc.child_ids(root_id).unwrap().len(), 5);
+        assert_eq!(doc.fstar.num_nodes, doc.tag_count());
+
+        let options = ParseOptions {
+            attribute_usage: AttributeUsage::AddToTagValue,
+            empty_value: Some(String::new()),
+            ..ParseOptions::default()
+        };
+        let doc = XmlDoc::with_options(1, xml, &options).unwrap();
+        assert_eq!(doc.values("Doc.Hdr"), vec![""]);
+        assert_eq!(doc.values("Doc.Amt"), vec!["EUR-"]);
+
+        // a self-closing root element and self-closing elements above the top node
+        let doc = XmlDoc::with_options(1, r#"<A x="1"/>"#, &ParseOptions::default()).unwrap();
+        assert_eq!(doc.values("A.x"), vec!["1"]);
+        let options = ParseOptions {
+            top_node_tree_level: 2,
+            ..ParseOptions::default()
+        };
+        let doc = XmlDoc::with_options(1, "<R><S/><A><B>1</B></A></R>", &options).unwrap();
+        assert_eq!(doc.values("B"), vec!["1"]);
+        assert!(doc.values("S").is_empty());
+    }
+
+    #[test]
+    fn mixed_content_policies() {
+        let xml = "<P>see <B>this</B> note<!-- c --> here <I/></P>";
+        let options = |mixed_content, ignore_whitespace| ParseOptions {
+            mixed_content,
+            ignore_whitespace,
+            ..ParseOptions::default()
+        };
+        let parse = |mixed_content, ignore_whitespace| {
+            XmlDoc::with_options(1, xml, &options(mixed_content, ignore_whitespace)).unwrap()
+        };
+
+        let doc = parse(MixedContent::Concatenate, true);
+        assert_eq!(doc.values("P"), vec!["__node__"]);
+        assert_eq!(doc.values("P.#text"), vec!["see  note here"]);
+        let text_id = doc.tag_ids("P.#text")[0];
+        assert_eq!(doc.parent_id(text_id), doc.tag_ids("P").first().copied());
+
+        // every text value has its own id, the ids and the children of P are in document order
+        let doc = parse(MixedContent::Separate, true);
+        assert_eq!(doc.values("P.#text"), vec!["see", "note", "here"]);
+        let p_id = doc.tag_ids("P")[0];
+        let text_ids = doc.tag_ids("P.#text");
+        let b_id = doc.tag_ids("P.B")[0];
+        let i_id = doc.tag_ids("P.I")[0];
+        assert_eq!(
+            doc.child_ids(p_id),
+            Some(vec![text_ids[0], b_id, text_ids[1], text_ids[2], i_id])
+        );
+        assert!(text_ids.iter().all(|&id| doc.parent_id(id) == Some(p_id)));
+        assert_eq!(doc.fstar.num_nodes, doc.tag_count());
+
+        let doc = parse(MixedContent::Separate, false);
+        assert_eq!(doc.values("P.#text"), vec!["see ", " note", " here "]);
+        let options = options(MixedContent::Separate, false);
+        let doc = XmlDoc::with_options(1, "<A>\n <B>1</B>\n</A>", &options).unwrap();
+        assert_eq!(doc.values("A.#text"), vec!["\n ", "\n"]);
+
+        // text split by a comment or a CDATA section without child elements
+        let xml = "<A><B>1<!-- c -->2<![CDATA[3]]></B><C> 4 </C></A>";
+        let doc = XmlDoc::with_options(1, xml, &ParseOptions::default()).unwrap();
+        assert_eq!(doc.values("A.B"), vec!["123"]);
+        assert_eq!(doc.values("A.C"), vec!["4"]);
+        let options = ParseOptions {
+            mixed_content: MixedContent::Separate,
+            ..ParseOptions::default()
+        };
+        let doc = XmlDoc::with_options(1, xml, &options).unwrap();
+        assert_eq!(doc.values("A.B"), vec!["__node__"]);
+        assert_eq!(doc.values("A.B.#text"), vec!["1", "2", "3"]);
+        assert_eq!(doc.values("A.C"), vec!["4"]);
+    }
+
+    #[test]
+    fn parse_errors_carry_doc_id_and_position() {
+        let parse = |xml| XmlDoc::new(7, xml, AttributeUsage::AddSeparateTag, None, None);
+
+        match parse("<A><B>1</B></C>") {
+            Err(UtilrsError::XmlParse {
+                doc_id, position, ..
+            }) => {
+                assert_eq!(doc_id, 7);
+                assert_eq!(position, 13);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // a second root element has no parent in the tree
+        match parse("<A>1</A><B>2</B>") {
+            Err(UtilrsError::XmlParse { message, .. }) => {
+                assert!(message.contains("can not be added to the tree"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
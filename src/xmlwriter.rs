@@ -0,0 +1,420 @@
+use quick_xml::escape::escape;
+use std::collections::HashMap;
+
+use super::xpath::QueryTree;
+use super::{tag_name, AttributeUsage, NamespaceMode, Tag, XmlDoc};
+
+/// Options of the xml writer
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// indent nested elements by this number of spaces, None writes compact xml
+    pub indent: Option<usize>,
+    /// start with an xml declaration
+    pub declaration: bool,
+}
+
+// split a name in Clark notation into namespace and local name
+fn split_clark_name(name: &str) -> Option<(&str, &str)> {
+    name.strip_prefix('{')?.split_once('}')
+}
+
+fn escaped(text: &str) -> String {
+    String::from_utf8_lossy(&escape(text.as_bytes())).into_owned()
+}
+
+// writes the elements of a parsed document from its tree and the tag values
+pub(super) struct XmlWriter<'a> {
+    doc: &'a XmlDoc,
+    tree: QueryTree<'a>,
+    indent: Option<usize>,
+    // the elements with attributes as parsed, with the attribute usage AddToTagName or
+    // AddToTagValue the attributes are restored from them
+    source_tags: HashMap<usize, &'a Tag>,
+    // the namespace written as default namespace, elements in it need no prefix
+    default_namespace: Option<&'a str>,
+    // the prefixes of the namespaces of names in Clark notation
+    prefixes: Vec<(&'a str, String)>,
+    xml: String,
+}
+
+impl<'a> XmlWriter<'a> {
+    pub(super) fn new(doc: &'a XmlDoc, options: &WriteOptions) -> Self {
+        let tree = QueryTree::new(doc);
+        let source_tags = match doc.options.attribute_usage {
+            AttributeUsage::AddToTagName | AttributeUsage::AddToTagValue => doc
+                .tags_n_values
+                .iter()
+                .flatten()
+                .filter(|tag| tag.attributes.is_some())
+                .map(|tag| (tag.tag_id, tag))
+                .collect(),
+            _ => HashMap::new(),
+        };
+
+        // elements in the default namespace are written without prefix, all other
+        // namespaces - and the namespaces of attributes - get a prefix of their own. Elements
+        // in no namespace rule out a default namespace.
+        let mut default_namespace = doc.default_namespace.as_deref();
+        let mut prefixes: Vec<(&str, String)> = Vec::new();
+        if doc.options.namespace_mode == NamespaceMode::Clark {
+            if doc.xml_parsed.iter().any(|(path, values)| {
+                !tree.is_attribute(values[0].0) && split_clark_name(tag_name(path)).is_none()
+            }) {
+                default_namespace = None;
+            }
+            let tag_names = doc.xml_parsed.iter().map(|(path, values)| {
+                let is_attribute = values.iter().any(|v| tree.is_attribute(v.0));
+                (tag_name(path), is_attribute)
+            });
+            let attribute_keys = match source_tags.is_empty() {
+                true => None,
+                false => doc.tags_n_values.as_ref(),
+            }
+            .into_iter()
+            .flatten()
+            .flat_map(|tag| tag.attributes.iter().flatten())
+            .map(|att| (att.key.as_str(), true));
+            for (name, is_attribute) in tag_names.chain(attribute_keys) {
+                let namespace = match split_clark_name(name) {
+                    Some((namespace, _)) => namespace,
+                    None => continue,
+                };
+                if (is_attribute || default_namespace != Some(namespace))
+                    && !prefixes.iter().any(|(ns, _)| *ns == namespace)
+                {
+                    prefixes.push((namespace, format!("ns{}", prefixes.len())));
+                }
+            }
+        }
+
+        XmlWriter {
+            doc,
+            tree,
+            indent: options.indent,
+            source_tags,
+            default_namespace,
+            prefixes,
+            xml: String::new(),
+        }
+    }
+
+    // the name to write for an element or attribute name of the tree
+    fn qualified_name(&self, name: &str, is_attribute: bool) -> String {
+        match split_clark_name(name) {
+            Some((namespace, local_name))
+                if is_attribute || self.default_namespace != Some(namespace) =>
+            {
+                match self.prefixes.iter().find(|(ns, _)| *ns == namespace) {
+                    Some((_, prefix)) => format!("{}:{}", prefix, local_name),
+                    None => local_name.to_owned(),
+                }
+            }
+            Some((_, local_name)) => local_name.to_owned(),
+            None => name.to_owned(),
+        }
+    }
+
+    fn element_name(&self, tag_id: usize) -> String {
+        // the attribute values were added to the name of the element
+        let name = match self.source_tags.get(&tag_id) {
+            Some(tag) if self.doc.options.attribute_usage == AttributeUsage::AddToTagName => {
+                tag_name(&tag.name)
+            }
+            _ => self.tree.name(tag_id),
+        };
+        self.qualified_name(name, false)
+    }
+
+    fn attributes(&self, tag_id: usize) -> Vec<(String, String)> {
+        let attributes: Vec<(String, String)> = match self.doc.options.attribute_usage {
+            AttributeUsage::AddSeparateTag => self
+                .tree
+                .attributes(tag_id, None)
+                .into_iter()
+                .map(|id| {
+                    let name = self.qualified_name(self.tree.name(id), true);
+                    (name, self.tree.value(id).to_owned())
+                })
+                .collect(),
+            AttributeUsage::AddToTagName | AttributeUsage::AddToTagValue => self
+                .source_tags
+                .get(&tag_id)
+                .and_then(|tag| tag.attributes.as_ref())
+                .map_or(Vec::new(), |attributes| {
+                    attributes
+                        .iter()
+                        .map(|att| (self.qualified_name(&att.key, true), att.value.to_owned()))
+                        .collect()
+                }),
+            AttributeUsage::Ignore => Vec::new(),
+        };
+        if Some(tag_id) != self.tree.root() {
+            return attributes;
+        }
+
+        // the namespace declarations which were not kept as attributes
+        let mut declarations = Vec::new();
+        if let Some(namespace) = self.default_namespace {
+            let element_name = self.element_name(tag_id);
+            let key = match element_name.split_once(':') {
+                Some((prefix, _)) => format!("xmlns:{}", prefix),
+                None => "xmlns".to_string(),
+            };
+            if !attributes.iter().any(|(name, _)| *name == key) {
+                declarations.push((key, namespace.to_owned()));
+            }
+        }
+        for (namespace, prefix) in self.prefixes.iter() {
+            declarations.push((format!("xmlns:{}", prefix), namespace.to_string()));
+        }
+        declarations.extend(attributes);
+        declarations
+    }
+
+    // the text value of an element, None for a node
+    fn text(&self, tag_id: usize) -> Option<String> {
+        let mut value = self.tree.value(tag_id);
+        if let Some(tag) = self.source_tags.get(&tag_id) {
+            if self.doc.options.attribute_usage == AttributeUsage::AddToTagValue {
+                // the attribute values were added in front of the value
+                let prefix: String = tag
+                    .attributes
+                    .iter()
+                    .flatten()
+                    .map(|att| format!("{}-", att.value))
+                    .collect();
+                value = value.strip_prefix(prefix.as_str()).unwrap_or(value);
+            }
+        }
+        match value {
+            "__node__" => None,
+            value if self.doc.options.empty_value.as_deref() == Some(value) => None,
+            value => Some(value.to_owned()),
+        }
+    }
+
+    fn new_line(&mut self, depth: usize) {
+        if let Some(indent) = self.indent {
+            if !self.xml.is_empty() {
+                self.xml.push('\n');
+            }
+            self.xml.push_str(&" ".repeat(indent * depth));
+        }
+    }
+
+    // an element and its content as xml, e.g. to show an element added to a document. The
+    // writer can be reused for any number of elements of the document.
+    pub(super) fn element_to_xml(&mut self, tag_id: usize) -> String {
+        self.write_element(tag_id, 0, false);
+        std::mem::take(&mut self.xml)
+    }
+
+    // write an element and its content, the content of an element with text and child
+    // elements is written without indentation so the text stays unchanged
+    fn write_element(&mut self, tag_id: usize, depth: usize, indent: bool) {
+        if indent {
+            self.new_line(depth);
+        }
+        let name = self.element_name(tag_id);
+        self.xml.push('<');
+        self.xml.push_str(&name);
+        for (key, value) in self.attributes(tag_id) {
+            self.xml
+                .push_str(&format!(" {}=\"{}\"", key, escaped(&value)));
+        }
+
+        let content = self.tree.content(tag_id);
+        let text = self.text(tag_id);
+        if content.is_empty() && text.is_none() {
+            self.xml.push_str("/>");
+            return;
+        }
+        self.xml.push('>');
+
+        let mixed = text.is_some() || content.iter().any(|&id| self.tree.is_text(id));
+        if let Some(text) = text {
+            self.xml.push_str(&escaped(&text));
+        }
+        for &child in content.iter() {
+            if self.tree.is_text(child) {
+                self.xml.push_str(&escaped(self.tree.value(child)));
+            } else {
+                self.write_element(child, depth + 1, indent && !mixed);
+            }
+        }
+        if indent && !mixed {
+            self.new_line(depth);
+        }
+        self.xml.push_str(&format!("</{}>", name));
+    }
+}
+
+impl XmlDoc {
+    /// Write the document as xml. The attributes are restored as far as the attribute
+    /// usage keeps them: completely for `AddSeparateTag`, from the parsed tags for
+    /// `AddToTagName` and `AddToTagValue`, not at all for `Ignore`. The text values of an
+    /// element with mixed content are written as stored, e.g. concatenated after its child
+    /// elements for `MixedContent::Concatenate`.
+    pub fn to_xml(&self, options: &WriteOptions) -> String {
+        let mut writer = XmlWriter::new(self, options);
+        if options.declaration {
+            writer
+                .xml
+                .push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+        }
+        if let Some(root) = writer.tree.root() {
+            writer.write_element(root, 0, true);
+        }
+        writer.xml
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{MixedContent, ParseOptions};
+    use super::*;
+
+    fn round_trip(xml: &str, options: &ParseOptions) -> String {
+        let doc = XmlDoc::with_options(1, xml, options).unwrap();
+        doc.to_xml(&WriteOptions::default())
+    }
+
+    #[test]
+    fn write_attributes_by_usage() {
+        let xml = r#"<Doc id="1"><Amt Ccy="EUR" Dt="x">10</Amt><Hdr a="&lt;&amp;"><Id>1</Id></Hdr><Empty b="2"/></Doc>"#;
+        let usage = |attribute_usage| ParseOptions {
+            attribute_usage,
+            ..ParseOptions::default()
+        };
+        assert_eq!(round_trip(xml, &usage(AttributeUsage::AddSeparateTag)), xml);
+        assert_eq!(round_trip(xml, &usage(AttributeUsage::AddToTagName)), xml);
+        assert_eq!(round_trip(xml, &usage(AttributeUsage::AddToTagValue)), xml);
+        assert_eq!(
+            round_trip(xml, &usage(AttributeUsage::Ignore)),
+            "<Doc><Amt>10</Amt><Hdr><Id>1</Id></Hdr><Empty/></Doc>"
+        );
+    }
+
+    #[test]
+    fn write_namespaces_and_mixed_content() {
+        let xml = r#"<Document xmlns="urn:a" xmlns:b="urn:b"><b:Id b:x="1">1</b:Id><Nm>A</Nm></Document>"#;
+        for namespace_mode in [NamespaceMode::LocalName, NamespaceMode::Clark] {
+            let options = ParseOptions {
+                namespace_mode,
+                ..ParseOptions::default()
+            };
+            let written = round_trip(xml, &options);
+            let doc = XmlDoc::with_options(1, &written, &options).unwrap();
+            assert_eq!(doc.default_namespace.as_deref(), Some("urn:a"));
+            assert_eq!(
+                doc.xml_parsed,
+                XmlDoc::with_options(1, xml, &options).unwrap().xml_parsed
+            );
+        }
+
+        // an element in no namespace below a root in a namespace
+        let xml = r#"<a:Doc xmlns:a="urn:a"><Id>1</Id></a:Doc>"#;
+        let options = ParseOptions {
+            namespace_mode: NamespaceMode::Clark,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            round_trip(xml, &options),
+            r#"<ns0:Doc xmlns:ns0="urn:a"><Id>1</Id></ns0:Doc>"#
+        );
+
+        let xml = "<P>see <B>this</B> note<I/></P>";
+        let options = ParseOptions {
+            mixed_content: MixedContent::Separate,
+            ignore_whitespace: false,
+            ..ParseOptions::default()
+        };
+        assert_eq!(round_trip(xml, &options), xml);
+    }
+
+    #[test]
+    fn round_trip_pain_008_sample() {
+        let xml = include_str!("../testdata/pain.008.001.02.xml");
+        let usages = [
+            AttributeUsage::AddSeparateTag,
+            AttributeUsage::AddToTagName,
+            AttributeUsage::AddToTagValue,
+            AttributeUsage::Ignore,
+        ];
+        for attribute_usage in usages {
+            for namespace_mode in [NamespaceMode::LocalName, NamespaceMode::Clark] {
+                let options = ParseOptions {
+                    attribute_usage,
+                    namespace_mode,
+                    ..ParseOptions::default()
+                };
+                let doc = XmlDoc::with_options(1, xml, &options).unwrap();
+                for indent in [None, Some(2)] {
+                    let written = doc.to_xml(&WriteOptions {
+                        indent,
+                        declaration: true,
+                    });
+                    let reparsed = XmlDoc::with_options(1, &written, &options).unwrap();
+                    assert_eq!(
+                        reparsed.xml_parsed, doc.xml_parsed,
+                        "{:?} {:?} indent {:?}",
+                        attribute_usage, namespace_mode, indent
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn write_pretty_and_edited() {
+        let xml = "<A><B><C>1</C><D/></B><E><F>2</F>t</E></A>";
+        let mut doc = XmlDoc::with_options(1, xml, &ParseOptions::default()).unwrap();
+        let c_id = doc.tag_ids("A.B.C")[0];
+        assert!(doc.set_value(c_id, "x & y"));
+        assert!(!doc.set_value(999, "none"));
+        let options = WriteOptions {
+            indent: Some(2),
+            declaration: true,
+        };
+        assert_eq!(
+            doc.to_xml(&options),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<A>\n  <B>\n    <C>x &amp; y</C>\n    \
+            <D/>\n  </B>\n  <E><F>2</F>t</E>\n</A>"
+        );
+
+        // the tags as parsed follow the edits, also of the attributes
+        let xml = r#"<Doc><Amt Ccy="EUR">10</Amt></Doc>"#;
+        for attribute_usage in [
+            AttributeUsage::AddSeparateTag,
+            AttributeUsage::AddToTagValue,
+        ] {
+            let options = ParseOptions {
+                attribute_usage,
+                ..ParseOptions::default()
+            };
+            let mut doc = XmlDoc::with_options(1, xml, &options).unwrap();
+            if attribute_usage == AttributeUsage::AddSeparateTag {
+                let amt_id = doc.tag_ids("Doc.Amt.Amt")[0];
+                assert!(doc.set_value(amt_id, "20"));
+                let ccy_id = doc.tag_ids("Doc.Amt.Ccy")[0];
+                assert!(doc.set_value(ccy_id, "CHF"));
+            } else {
+                let amt_id = doc.tag_ids("Doc.Amt")[0];
+                assert!(doc.set_value(amt_id, "EUR-20"));
+            }
+            let amt = doc
+                .tags_n_values
+                .iter()
+                .flatten()
+                .find(|tag| tag.name == "Doc.Amt")
+                .unwrap();
+            assert_eq!(amt.value, "20");
+            let ccy = amt.attributes.as_ref().unwrap()[0].value.as_str();
+            match attribute_usage {
+                AttributeUsage::AddSeparateTag => assert_eq!(ccy, "CHF"),
+                _ => assert_eq!(ccy, "EUR"),
+            }
+            assert!(doc.to_xml(&WriteOptions::default()).contains(">20</Amt>"));
+        }
+    }
+}
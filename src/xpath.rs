@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{tag_name, XmlDoc, TEXT_TAG_NAME};
+
+// the document node the first step of a query starts from, the tag ids start at 1
+const DOCUMENT_NODE: usize = 0;
+
+// what a step of a query selects
+#[derive(Debug, Clone, PartialEq)]
+enum NodeTest {
+    // the child elements with the name
+    Name(String),
+    // all child elements, `*`
+    AnyElement,
+    // the text values, `text()`
+    Text,
+    // the attributes with the name or all attributes, `@name` or `@*`
+    Attribute(Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Predicate {
+    // `[n]`, 1 is the first node
+    Position(usize),
+    // `[last()]`
+    Last,
+    // `[@name]` or `[@name='value']`
+    Attribute(String, Option<String>),
+    // `[text()='value']`
+    Text(String),
+    // `[name]` or `[name='value']`, a child element with the name and text value
+    Child(String, Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Step {
+    // the step follows `//` and selects from the descendants of the context nodes
+    descendants: bool,
+    test: NodeTest,
+    predicates: Vec<Predicate>,
+}
+
+/// A query in a subset of XPath over a parsed document.
+///
+/// Supported are the child (`/`) and descendant (`//`) axes, element names and `*`,
+/// `text()` and `@name` or `@*` as the last step and the predicates `[n]`, `[last()]`,
+/// `[@name]`, `[@name='value']`, `[text()='value']`, `[name]` and `[name='value']`, e.g.
+/// `//DrctDbtTxInf/InstdAmt[@Ccy='EUR']/text()`. Names are the tag names of the parse
+/// options, e.g. `{namespace}name` in Clark notation. Attributes can be selected for the
+/// attribute usage `AddSeparateTag` only.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XPath {
+    steps: Vec<Step>,
+}
+
+impl XPath {
+    /// Parse a query, the error describes why the query is not supported
+    pub fn parse(xpath: &str) -> Result<Self, String> {
+        let mut parser = PathParser { xpath, pos: 0 };
+        let mut steps = Vec::new();
+        // a relative query starts at the document node as well
+        let mut descendants = parser.eat("//");
+        if !descendants {
+            parser.eat("/");
+        }
+        loop {
+            let step = parser.step(descendants)?;
+            let is_last_step = !matches!(step.test, NodeTest::Name(_) | NodeTest::AnyElement);
+            steps.push(step);
+            if parser.at_end() {
+                break;
+            }
+            if is_last_step {
+                return Err(parser.error("text() and attributes must be the last step"));
+            }
+            descendants = if parser.eat("//") {
+                true
+            } else if parser.eat("/") {
+                false
+            } else {
+                return Err(parser.error("expected '/'"));
+            };
+        }
+        Ok(XPath { steps })
+    }
+}
+
+// a recursive descent parser of the supported XPath subset
+struct PathParser<'a> {
+    xpath: &'a str,
+    pos: usize,
+}
+
+impl<'a> PathParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.xpath[self.pos..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.xpath.len()
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.xpath.len() - self.rest().trim_start().len();
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!(
+            "invalid xpath '{}' at position {}: {}",
+            self.xpath, self.pos, message
+        )
+    }
+
+    // an element or attribute name, a namespace in braces (Clark notation) may contain any
+    // character but '}'
+    fn name(&mut self) -> Result<String, String> {
+        let start = self.pos;
+        let bytes = self.xpath.as_bytes();
+        while self.pos < bytes.len() {
+            match bytes[self.pos] {
+                b'{' => match self.rest().find('}') {
+                    Some(end) => self.pos += end + 1,
+                    None => return Err(self.error("unclosed '{'")),
+                },
+                b'/' | b'[' | b']' | b'=' | b'@' | b'\'' | b'"' | b'(' | b')' | b'*' => break,
+                b if b.is_ascii_whitespace() => break,
+                _ => self.pos += 1,
+            }
+        }
+        if self.pos == start {
+            return Err(self.error("expected a name"));
+        }
+        Ok(self.xpath[start..self.pos].to_owned())
+    }
+
+    fn step(&mut self, descendants: bool) -> Result<Step, String> {
+        let test = if self.eat("text()") {
+            NodeTest::Text
+        } else if self.eat("@*") {
+            NodeTest::Attribute(None)
+        } else if self.eat("@") {
+            NodeTest::Attribute(Some(self.name()?))
+        } else if self.eat("*") {
+            NodeTest::AnyElement
+        } else {
+            NodeTest::Name(self.name()?)
+        };
+
+        let mut predicates = Vec::new();
+        while self.eat("[") {
+            predicates.push(self.predicate()?);
+            self.skip_whitespace();
+            if !self.eat("]") {
+                return Err(self.error("expected ']'"));
+            }
+        }
+        Ok(Step {
+            descendants,
+            test,
+            predicates,
+        })
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, String> {
+        self.skip_whitespace();
+        if self.eat("last()") {
+            return Ok(Predicate::Last);
+        }
+        let digits = self.rest().bytes().take_while(u8::is_ascii_digit).count();
+        if digits > 0 {
+            let position: usize = self.rest()[..digits]
+                .parse()
+                .map_err(|_| self.error("invalid position"))?;
+            if position == 0 {
+                return Err(self.error("positions start at 1"));
+            }
+            self.pos += digits;
+            return Ok(Predicate::Position(position));
+        }
+        if self.eat("text()") {
+            return match self.comparison()? {
+                Some(value) => Ok(Predicate::Text(value)),
+                None => Err(self.error("expected '=' after text()")),
+            };
+        }
+        if self.eat("@") {
+            let name = self.name()?;
+            return Ok(Predicate::Attribute(name, self.comparison()?));
+        }
+        let name = self.name()?;
+        Ok(Predicate::Child(name, self.comparison()?))
+    }
+
+    // the optional `='value'` of a predicate
+    fn comparison(&mut self) -> Result<Option<String>, String> {
+        self.skip_whitespace();
+        if !self.eat("=") {
+            return Ok(None);
+        }
+        self.skip_whitespace();
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('\'' | '"')) => quote,
+            _ => return Err(self.error("expected a quoted value")),
+        };
+        self.pos += 1;
+        match self.rest().find(quote) {
+            Some(end) => {
+                let value = self.rest()[..end].to_owned();
+                self.pos += end + 1;
+                Ok(Some(value))
+            }
+            None => Err(self.error("unclosed quoted value")),
+        }
+    }
+}
+
+// the tree of a parsed document as needed to evaluate queries and to write the document
+pub(super) struct QueryTree<'a> {
+    doc: &'a XmlDoc,
+    // path and value of the tags by their tag id
+    tags: HashMap<usize, (&'a str, &'a str)>,
+    // the child tags of the tags in document order
+    children: HashMap<usize, Vec<usize>>,
+    // the ids of all attribute tags
+    attributes: HashSet<usize>,
+}
+
+impl<'a> QueryTree<'a> {
+    pub(super) fn new(doc: &'a XmlDoc) -> Self {
+        let tags = doc
+            .xml_parsed
+            .iter()
+            .flat_map(|(path, values)| {
+                values
+                    .iter()
+                    .map(move |v| (v.0, (path.as_str(), v.2.as_str())))
+            })
+            .collect();
+        let mut children = HashMap::new();
+        if doc.fstar.has_root() {
+            children.insert(DOCUMENT_NODE, doc.node_tag_id(0).into_iter().collect());
+        }
+        for node in 0..doc.fstar.num_nodes {
+            if let Some(tag_id) = doc.node_tag_id(node) {
+                let child_ids = doc
+                    .fstar
+                    .children(node)
+                    .iter()
+                    .filter_map(|&child| doc.node_tag_id(child))
+                    .collect();
+                children.insert(tag_id, child_ids);
+            }
+        }
+        let attributes = doc.attribute_ids.values().flatten().copied().collect();
+        QueryTree {
+            doc,
+            tags,
+            children,
+            attributes,
+        }
+    }
+
+    pub(super) fn name(&self, tag_id: usize) -> &'a str {
+        self.tags
+            .get(&tag_id)
+            .map_or("", |(path, _)| tag_name(path))
+    }
+
+    pub(super) fn value(&self, tag_id: usize) -> &'a str {
+        self.tags.get(&tag_id).map_or("", |(_, value)| value)
+    }
+
+    pub(super) fn is_text(&self, tag_id: usize) -> bool {
+        self.name(tag_id) == TEXT_TAG_NAME
+    }
+
+    pub(super) fn is_attribute(&self, tag_id: usize) -> bool {
+        self.attributes.contains(&tag_id)
+    }
+
+    // the root element of the document
+    pub(super) fn root(&self) -> Option<usize> {
+        self.children.get(&DOCUMENT_NODE)?.first().copied()
+    }
+
+    // the child elements and text tags of a tag in document order, without its attributes
+    pub(super) fn content(&self, tag_id: usize) -> Vec<usize> {
+        self.children.get(&tag_id).map_or(Vec::new(), |children| {
+            children
+                .iter()
+                .copied()
+                .filter(|id| !self.attributes.contains(id))
+                .collect()
+        })
+    }
+
+    // the child elements of a tag, without its attributes and text values
+    fn elements(&self, tag_id: usize) -> Vec<usize> {
+        self.children.get(&tag_id).map_or(Vec::new(), |children| {
+            children
+                .iter()
+                .copied()
+                .filter(|id| !self.attributes.contains(id) && !self.is_text(*id))
+                .collect()
+        })
+    }
+
+    // the tags holding the text values of an element: the element itself if it has a single
+    // text value, else its text tags
+    fn texts(&self, tag_id: usize) -> Vec<usize> {
+        if tag_id == DOCUMENT_NODE {
+            return Vec::new();
+        }
+        let mut texts = Vec::new();
+        if self.value(tag_id) != "__node__" {
+            texts.push(tag_id);
+        }
+        if let Some(children) = self.children.get(&tag_id) {
+            texts.extend(children.iter().copied().filter(|&id| self.is_text(id)));
+        }
+        texts
+    }
+
+    pub(super) fn attributes(&self, tag_id: usize, name: Option<&str>) -> Vec<usize> {
+        self.doc
+            .attribute_ids
+            .get(&tag_id)
+            .map_or(Vec::new(), |attributes| {
+                attributes
+                    .iter()
+                    .copied()
+                    .filter(|&id| name.is_none_or(|name| self.name(id) == name))
+                    .collect()
+            })
+    }
+
+    // the element and all its descendant elements in document order
+    fn descendants_or_self(&self, tag_id: usize) -> Vec<usize> {
+        let mut descendants = Vec::new();
+        let mut stack = vec![tag_id];
+        while let Some(id) = stack.pop() {
+            descendants.push(id);
+            stack.extend(self.elements(id).into_iter().rev());
+        }
+        descendants
+    }
+
+    fn has_text(&self, tag_id: usize, value: &str) -> bool {
+        self.texts(tag_id).iter().any(|&id| self.value(id) == value)
+    }
+
+    fn matches(&self, tag_id: usize, predicate: &Predicate) -> bool {
+        match predicate {
+            Predicate::Attribute(name, value) => self
+                .attributes(tag_id, Some(name))
+                .iter()
+                .any(|&id| value.as_ref().is_none_or(|value| self.value(id) == value)),
+            Predicate::Text(value) => self.has_text(tag_id, value),
+            Predicate::Child(name, value) => self.elements(tag_id).iter().any(|&id| {
+                self.name(id) == name && value.as_ref().is_none_or(|value| self.has_text(id, value))
+            }),
+            Predicate::Position(_) | Predicate::Last => true,
+        }
+    }
+
+    fn select(&self, context: &[usize], step: &Step) -> Vec<usize> {
+        let mut selected = Vec::new();
+        for &node in context {
+            let parents = match step.descendants {
+                true => self.descendants_or_self(node),
+                false => vec![node],
+            };
+            for parent in parents {
+                let mut candidates = match &step.test {
+                    NodeTest::Name(name) => self
+                        .elements(parent)
+                        .into_iter()
+                        .filter(|&id| self.name(id) == name)
+                        .collect(),
+                    NodeTest::AnyElement => self.elements(parent),
+                    NodeTest::Text => self.texts(parent),
+                    NodeTest::Attribute(name) => self.attributes(parent, name.as_deref()),
+                };
+                // the positions are the positions within the nodes passing the previous
+                // predicates
+                for predicate in &step.predicates {
+                    candidates = match predicate {
+                        Predicate::Position(position) => {
+                            candidates.get(position - 1).copied().into_iter().collect()
+                        }
+                        Predicate::Last => candidates.last().copied().into_iter().collect(),
+                        _ => candidates
+                            .into_iter()
+                            .filter(|&id| self.matches(id, predicate))
+                            .collect(),
+                    };
+                }
+                selected.extend(candidates);
+            }
+        }
+        selected.sort_unstable();
+        selected.dedup();
+        selected
+    }
+}
+
+impl XmlDoc {
+    /// Evaluate a query in the supported subset of XPath, see [`XPath`]. Returns the ids and
+    /// values of the selected tags in document order.
+    pub fn query(&self, xpath: &str) -> Result<Vec<(usize, &str)>, String> {
+        let xpath = XPath::parse(xpath)?;
+        let tree = QueryTree::new(self);
+        let mut selected = vec![DOCUMENT_NODE];
+        for step in &xpath.steps {
+            selected = tree.select(&selected, step);
+        }
+        Ok(selected
+            .into_iter()
+            .map(|tag_id| (tag_id, tree.value(tag_id)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{AttributeUsage, NamespaceMode, ParseOptions};
+    use super::*;
+
+    const PAYMENTS: &str = r#"<Document><PmtInf><Id>1</Id><Tx><Amt Ccy="EUR">10</Amt><Nm>A</Nm></Tx><Tx><Amt Ccy="CHF">20</Amt><Nm>B</Nm></Tx><Tx><Amt Ccy="EUR">30</Amt><Nm>C</Nm></Tx></PmtInf><PmtInf><Id>2</Id><Tx><Amt Ccy="EUR">40</Amt><Nm>D</Nm></Tx></PmtInf></Document>"#;
+
+    fn values(doc: &XmlDoc, xpath: &str) -> Vec<String> {
+        doc.query(xpath)
+            .unwrap()
+            .into_iter()
+            .map(|(_, value)| value.to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn query_axes_and_predicates() {
+        let doc = XmlDoc::with_options(1, PAYMENTS, &ParseOptions::default()).unwrap();
+
+        assert_eq!(values(&doc, "/Document/PmtInf/Id"), vec!["1", "2"]);
+        assert_eq!(values(&doc, "Document/PmtInf/Id/text()"), vec!["1", "2"]);
+        assert_eq!(values(&doc, "//Tx/Nm"), vec!["A", "B", "C", "D"]);
+        assert_eq!(values(&doc, "//Tx[1]/Nm"), vec!["A", "D"]);
+        assert_eq!(values(&doc, "//Tx[last()]/Nm"), vec!["C", "D"]);
+        assert_eq!(values(&doc, "/Document/PmtInf[2]//Nm"), vec!["D"]);
+        assert_eq!(values(&doc, "//PmtInf[Id='2']/Tx/Nm"), vec!["D"]);
+        assert_eq!(values(&doc, "//Tx[Nm='B']/Amt/@Ccy"), vec!["CHF"]);
+        assert_eq!(values(&doc, "//Amt[@Ccy='EUR']"), vec!["10", "30", "40"]);
+        // the position is the position within the nodes passing the previous predicate
+        assert_eq!(values(&doc, "//Tx[Amt = \"30\"][1]/Nm"), vec!["C"]);
+        assert_eq!(values(&doc, "/Document/PmtInf[1]/Tx[Nm][2]/Nm"), vec!["B"]);
+        assert_eq!(values(&doc, "//Tx[Amt][Nm]/Nm[text()='C']"), vec!["C"]);
+        assert_eq!(values(&doc, "//Amt/@*"), vec!["EUR", "CHF", "EUR", "EUR"]);
+        assert_eq!(values(&doc, "/Document/*/Id"), vec!["1", "2"]);
+        assert!(values(&doc, "/PmtInf").is_empty());
+        assert!(values(&doc, "//Amt[@Ccy='USD']").is_empty());
+
+        let (amt_id, _) = doc.query("//Amt[@Ccy='CHF']").unwrap()[0];
+        assert_eq!(
+            doc.find_tag(amt_id),
+            Some(("Document.PmtInf.Tx.Amt.Amt", "20"))
+        );
+    }
+
+    #[test]
+    fn query_text_and_names() {
+        let xml = "<n:P xmlns:n=\"urn:x\">see <n:B>this</n:B> note</n:P>";
+        let options = ParseOptions {
+            attribute_usage: AttributeUsage::Ignore,
+            ..ParseOptions::default()
+        };
+        let doc = XmlDoc::with_options(1, xml, &options).unwrap();
+        assert_eq!(values(&doc, "/n:P/text()"), vec!["see  note"]);
+        assert_eq!(values(&doc, "//n:B"), vec!["this"]);
+        assert_eq!(values(&doc, "/n:P/*"), vec!["this"]);
+
+        let options = ParseOptions {
+            namespace_mode: NamespaceMode::Clark,
+            ..ParseOptions::default()
+        };
+        let doc = XmlDoc::with_options(1, xml, &options).unwrap();
+        assert_eq!(values(&doc, "/{urn:x}P/{urn:x}B"), vec!["this"]);
+    }
+
+    #[test]
+    fn invalid_queries() {
+        for (xpath, reason) in [
+            ("", "expected a name"),
+            ("/A/text()/B", "must be the last step"),
+            ("/A[0]", "positions start at 1"),
+            ("/A[@x='1]", "unclosed quoted value"),
+            ("/A[@x=1]", "expected a quoted value"),
+            ("/A[1", "expected ']'"),
+            ("/{urn:x/A", "unclosed '{'"),
+            ("/A[text()]", "expected '=' after text()"),
+        ] {
+            let err = XPath::parse(xpath).unwrap_err();
+            assert!(err.contains(reason), "{}: {}", xpath, err);
+        }
+    }
+}
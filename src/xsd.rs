@@ -0,0 +1,2365 @@
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::error::{UtilrsError, UtilrsResult};
+
+const XSD_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema";
+const XSI_NAMESPACE: &str = "http://www.w3.org/2001/XMLSchema-instance";
+const UNBOUNDED: usize = usize::MAX;
+// number of enumeration values listed in a violation
+const MAX_LISTED_VALUES: usize = 10;
+
+/*
+Elements of documents and schemas
+*/
+
+// an element of a document or schema as needed for the validation
+#[derive(Debug, Default)]
+struct Element {
+    namespace: Option<String>,
+    name: String,
+    // namespace, local name and value of the attributes, without namespace declarations
+    attributes: Vec<(Option<String>, String, String)>,
+    // the prefixes declared on the element, "" for the default namespace
+    declarations: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    // the value of an attribute without namespace
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(namespace, key, _)| namespace.is_none() && key == name)
+            .map(|(_, _, value)| value.as_str())
+    }
+
+    // the child elements of a schema element, without annotations
+    fn schema_children(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter(|child| {
+            child.namespace.as_deref() == Some(XSD_NAMESPACE) && child.name != "annotation"
+        })
+    }
+
+    // the name of the element in messages, in Clark notation if it is not in the namespace
+    // of the schema
+    fn label(&self, target_namespace: Option<&str>) -> String {
+        match self.namespace.as_deref() {
+            Some(namespace) if Some(namespace) != target_namespace => {
+                format!("{{{}}}{}", namespace, self.name)
+            }
+            _ => self.name.to_owned(),
+        }
+    }
+}
+
+fn read_elements(xml: &str) -> Result<Element, String> {
+    let mut reader = Reader::from_str(xml);
+    let mut buf = Vec::new();
+    let mut ns_buf = Vec::new();
+    let mut open_elements: Vec<Element> = Vec::new();
+    let mut root = None;
+    let error = |reader: &Reader<&[u8]>, message: String| {
+        format!("{} at position {}", message, reader.buffer_position())
+    };
+    loop {
+        let (namespace, event) = reader
+            .read_namespaced_event(&mut buf, &mut ns_buf)
+            .map_err(|e| error(&reader, e.to_string()))?;
+        let namespace = namespace.map(|ns| String::from_utf8_lossy(ns).into_owned());
+        let closed = match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let mut element = Element {
+                    namespace,
+                    name: String::from_utf8_lossy(e.local_name()).into_owned(),
+                    ..Element::default()
+                };
+                for attribute in e.attributes() {
+                    let attribute = attribute.map_err(|e| error(&reader, e.to_string()))?;
+                    let value = attribute
+                        .unescape_and_decode_value(&reader)
+                        .map_err(|e| error(&reader, e.to_string()))?;
+                    let key = String::from_utf8_lossy(attribute.key).into_owned();
+                    if key == "xmlns" {
+                        element.declarations.push((String::new(), value));
+                    } else if let Some(prefix) = key.strip_prefix("xmlns:") {
+                        element.declarations.push((prefix.to_owned(), value));
+                    } else {
+                        let (namespace, name) = reader.attribute_namespace(attribute.key, &ns_buf);
+                        element.attributes.push((
+                            namespace.map(|ns| String::from_utf8_lossy(ns).into_owned()),
+                            String::from_utf8_lossy(name).into_owned(),
+                            value,
+                        ));
+                    }
+                }
+                match event {
+                    Event::Empty(_) => Some(element),
+                    _ => {
+                        open_elements.push(element);
+                        None
+                    }
+                }
+            }
+            Event::End(_) => open_elements.pop(),
+            Event::Text(ref e) | Event::CData(ref e) => {
+                if let Some(element) = open_elements.last_mut() {
+                    let text = e
+                        .unescape_and_decode(&reader)
+                        .map_err(|e| error(&reader, e.to_string()))?;
+                    element.text.push_str(&text);
+                }
+                None
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        if let Some(element) = closed {
+            match open_elements.last_mut() {
+                Some(parent) => parent.children.push(element),
+                None => root = Some(element),
+            }
+        }
+        buf.clear();
+    }
+    root.ok_or_else(|| "no root element".to_string())
+}
+
+/*
+Regular expressions of pattern facets
+*/
+
+// the characters of XML names, \i matches the first character of a name and \c any other
+const NAME_START_CHARS: &str = r":A-Z_a-z\x{C0}-\x{D6}\x{D8}-\x{F6}\x{F8}-\x{2FF}\x{370}-\x{37D}\x{37F}-\x{1FFF}\x{200C}-\x{200D}\x{2070}-\x{218F}\x{2C00}-\x{2FEF}\x{3001}-\x{D7FF}\x{F900}-\x{FDCF}\x{FDF0}-\x{FFFD}\x{10000}-\x{EFFFF}";
+const NAME_CHARS: &str = r"\-.0-9\x{B7}\x{300}-\x{36F}\x{203F}-\x{2040}";
+
+// the Unicode blocks of the escapes \p{IsBlock}, with the names used by XML Schema
+const BLOCKS: &[(&str, u32, u32)] = &[
+    ("BasicLatin", 0x0000, 0x007F),
+    ("Latin-1Supplement", 0x0080, 0x00FF),
+    ("LatinExtended-A", 0x0100, 0x017F),
+    ("LatinExtended-B", 0x0180, 0x024F),
+    ("IPAExtensions", 0x0250, 0x02AF),
+    ("SpacingModifierLetters", 0x02B0, 0x02FF),
+    ("CombiningDiacriticalMarks", 0x0300, 0x036F),
+    ("Greek", 0x0370, 0x03FF),
+    ("Cyrillic", 0x0400, 0x04FF),
+    ("Armenian", 0x0530, 0x058F),
+    ("Hebrew", 0x0590, 0x05FF),
+    ("Arabic", 0x0600, 0x06FF),
+    ("Syriac", 0x0700, 0x074F),
+    ("Thaana", 0x0780, 0x07BF),
+    ("Devanagari", 0x0900, 0x097F),
+    ("Bengali", 0x0980, 0x09FF),
+    ("Gurmukhi", 0x0A00, 0x0A7F),
+    ("Gujarati", 0x0A80, 0x0AFF),
+    ("Oriya", 0x0B00, 0x0B7F),
+    ("Tamil", 0x0B80, 0x0BFF),
+    ("Telugu", 0x0C00, 0x0C7F),
+    ("Kannada", 0x0C80, 0x0CFF),
+    ("Malayalam", 0x0D00, 0x0D7F),
+    ("Sinhala", 0x0D80, 0x0DFF),
+    ("Thai", 0x0E00, 0x0E7F),
+    ("Lao", 0x0E80, 0x0EFF),
+    ("Tibetan", 0x0F00, 0x0FFF),
+    ("Myanmar", 0x1000, 0x109F),
+    ("Georgian", 0x10A0, 0x10FF),
+    ("HangulJamo", 0x1100, 0x11FF),
+    ("Ethiopic", 0x1200, 0x137F),
+    ("Cherokee", 0x13A0, 0x13FF),
+    ("UnifiedCanadianAboriginalSyllabics", 0x1400, 0x167F),
+    ("Ogham", 0x1680, 0x169F),
+    ("Runic", 0x16A0, 0x16FF),
+    ("Khmer", 0x1780, 0x17FF),
+    ("Mongolian", 0x1800, 0x18AF),
+    ("LatinExtendedAdditional", 0x1E00, 0x1EFF),
+    ("GreekExtended", 0x1F00, 0x1FFF),
+    ("GeneralPunctuation", 0x2000, 0x206F),
+    ("SuperscriptsandSubscripts", 0x2070, 0x209F),
+    ("CurrencySymbols", 0x20A0, 0x20CF),
+    ("CombiningMarksforSymbols", 0x20D0, 0x20FF),
+    ("LetterlikeSymbols", 0x2100, 0x214F),
+    ("NumberForms", 0x2150, 0x218F),
+    ("Arrows", 0x2190, 0x21FF),
+    ("MathematicalOperators", 0x2200, 0x22FF),
+    ("MiscellaneousTechnical", 0x2300, 0x23FF),
+    ("ControlPictures", 0x2400, 0x243F),
+    ("OpticalCharacterRecognition", 0x2440, 0x245F),
+    ("EnclosedAlphanumerics", 0x2460, 0x24FF),
+    ("BoxDrawing", 0x2500, 0x257F),
+    ("BlockElements", 0x2580, 0x259F),
+    ("GeometricShapes", 0x25A0, 0x25FF),
+    ("MiscellaneousSymbols", 0x2600, 0x26FF),
+    ("Dingbats", 0x2700, 0x27BF),
+    ("BraillePatterns", 0x2800, 0x28FF),
+    ("CJKRadicalsSupplement", 0x2E80, 0x2EFF),
+    ("KangxiRadicals", 0x2F00, 0x2FDF),
+    ("IdeographicDescriptionCharacters", 0x2FF0, 0x2FFF),
+    ("CJKSymbolsandPunctuation", 0x3000, 0x303F),
+    ("Hiragana", 0x3040, 0x309F),
+    ("Katakana", 0x30A0, 0x30FF),
+    ("Bopomofo", 0x3100, 0x312F),
+    ("HangulCompatibilityJamo", 0x3130, 0x318F),
+    ("Kanbun", 0x3190, 0x319F),
+    ("BopomofoExtended", 0x31A0, 0x31BF),
+    ("EnclosedCJKLettersandMonths", 0x3200, 0x32FF),
+    ("CJKCompatibility", 0x3300, 0x33FF),
+    ("CJKUnifiedIdeographsExtensionA", 0x3400, 0x4DB5),
+    ("CJKUnifiedIdeographs", 0x4E00, 0x9FFF),
+    ("YiSyllables", 0xA000, 0xA48F),
+    ("YiRadicals", 0xA490, 0xA4CF),
+    ("HangulSyllables", 0xAC00, 0xD7A3),
+    ("PrivateUse", 0xE000, 0xF8FF),
+    ("CJKCompatibilityIdeographs", 0xF900, 0xFAFF),
+    ("AlphabeticPresentationForms", 0xFB00, 0xFB4F),
+    ("ArabicPresentationForms-A", 0xFB50, 0xFDFF),
+    ("CombiningHalfMarks", 0xFE20, 0xFE2F),
+    ("CJKCompatibilityForms", 0xFE30, 0xFE4F),
+    ("SmallFormVariants", 0xFE50, 0xFE6F),
+    ("ArabicPresentationForms-B", 0xFE70, 0xFEFE),
+    ("HalfwidthandFullwidthForms", 0xFF00, 0xFFEF),
+    ("Specials", 0xFFF0, 0xFFFF),
+    ("OldItalic", 0x10300, 0x1032F),
+    ("Gothic", 0x10330, 0x1034F),
+    ("Deseret", 0x10400, 0x1044F),
+    ("ByzantineMusicalSymbols", 0x1D000, 0x1D0FF),
+    ("MusicalSymbols", 0x1D100, 0x1D1FF),
+    ("MathematicalAlphanumericSymbols", 0x1D400, 0x1D7FF),
+    ("CJKUnifiedIdeographsExtensionB", 0x20000, 0x2A6D6),
+    ("CJKCompatibilityIdeographsSupplement", 0x2F800, 0x2FA1F),
+    ("Tags", 0xE0000, 0xE007F),
+];
+
+// a character class of the characters in `set`, or of all others for `complement`
+fn char_class(set: &str, complement: bool) -> String {
+    match complement {
+        true => format!("[^{}]", set),
+        false => format!("[{}]", set),
+    }
+}
+
+// translate the escape of the character `c` of an XSD regular expression, the name of a
+// \p{...} escape is read from `chars`. The translation is valid inside and outside of a
+// character class.
+fn translate_escape(
+    c: char,
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+) -> Result<String, String> {
+    Ok(match c {
+        'n' | 'r' | 't' | '\\' | '|' | '.' | '?' | '*' | '+' | '(' | ')' | '{' | '}' | '-'
+        | '[' | ']' | '^' => format!("\\{}", c),
+        'd' | 'D' => format!("\\{}", c),
+        's' | 'S' => char_class(r" \t\n\r", c == 'S'),
+        'i' | 'I' => char_class(NAME_START_CHARS, c == 'I'),
+        'c' | 'C' => char_class(&format!("{}{}", NAME_START_CHARS, NAME_CHARS), c == 'C'),
+        // all characters except punctuation, separators and other characters
+        'w' | 'W' => char_class(r"\p{P}\p{Z}\p{C}", c == 'w'),
+        'p' | 'P' => {
+            if chars.next() != Some('{') {
+                return Err(format!("'\\{}' without a property name", c));
+            }
+            let name: String = chars.by_ref().take_while(|c| *c != '}').collect();
+            match name.strip_prefix("Is") {
+                Some(block) => {
+                    let (_, first, last) = BLOCKS
+                        .iter()
+                        .find(|(name, _, _)| *name == block)
+                        .ok_or_else(|| format!("the block '{}' is not supported", block))?;
+                    char_class(&format!("\\x{{{:X}}}-\\x{{{:X}}}", first, last), c == 'P')
+                }
+                // a general category, the regex crate uses the same names
+                None => format!("\\{}{{{}}}", c, name),
+            }
+        }
+        c => return Err(format!("the escape '\\{}' is not supported", c)),
+    })
+}
+
+// translate an XSD regular expression to the syntax of the regex crate. An XSD expression
+// matches the whole value, ^ and $ are ordinary characters, . matches all characters but
+// line breaks and a character class can be subtracted from a class with -[...].
+fn translate_pattern(pattern: &str) -> Result<String, String> {
+    // each class is nested in another one, so a subtraction replaces the inner class by the
+    // set difference of the classes: [a-z-[aeiou]] is [[a-z]--[[aeiou]]]
+    fn open_class(regex: &mut String, chars: &mut std::iter::Peekable<std::str::Chars>) {
+        regex.push_str("[[");
+        if chars.next_if_eq(&'^').is_some() {
+            regex.push('^');
+        }
+    }
+
+    let mut regex = String::from("^(?:");
+    // the open character classes, true for a class another one is subtracted from
+    let mut classes: Vec<bool> = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        let in_class = !classes.is_empty();
+        match c {
+            '\\' => {
+                let escaped = chars.next().ok_or("the pattern ends with '\\'")?;
+                regex.push_str(&translate_escape(escaped, &mut chars)?);
+            }
+            '[' if in_class => return Err("'[' in a character class".to_string()),
+            '[' => {
+                classes.push(false);
+                open_class(&mut regex, &mut chars);
+            }
+            '-' if in_class && chars.next_if_eq(&'[').is_some() => {
+                if let Some(subtracted) = classes.last_mut() {
+                    *subtracted = true;
+                }
+                classes.push(false);
+                regex.push_str("]--");
+                open_class(&mut regex, &mut chars);
+            }
+            ']' if in_class => match classes.pop() {
+                Some(true) => regex.push(']'),
+                _ => regex.push_str("]]"),
+            },
+            // the operators of the set operations of the regex crate
+            '&' | '~' if in_class => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            '-' if in_class && chars.peek() == Some(&'-') => regex.push_str("\\-"),
+            '.' if !in_class => regex.push_str(r"[^\n\r]"),
+            '^' | '$' if !in_class => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            c => regex.push(c),
+        }
+    }
+    if !classes.is_empty() {
+        return Err("a character class is not closed".to_string());
+    }
+    regex.push_str(")$");
+    Ok(regex)
+}
+
+/*
+Schema components
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+enum TypeRef {
+    // a built-in type by its local name
+    Builtin(String),
+    // a type defined at the top level of the schema
+    Named(String),
+    // a type defined inline, the index into the anonymous types of the schema
+    Anonymous(usize),
+    // a type of another namespace, its values are not checked
+    Foreign,
+}
+
+#[derive(Debug)]
+enum Facet {
+    Enumeration(Vec<String>),
+    Patterns(Vec<(String, Regex)>),
+    Length(usize),
+    MinLength(usize),
+    MaxLength(usize),
+    TotalDigits(usize),
+    FractionDigits(usize),
+    MinInclusive(String),
+    MaxInclusive(String),
+    MinExclusive(String),
+    MaxExclusive(String),
+}
+
+#[derive(Debug)]
+struct SimpleType {
+    base: TypeRef,
+    facets: Vec<Facet>,
+}
+
+#[derive(Debug)]
+enum Content {
+    Empty,
+    // text of a simple type
+    Simple(TypeRef),
+    // child elements, with text between them if mixed
+    Elements { particle: Particle, mixed: bool },
+}
+
+#[derive(Debug)]
+struct AttributeDecl {
+    name: String,
+    type_ref: TypeRef,
+    required: bool,
+    fixed: Option<String>,
+}
+
+#[derive(Debug)]
+struct ComplexType {
+    // the type the content and attributes derive from, true for an extension whose content
+    // is followed by the own content
+    base: Option<(TypeRef, bool)>,
+    content: Content,
+    attributes: Vec<AttributeDecl>,
+    attribute_groups: Vec<String>,
+    any_attribute: bool,
+}
+
+#[derive(Debug)]
+enum TypeDef {
+    Simple(SimpleType),
+    Complex(ComplexType),
+}
+
+#[derive(Debug)]
+struct ElementDecl {
+    name: String,
+    namespace: Option<String>,
+    type_ref: TypeRef,
+}
+
+#[derive(Debug)]
+enum Term {
+    Element(ElementDecl),
+    // a reference to a top level element
+    ElementRef(String),
+    // a reference to an element of an imported namespace, its content is not checked
+    ForeignElement {
+        namespace: Option<String>,
+        name: String,
+        // the name in Clark notation for messages
+        label: String,
+    },
+    Sequence(Vec<Particle>),
+    Choice(Vec<Particle>),
+    All(Vec<Particle>),
+    // any element, of another namespace only for ##other, its content is not checked
+    Any {
+        other_only: bool,
+    },
+    // a reference to a top level group
+    Group(String),
+}
+
+#[derive(Debug)]
+struct Particle {
+    min: usize,
+    max: usize,
+    term: Term,
+}
+
+/// An XML schema read from a schema file and its included files.
+///
+/// Supported are the top level elements, complex and simple types, groups and attribute
+/// groups. Complex types with sequence, choice, all, any, simple content and complex
+/// content extensions and restrictions, simple types with restrictions by the facets
+/// enumeration, pattern, (min/max)length, totalDigits, fractionDigits and the inclusive and
+/// exclusive bounds. Lists, unions and identity constraints are not checked, neither are
+/// the elements and types imported from other namespaces, an import is reported in the
+/// warnings of the schema. The regular expressions of patterns are translated to the regex
+/// crate, a pattern which can not be translated is not checked and reported in the warnings
+/// of the schema.
+#[derive(Debug)]
+pub struct Schema {
+    /// the file the schema was read from
+    pub file: String,
+    pub target_namespace: Option<String>,
+    /// the parts of the schema which are not checked, e.g. unsupported patterns
+    pub warnings: Vec<String>,
+    elements: HashMap<String, ElementDecl>,
+    types: HashMap<String, TypeDef>,
+    anonymous_types: Vec<TypeDef>,
+    groups: HashMap<String, Particle>,
+    attribute_groups: HashMap<String, (Vec<AttributeDecl>, Vec<String>)>,
+}
+
+// reads the components of a schema file into the schema
+struct SchemaParser<'a> {
+    schema: &'a mut Schema,
+    // the prefixes declared on the schema element
+    prefixes: Vec<(String, String)>,
+    // the local elements are in the target namespace
+    qualified: bool,
+}
+
+impl<'a> SchemaParser<'a> {
+    // the namespace and local name of a qualified name used as attribute value
+    fn resolve(&self, qname: &str) -> Result<(Option<&str>, String), String> {
+        let (prefix, local) = qname.split_once(':').unwrap_or(("", qname));
+        let namespace = self
+            .prefixes
+            .iter()
+            .find(|(p, _)| p == prefix)
+            .map(|(_, namespace)| namespace.as_str());
+        match (prefix, namespace) {
+            (_, Some(namespace)) => Ok((Some(namespace), local.to_owned())),
+            ("", None) => Ok((None, local.to_owned())),
+            (prefix, None) => Err(format!("the prefix '{}' is not declared", prefix)),
+        }
+    }
+
+    fn type_ref(&self, qname: &str) -> Result<TypeRef, String> {
+        let (namespace, name) = self.resolve(qname)?;
+        Ok(match namespace {
+            Some(XSD_NAMESPACE) => TypeRef::Builtin(name),
+            namespace if namespace == self.schema.target_namespace.as_deref() => {
+                TypeRef::Named(name)
+            }
+            _ => TypeRef::Foreign,
+        })
+    }
+
+    // the local name of a reference to a top level component of the schema
+    fn component_ref(&self, element: &Element) -> Result<String, String> {
+        let qname = element
+            .attribute("ref")
+            .ok_or_else(|| format!("xs:{} without ref", element.name))?;
+        Ok(self.resolve(qname)?.1)
+    }
+
+    fn name(element: &Element) -> Result<String, String> {
+        element
+            .attribute("name")
+            .map(|name| name.to_owned())
+            .ok_or_else(|| format!("xs:{} without name", element.name))
+    }
+
+    fn add_anonymous(&mut self, type_def: TypeDef) -> TypeRef {
+        self.schema.anonymous_types.push(type_def);
+        TypeRef::Anonymous(self.schema.anonymous_types.len() - 1)
+    }
+
+    fn components(
+        &mut self,
+        schema: &Element,
+        dir: &Path,
+        read_files: &mut HashSet<String>,
+    ) -> Result<(), String> {
+        for child in schema.schema_children() {
+            match child.name.as_str() {
+                "element" => {
+                    let decl = self.element_decl(child, true)?;
+                    self.schema.elements.insert(decl.name.to_owned(), decl);
+                }
+                "complexType" => {
+                    let type_def = TypeDef::Complex(self.complex_type(child)?);
+                    self.schema.types.insert(Self::name(child)?, type_def);
+                }
+                "simpleType" => {
+                    let type_def = TypeDef::Simple(self.simple_type(child)?);
+                    self.schema.types.insert(Self::name(child)?, type_def);
+                }
+                "group" => {
+                    let model_group = child
+                        .schema_children()
+                        .next()
+                        .ok_or("xs:group without content")?;
+                    let particle = Particle {
+                        min: 1,
+                        max: 1,
+                        term: self.particle(model_group)?.term,
+                    };
+                    self.schema.groups.insert(Self::name(child)?, particle);
+                }
+                "attributeGroup" => {
+                    let attributes = self.attributes(child)?;
+                    self.schema
+                        .attribute_groups
+                        .insert(Self::name(child)?, attributes);
+                }
+                "include" => {
+                    let location = child
+                        .attribute("schemaLocation")
+                        .ok_or("xs:include without schemaLocation")?;
+                    self.include(location, dir, read_files)?;
+                }
+                // the components of other namespaces are not checked
+                "import" => self.schema.warnings.push(format!(
+                    "xs:import of the namespace '{}' is not supported, its elements and types \
+                    are not checked",
+                    child.attribute("namespace").unwrap_or_default()
+                )),
+                "attribute" | "notation" => {}
+                name => return Err(format!("xs:{} is not supported", name)),
+            }
+        }
+        Ok(())
+    }
+
+    // read the components of an included schema file of the local directory
+    fn include(
+        &mut self,
+        location: &str,
+        dir: &Path,
+        read_files: &mut HashSet<String>,
+    ) -> Result<(), String> {
+        if location.contains("://") {
+            return Err(format!(
+                "the included schema '{}' is not a local file",
+                location
+            ));
+        }
+        let path = dir.join(location);
+        let key = path.to_string_lossy().into_owned();
+        if !read_files.insert(key.to_owned()) {
+            return Ok(());
+        }
+        let xsd = fs::read_to_string(&path)
+            .map_err(|e| format!("the included schema '{}' can not be read: {}", key, e))?;
+        let schema = read_elements(&xsd)
+            .map_err(|e| format!("the included schema '{}' is invalid: {}", key, e))?;
+        // the prefixes and the element form of the included file apply to its components
+        let prefixes = std::mem::replace(&mut self.prefixes, schema.declarations.to_owned());
+        let qualified = std::mem::replace(
+            &mut self.qualified,
+            schema.attribute("elementFormDefault") == Some("qualified"),
+        );
+        let dir = path.parent().unwrap_or(dir);
+        let result = self.components(&schema, dir, read_files);
+        self.prefixes = prefixes;
+        self.qualified = qualified;
+        result
+    }
+
+    fn occurs(element: &Element) -> Result<(usize, usize), String> {
+        let number = |name: &str, default: usize| match element.attribute(name) {
+            None => Ok(default),
+            Some("unbounded") => Ok(UNBOUNDED),
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid {} '{}'", name, value)),
+        };
+        Ok((number("minOccurs", 1)?, number("maxOccurs", 1)?))
+    }
+
+    fn element_decl(&mut self, element: &Element, top_level: bool) -> Result<ElementDecl, String> {
+        let qualified = match element.attribute("form") {
+            Some(form) => form == "qualified",
+            None => self.qualified,
+        };
+        let namespace = match top_level || qualified {
+            true => self.schema.target_namespace.to_owned(),
+            false => None,
+        };
+        let type_ref = match element.attribute("type") {
+            Some(qname) => self.type_ref(qname)?,
+            None => match element.schema_children().next() {
+                Some(child) if child.name == "complexType" => {
+                    let type_def = TypeDef::Complex(self.complex_type(child)?);
+                    self.add_anonymous(type_def)
+                }
+                Some(child) if child.name == "simpleType" => {
+                    let type_def = TypeDef::Simple(self.simple_type(child)?);
+                    self.add_anonymous(type_def)
+                }
+                _ => TypeRef::Builtin("anyType".to_string()),
+            },
+        };
+        Ok(ElementDecl {
+            name: Self::name(element)?,
+            namespace,
+            type_ref,
+        })
+    }
+
+    fn particle(&mut self, element: &Element) -> Result<Particle, String> {
+        let (min, max) = Self::occurs(element)?;
+        let particles = |parser: &mut Self| -> Result<Vec<Particle>, String> {
+            element
+                .schema_children()
+                .map(|child| parser.particle(child))
+                .collect()
+        };
+        let term = match element.name.as_str() {
+            "element" => match element.attribute("ref") {
+                Some(qname) => match self.resolve(qname)? {
+                    (namespace, name) if namespace == self.schema.target_namespace.as_deref() => {
+                        Term::ElementRef(name)
+                    }
+                    (namespace, name) => Term::ForeignElement {
+                        namespace: namespace.map(|namespace| namespace.to_owned()),
+                        label: format!("{{{}}}{}", namespace.unwrap_or_default(), name),
+                        name,
+                    },
+                },
+                None => Term::Element(self.element_decl(element, false)?),
+            },
+            "sequence" => Term::Sequence(particles(self)?),
+            "choice" => Term::Choice(particles(self)?),
+            "all" => Term::All(particles(self)?),
+            "any" => Term::Any {
+                other_only: element.attribute("namespace") == Some("##other"),
+            },
+            "group" => Term::Group(self.component_ref(element)?),
+            name => return Err(format!("xs:{} is not supported in a content model", name)),
+        };
+        Ok(Particle { min, max, term })
+    }
+
+    // the attribute declarations and attribute group references of a type or group
+    fn attributes(
+        &mut self,
+        element: &Element,
+    ) -> Result<(Vec<AttributeDecl>, Vec<String>), String> {
+        let mut attributes = Vec::new();
+        let mut groups = Vec::new();
+        for child in element.schema_children() {
+            match child.name.as_str() {
+                "attribute" if child.attribute("use") == Some("prohibited") => {}
+                "attribute" => {
+                    let type_ref = match (child.attribute("type"), child.schema_children().next()) {
+                        (Some(qname), _) => self.type_ref(qname)?,
+                        (None, Some(simple_type)) => {
+                            let type_def = TypeDef::Simple(self.simple_type(simple_type)?);
+                            self.add_anonymous(type_def)
+                        }
+                        (None, None) => TypeRef::Builtin("anySimpleType".to_string()),
+                    };
+                    let name = match child.attribute("ref") {
+                        Some(_) => self.component_ref(child)?,
+                        None => Self::name(child)?,
+                    };
+                    attributes.push(AttributeDecl {
+                        name,
+                        type_ref,
+                        required: child.attribute("use") == Some("required"),
+                        fixed: child.attribute("fixed").map(|fixed| fixed.to_owned()),
+                    });
+                }
+                "attributeGroup" => groups.push(self.component_ref(child)?),
+                _ => {}
+            }
+        }
+        Ok((attributes, groups))
+    }
+
+    fn complex_type(&mut self, element: &Element) -> Result<ComplexType, String> {
+        let mut mixed = element.attribute("mixed") == Some("true");
+        let mut base = None;
+        let mut content = None;
+        // the element holding the particle and attributes, the derivation for a complex type
+        // with simple or complex content
+        let mut definition = element;
+        if let Some(child) = element
+            .schema_children()
+            .find(|child| child.name == "simpleContent" || child.name == "complexContent")
+        {
+            mixed = mixed || child.attribute("mixed") == Some("true");
+            let derivation = child
+                .schema_children()
+                .next()
+                .ok_or_else(|| format!("xs:{} without derivation", child.name))?;
+            let base_ref = match derivation.attribute("base") {
+                Some(qname) => self.type_ref(qname)?,
+                None => return Err(format!("xs:{} without base", derivation.name)),
+            };
+            let extension = derivation.name == "extension";
+            if child.name == "simpleContent" {
+                let value_type = match extension {
+                    true => base_ref.to_owned(),
+                    // the facets of a restriction apply to the value of the base type
+                    false => {
+                        let simple_type = SimpleType {
+                            base: base_ref.to_owned(),
+                            facets: self.facets(derivation)?,
+                        };
+                        self.add_anonymous(TypeDef::Simple(simple_type))
+                    }
+                };
+                content = Some(Content::Simple(value_type));
+            }
+            base = Some((base_ref, extension && child.name == "complexContent"));
+            definition = derivation;
+        }
+        if content.is_none() {
+            let particle = definition.schema_children().find(|child| {
+                matches!(child.name.as_str(), "sequence" | "choice" | "all" | "group")
+            });
+            content = Some(match particle {
+                Some(particle) => Content::Elements {
+                    particle: self.particle(particle)?,
+                    mixed,
+                },
+                None if mixed => Content::Elements {
+                    particle: Particle {
+                        min: 1,
+                        max: 1,
+                        term: Term::Sequence(Vec::new()),
+                    },
+                    mixed,
+                },
+                None => Content::Empty,
+            });
+        }
+        let (attributes, attribute_groups) = self.attributes(definition)?;
+        Ok(ComplexType {
+            base,
+            content: content.unwrap_or(Content::Empty),
+            attributes,
+            attribute_groups,
+            any_attribute: definition
+                .schema_children()
+                .any(|child| child.name == "anyAttribute"),
+        })
+    }
+
+    fn facets(&mut self, restriction: &Element) -> Result<Vec<Facet>, String> {
+        let mut facets = Vec::new();
+        let mut enumeration = Vec::new();
+        let mut patterns = Vec::new();
+        // the value has to match one of the patterns, so none is checked if one of them can
+        // not be translated
+        let mut unsupported_pattern = false;
+        for facet in restriction.schema_children() {
+            let value = || {
+                facet
+                    .attribute("value")
+                    .map(|value| value.to_owned())
+                    .ok_or_else(|| format!("xs:{} without value", facet.name))
+            };
+            let number = || {
+                value()?
+                    .parse::<usize>()
+                    .map_err(|_| format!("invalid value of xs:{}", facet.name))
+            };
+            match facet.name.as_str() {
+                "enumeration" => enumeration.push(value()?),
+                "pattern" => {
+                    let pattern = value()?;
+                    let regex = translate_pattern(&pattern).and_then(|regex| {
+                        // the last line of a regex error describes the error
+                        Regex::new(&regex).map_err(|e| {
+                            e.to_string().lines().last().unwrap_or_default().to_owned()
+                        })
+                    });
+                    match regex {
+                        Ok(regex) => patterns.push((pattern, regex)),
+                        Err(message) => {
+                            unsupported_pattern = true;
+                            self.schema.warnings.push(format!(
+                                "the pattern '{}' is not checked: {}",
+                                pattern, message
+                            ));
+                        }
+                    }
+                }
+                "length" => facets.push(Facet::Length(number()?)),
+                "minLength" => facets.push(Facet::MinLength(number()?)),
+                "maxLength" => facets.push(Facet::MaxLength(number()?)),
+                "totalDigits" => facets.push(Facet::TotalDigits(number()?)),
+                "fractionDigits" => facets.push(Facet::FractionDigits(number()?)),
+                "minInclusive" => facets.push(Facet::MinInclusive(value()?)),
+                "maxInclusive" => facets.push(Facet::MaxInclusive(value()?)),
+                "minExclusive" => facets.push(Facet::MinExclusive(value()?)),
+                "maxExclusive" => facets.push(Facet::MaxExclusive(value()?)),
+                // the facets of a simple content restriction are followed by its attributes
+                "whiteSpace" | "simpleType" | "attribute" | "attributeGroup" | "anyAttribute" => {}
+                name => return Err(format!("the facet xs:{} is not supported", name)),
+            }
+        }
+        if !enumeration.is_empty() {
+            facets.push(Facet::Enumeration(enumeration));
+        }
+        if !patterns.is_empty() && !unsupported_pattern {
+            facets.push(Facet::Patterns(patterns));
+        }
+        Ok(facets)
+    }
+
+    fn simple_type(&mut self, element: &Element) -> Result<SimpleType, String> {
+        let derivation = element
+            .schema_children()
+            .next()
+            .ok_or("xs:simpleType without content")?;
+        if derivation.name != "restriction" {
+            // the items of lists and the members of unions are not checked
+            return Ok(SimpleType {
+                base: TypeRef::Builtin("anySimpleType".to_string()),
+                facets: Vec::new(),
+            });
+        }
+        let base = match derivation.attribute("base") {
+            Some(qname) => self.type_ref(qname)?,
+            None => {
+                let simple_type = derivation
+                    .schema_children()
+                    .find(|child| child.name == "simpleType")
+                    .ok_or("xs:restriction without base")?;
+                let type_def = TypeDef::Simple(self.simple_type(simple_type)?);
+                self.add_anonymous(type_def)
+            }
+        };
+        Ok(SimpleType {
+            base,
+            facets: self.facets(derivation)?,
+        })
+    }
+}
+
+impl Schema {
+    /// Read a schema from the content of the schema file `file`, included schemas are read
+    /// from the directory of the file
+    pub fn parse(file: &str, xsd: &str) -> Result<Self, String> {
+        let root = read_elements(xsd)?;
+        if root.namespace.as_deref() != Some(XSD_NAMESPACE) || root.name != "schema" {
+            return Err("the root element is no xs:schema".to_string());
+        }
+        let mut schema = Schema {
+            file: file.to_owned(),
+            target_namespace: root.attribute("targetNamespace").map(|ns| ns.to_owned()),
+            elements: HashMap::new(),
+            types: HashMap::new(),
+            warnings: Vec::new(),
+            anonymous_types: Vec::new(),
+            groups: HashMap::new(),
+            attribute_groups: HashMap::new(),
+        };
+        let mut parser = SchemaParser {
+            schema: &mut schema,
+            prefixes: root.declarations.to_owned(),
+            qualified: root.attribute("elementFormDefault") == Some("qualified"),
+        };
+        let dir = Path::new(file).parent().unwrap_or_else(|| Path::new(""));
+        let mut read_files = HashSet::from([file.to_owned()]);
+        parser.components(&root, dir, &mut read_files)?;
+        schema.check_references()?;
+        Ok(schema)
+    }
+
+    fn type_def(&self, type_ref: &TypeRef) -> Option<&TypeDef> {
+        match type_ref {
+            TypeRef::Named(name) => self.types.get(name),
+            TypeRef::Anonymous(index) => self.anonymous_types.get(*index),
+            _ => None,
+        }
+    }
+
+    // all references to types, elements and groups are defined in the schema
+    fn check_references(&self) -> Result<(), String> {
+        let check_type = |type_ref: &TypeRef| match type_ref {
+            TypeRef::Named(name) if !self.types.contains_key(name) => {
+                Err(format!("the type '{}' is not defined", name))
+            }
+            _ => Ok(()),
+        };
+        let check_attributes = |attributes: &[AttributeDecl], groups: &[String]| {
+            for attribute in attributes {
+                check_type(&attribute.type_ref)?;
+            }
+            match groups
+                .iter()
+                .find(|group| !self.attribute_groups.contains_key(*group))
+            {
+                Some(group) => Err(format!("the attribute group '{}' is not defined", group)),
+                None => Ok(()),
+            }
+        };
+        fn check_particle(
+            schema: &Schema,
+            particle: &Particle,
+            check_type: &dyn Fn(&TypeRef) -> Result<(), String>,
+        ) -> Result<(), String> {
+            match &particle.term {
+                Term::Element(decl) => check_type(&decl.type_ref),
+                Term::ElementRef(name) if !schema.elements.contains_key(name) => {
+                    Err(format!("the element '{}' is not defined", name))
+                }
+                Term::Group(name) if !schema.groups.contains_key(name) => {
+                    Err(format!("the group '{}' is not defined", name))
+                }
+                Term::Sequence(particles) | Term::Choice(particles) | Term::All(particles) => {
+                    particles
+                        .iter()
+                        .try_for_each(|p| check_particle(schema, p, check_type))
+                }
+                _ => Ok(()),
+            }
+        }
+
+        for decl in self.elements.values() {
+            check_type(&decl.type_ref)?;
+        }
+        for particle in self.groups.values() {
+            check_particle(self, particle, &check_type)?;
+        }
+        for (attributes, groups) in self.attribute_groups.values() {
+            check_attributes(attributes, groups)?;
+        }
+        for type_def in self.types.values().chain(self.anonymous_types.iter()) {
+            match type_def {
+                TypeDef::Simple(simple_type) => check_type(&simple_type.base)?,
+                TypeDef::Complex(complex_type) => {
+                    if let Some((base, _)) = &complex_type.base {
+                        check_type(base)?;
+                    }
+                    match &complex_type.content {
+                        Content::Simple(type_ref) => check_type(type_ref)?,
+                        Content::Elements { particle, .. } => {
+                            check_particle(self, particle, &check_type)?
+                        }
+                        Content::Empty => {}
+                    }
+                    check_attributes(&complex_type.attributes, &complex_type.attribute_groups)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate a document, returns the violations of the schema
+    pub fn validate(&self, xml: &str) -> Vec<Violation> {
+        let root = match read_elements(xml) {
+            Ok(root) => root,
+            Err(message) => {
+                return vec![Violation::new("/", "well-formed xml", message)];
+            }
+        };
+        let path = format!("/{}", root.name);
+        let mut validator = Validator {
+            schema: self,
+            violations: Vec::new(),
+        };
+        match self.elements.get(&root.name) {
+            Some(decl) if root.namespace == decl.namespace => {
+                validator.element(&root, &decl.type_ref, &path)
+            }
+            _ => {
+                let mut names: Vec<&str> = self.elements.keys().map(|n| n.as_str()).collect();
+                names.sort_unstable();
+                validator.violations.push(Violation::new(
+                    "/",
+                    expected_elements(&names),
+                    format!("element '{}'", root.label(self.target_namespace.as_deref())),
+                ));
+            }
+        }
+        validator.violations
+    }
+}
+
+/*
+Validation
+*/
+
+/// A violation of a schema by a document: the path of the element or attribute, what the
+/// schema expects there and what the document contains instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub path: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl Violation {
+    fn new(path: &str, expected: impl Into<String>, found: impl Into<String>) -> Self {
+        Violation {
+            path: path.to_owned(),
+            expected: expected.into(),
+            found: found.into(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected {}, found {}",
+            self.path, self.expected, self.found
+        )
+    }
+}
+
+// what wildcards expect, names of elements can not contain spaces
+const ANY_ELEMENT: &str = "any element";
+const OTHER_NAMESPACE_ELEMENT: &str = "an element of another namespace";
+
+fn expected_elements(names: &[&str]) -> String {
+    match names {
+        [] => "end of element".to_string(),
+        [name] if *name == ANY_ELEMENT || *name == OTHER_NAMESPACE_ELEMENT => name.to_string(),
+        [name] => format!("element '{}'", name),
+        names => format!("one of the elements ({})", names.join(", ")),
+    }
+}
+
+// how a child element was matched by the content model of its parent
+#[derive(Debug, Clone, Copy)]
+enum Match<'a> {
+    Unmatched,
+    Element(&'a ElementDecl),
+    Any,
+}
+
+// matches the child elements of an element with the particles of its content model. Each
+// particle consumes as many elements as it can, which finds the match for the content
+// models allowed by XML Schema which are deterministic.
+struct ContentMatcher<'a> {
+    schema: &'a Schema,
+    children: &'a [Element],
+    matches: Vec<Match<'a>>,
+    // the element names expected at positions of the children where no particle matched
+    expected: Vec<(usize, &'a str)>,
+}
+
+impl<'a> ContentMatcher<'a> {
+    // the position after the elements a particle matches from `pos` on, None if the particle
+    // does not match
+    fn particle(&mut self, particle: &'a Particle, mut pos: usize) -> Option<usize> {
+        let mut count = 0;
+        while count < particle.max {
+            match self.term(&particle.term, pos) {
+                Some(next) if next > pos => {
+                    pos = next;
+                    count += 1;
+                }
+                // a term matching no element matches any number of times
+                Some(_) => return Some(pos),
+                None => break,
+            }
+        }
+        match count >= particle.min {
+            true => Some(pos),
+            false => None,
+        }
+    }
+
+    fn element(&mut self, decl: &'a ElementDecl, pos: usize) -> Option<usize> {
+        match self.children.get(pos) {
+            Some(child) if child.name == decl.name && child.namespace == decl.namespace => {
+                self.matches[pos] = Match::Element(decl);
+                Some(pos + 1)
+            }
+            _ => {
+                self.expected.push((pos, &decl.name));
+                None
+            }
+        }
+    }
+
+    fn term(&mut self, term: &'a Term, pos: usize) -> Option<usize> {
+        match term {
+            Term::Element(decl) => self.element(decl, pos),
+            Term::ElementRef(name) => self.element(self.schema.elements.get(name)?, pos),
+            Term::ForeignElement {
+                namespace,
+                name,
+                label,
+            } => match self.children.get(pos) {
+                Some(child) if child.name == *name && child.namespace == *namespace => {
+                    self.matches[pos] = Match::Any;
+                    Some(pos + 1)
+                }
+                _ => {
+                    self.expected.push((pos, label));
+                    None
+                }
+            },
+            Term::Any { other_only } => match self.children.get(pos) {
+                Some(child)
+                    if !other_only
+                        || child.namespace.as_deref()
+                            != self.schema.target_namespace.as_deref() =>
+                {
+                    self.matches[pos] = Match::Any;
+                    Some(pos + 1)
+                }
+                _ => {
+                    let expected = match other_only {
+                        true => OTHER_NAMESPACE_ELEMENT,
+                        false => ANY_ELEMENT,
+                    };
+                    self.expected.push((pos, expected));
+                    None
+                }
+            },
+            Term::Sequence(particles) => particles
+                .iter()
+                .try_fold(pos, |pos, particle| self.particle(particle, pos)),
+            Term::Choice(particles) => {
+                let mut empty_match = false;
+                for particle in particles {
+                    match self.particle(particle, pos) {
+                        Some(next) if next > pos => return Some(next),
+                        Some(_) => empty_match = true,
+                        None => {}
+                    }
+                }
+                empty_match.then_some(pos)
+            }
+            Term::All(particles) => {
+                let mut matched = vec![false; particles.len()];
+                let mut pos = pos;
+                'elements: loop {
+                    for (i, particle) in particles.iter().enumerate() {
+                        if matched[i] {
+                            continue;
+                        }
+                        if let Some(next) = self.term(&particle.term, pos) {
+                            if next > pos {
+                                matched[i] = true;
+                                pos = next;
+                                continue 'elements;
+                            }
+                        }
+                    }
+                    break;
+                }
+                let mut complete = true;
+                for (i, particle) in particles.iter().enumerate() {
+                    if !matched[i] && particle.min > 0 {
+                        if let Term::Element(decl) = &particle.term {
+                            self.expected.push((pos, &decl.name));
+                        }
+                        complete = false;
+                    }
+                }
+                complete.then_some(pos)
+            }
+            Term::Group(name) => self.particle(self.schema.groups.get(name)?, pos),
+        }
+    }
+}
+
+// the content of a complex type including the content of the types it extends
+enum EffectiveContent<'a> {
+    Empty,
+    Simple(&'a TypeRef),
+    Elements(Vec<&'a Particle>, bool),
+}
+
+struct Validator<'a> {
+    schema: &'a Schema,
+    violations: Vec<Violation>,
+}
+
+impl<'a> Validator<'a> {
+    fn content(&self, complex_type: &'a ComplexType) -> EffectiveContent<'a> {
+        let own = match &complex_type.content {
+            Content::Empty => EffectiveContent::Empty,
+            Content::Simple(type_ref) => return EffectiveContent::Simple(type_ref),
+            Content::Elements { particle, mixed } => {
+                EffectiveContent::Elements(vec![particle], *mixed)
+            }
+        };
+        let base = match &complex_type.base {
+            Some((base, true)) => self.schema.type_def(base),
+            _ => None,
+        };
+        match (base, own) {
+            (Some(TypeDef::Complex(base)), own) => match (self.content(base), own) {
+                (
+                    EffectiveContent::Elements(mut particles, mixed),
+                    EffectiveContent::Elements(own, _),
+                ) => {
+                    particles.extend(own);
+                    EffectiveContent::Elements(particles, mixed)
+                }
+                (base, EffectiveContent::Empty) => base,
+                (_, own) => own,
+            },
+            (_, own) => own,
+        }
+    }
+
+    // the attributes of a complex type including the ones of its groups and base types,
+    // true if any attribute is allowed
+    fn attributes(&self, complex_type: &'a ComplexType) -> (Vec<&'a AttributeDecl>, bool) {
+        let mut attributes: Vec<&AttributeDecl> = complex_type.attributes.iter().collect();
+        let mut any_attribute = complex_type.any_attribute;
+        let mut groups: Vec<&String> = complex_type.attribute_groups.iter().collect();
+        let mut seen_groups = HashSet::new();
+        while let Some(group) = groups.pop() {
+            if !seen_groups.insert(group) {
+                continue;
+            }
+            if let Some((group_attributes, nested)) = self.schema.attribute_groups.get(group) {
+                attributes.extend(group_attributes.iter());
+                groups.extend(nested.iter());
+            }
+        }
+        if let Some((base, _)) = &complex_type.base {
+            if let Some(TypeDef::Complex(base)) = self.schema.type_def(base) {
+                let (base_attributes, base_any) = self.attributes(base);
+                for attribute in base_attributes {
+                    if !attributes.iter().any(|a| a.name == attribute.name) {
+                        attributes.push(attribute);
+                    }
+                }
+                any_attribute = any_attribute || base_any;
+            }
+        }
+        (attributes, any_attribute)
+    }
+
+    fn element(&mut self, element: &Element, type_ref: &TypeRef, path: &str) {
+        let complex_type = match (type_ref, self.schema.type_def(type_ref)) {
+            (TypeRef::Builtin(name), _) if name == "anyType" => return,
+            (TypeRef::Foreign, _) => return,
+            (_, Some(TypeDef::Complex(complex_type))) => complex_type,
+            // an element of a simple type has text only
+            _ => {
+                self.check_attributes(element, &[], false, path);
+                self.simple_content(element, type_ref, path);
+                return;
+            }
+        };
+
+        let (attributes, any_attribute) = self.attributes(complex_type);
+        self.check_attributes(element, &attributes, any_attribute, path);
+        match self.content(complex_type) {
+            EffectiveContent::Empty => {
+                if let Some(child) = element.children.first() {
+                    self.violations.push(Violation::new(
+                        path,
+                        "no child elements",
+                        format!("element '{}'", child.name),
+                    ));
+                } else if !element.text.trim().is_empty() {
+                    self.violations.push(Violation::new(
+                        path,
+                        "no text",
+                        found_value(&element.text),
+                    ));
+                }
+            }
+            EffectiveContent::Simple(value_type) => {
+                self.simple_content(element, value_type, path);
+            }
+            EffectiveContent::Elements(particles, mixed) => {
+                if !mixed && !element.text.trim().is_empty() {
+                    self.violations.push(Violation::new(
+                        path,
+                        "child elements only",
+                        format!("text {}", found_value(&element.text)),
+                    ));
+                }
+                self.child_elements(element, &particles, path);
+            }
+        }
+    }
+
+    fn simple_content(&mut self, element: &Element, type_ref: &TypeRef, path: &str) {
+        if let Some(child) = element.children.first() {
+            self.violations.push(Violation::new(
+                path,
+                "text only",
+                format!("element '{}'", child.name),
+            ));
+        } else if let Err(expected) = self.check_value(type_ref, &element.text) {
+            self.violations
+                .push(Violation::new(path, expected, found_value(&element.text)));
+        }
+    }
+
+    fn check_attributes(
+        &mut self,
+        element: &Element,
+        attributes: &[&AttributeDecl],
+        any_attribute: bool,
+        path: &str,
+    ) {
+        for (namespace, name, value) in element.attributes.iter() {
+            if namespace.as_deref() == Some(XSI_NAMESPACE) {
+                continue;
+            }
+            let attribute_path = format!("{}/@{}", path, name);
+            match attributes.iter().find(|a| a.name == *name) {
+                Some(decl) => {
+                    let checked = match &decl.fixed {
+                        Some(fixed) if fixed != value => Err(format!("'{}'", fixed)),
+                        _ => self.check_value(&decl.type_ref, value),
+                    };
+                    if let Err(expected) = checked {
+                        self.violations.push(Violation::new(
+                            &attribute_path,
+                            expected,
+                            found_value(value),
+                        ));
+                    }
+                }
+                None if !any_attribute => {
+                    let expected = match attributes.len() {
+                        0 => "no attributes".to_string(),
+                        _ => {
+                            let names: Vec<&str> =
+                                attributes.iter().map(|a| a.name.as_str()).collect();
+                            format!("one of the attributes ({})", names.join(", "))
+                        }
+                    };
+                    self.violations.push(Violation::new(
+                        path,
+                        expected,
+                        format!("attribute '{}'", name),
+                    ));
+                }
+                None => {}
+            }
+        }
+        for decl in attributes.iter().filter(|decl| decl.required) {
+            if element.attribute(&decl.name).is_none() {
+                self.violations.push(Violation::new(
+                    path,
+                    format!("attribute '{}'", decl.name),
+                    "no such attribute",
+                ));
+            }
+        }
+    }
+
+    fn child_elements(&mut self, element: &Element, particles: &[&'a Particle], path: &str) {
+        let children = &element.children;
+        let mut matcher = ContentMatcher {
+            schema: self.schema,
+            children,
+            matches: vec![Match::Unmatched; children.len()],
+            expected: Vec::new(),
+        };
+        let mut end = Some(0);
+        for particle in particles {
+            end = end.and_then(|pos| matcher.particle(particle, pos));
+        }
+
+        // the elements before the first one not matched by the content model are validated
+        let matched = matcher
+            .matches
+            .iter()
+            .position(|m| matches!(m, Match::Unmatched))
+            .unwrap_or(children.len());
+        if end != Some(children.len()) {
+            // the content model failed where the furthest particle failed
+            let pos = matcher
+                .expected
+                .iter()
+                .map(|(pos, _)| *pos)
+                .max()
+                .unwrap_or(matched)
+                .max(end.unwrap_or(0));
+            let mut names: Vec<&str> = Vec::new();
+            for (_, name) in matcher.expected.iter().filter(|(p, _)| *p == pos) {
+                if !names.contains(name) {
+                    names.push(name);
+                }
+            }
+            let found = match children.get(pos) {
+                Some(child) => {
+                    format!(
+                        "element '{}'",
+                        child.label(self.schema.target_namespace.as_deref())
+                    )
+                }
+                None => "end of element".to_string(),
+            };
+            self.violations
+                .push(Violation::new(path, expected_elements(&names), found));
+        }
+
+        for (pos, child) in children.iter().enumerate().take(matched) {
+            if let Match::Element(decl) = matcher.matches[pos] {
+                let repeated = children.iter().filter(|c| c.name == child.name).count() > 1;
+                let child_path = match repeated {
+                    true => {
+                        let index = children[..pos]
+                            .iter()
+                            .filter(|c| c.name == child.name)
+                            .count();
+                        format!("{}/{}[{}]", path, child.name, index + 1)
+                    }
+                    false => format!("{}/{}", path, child.name),
+                };
+                self.element(child, &decl.type_ref, &child_path);
+            }
+        }
+    }
+
+    // check a text or attribute value, returns what is expected if it is invalid
+    fn check_value(&self, type_ref: &TypeRef, value: &str) -> Result<(), String> {
+        match (type_ref, self.schema.type_def(type_ref)) {
+            (TypeRef::Builtin(name), _) => check_builtin(name, value),
+            (_, Some(TypeDef::Simple(simple_type))) => {
+                self.check_value(&simple_type.base, value)?;
+                simple_type
+                    .facets
+                    .iter()
+                    .try_for_each(|facet| check_facet(facet, value))
+            }
+            (_, Some(TypeDef::Complex(complex_type))) => match &complex_type.content {
+                Content::Simple(value_type) => self.check_value(value_type, value),
+                _ => Ok(()),
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+fn found_value(value: &str) -> String {
+    format!("'{}'", value)
+}
+
+fn is_digits(value: &str) -> bool {
+    !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn without_sign(value: &str) -> &str {
+    value.strip_prefix(['+', '-']).unwrap_or(value)
+}
+
+fn is_integer(value: &str) -> bool {
+    is_digits(without_sign(value))
+}
+
+fn is_decimal(value: &str) -> bool {
+    let (integer, fraction) = without_sign(value)
+        .split_once('.')
+        .unwrap_or((without_sign(value), ""));
+    (integer.is_empty() || is_digits(integer))
+        && (fraction.is_empty() || is_digits(fraction))
+        && !(integer.is_empty() && fraction.is_empty())
+}
+
+// the number of a fixed width field of a date or time within its bounds
+fn field(value: &str, min: u32, max: u32) -> Option<u32> {
+    match is_digits(value) {
+        true => value.parse().ok().filter(|n| (min..=max).contains(n)),
+        false => None,
+    }
+}
+
+// split a trailing timezone, Z or +hh:mm or -hh:mm, from a date or time
+fn split_timezone(value: &str) -> Option<&str> {
+    if let Some(value) = value.strip_suffix('Z') {
+        return Some(value);
+    }
+    if value.len() > 6 {
+        let (value, timezone) = value.split_at(value.len() - 6);
+        if timezone.starts_with(['+', '-']) && &timezone[3..4] == ":" {
+            field(&timezone[1..3], 0, 14)?;
+            field(&timezone[4..6], 0, 59)?;
+            return Some(value);
+        }
+    }
+    Some(value)
+}
+
+fn is_date(value: &str) -> bool {
+    let parts: Vec<&str> = value.rsplitn(3, '-').collect();
+    if parts.len() != 3 || parts[2].trim_start_matches('-').len() < 4 {
+        return false;
+    }
+    let (day, month, year) = (parts[0], parts[1], parts[2].trim_start_matches('-'));
+    let (year, month) = match (field(year, 0, u32::MAX), field(month, 1, 12)) {
+        (Some(year), Some(month)) if day.len() == 2 && parts[1].len() == 2 => (year, month),
+        _ => return false,
+    };
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    field(day, 1, days).is_some()
+}
+
+fn is_time(value: &str) -> bool {
+    let (time, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    let parts: Vec<&str> = time.split(':').collect();
+    parts.len() == 3
+        && parts.iter().all(|part| part.len() == 2)
+        && field(parts[0], 0, 24).is_some()
+        && field(parts[1], 0, 59).is_some()
+        && field(parts[2], 0, 59).is_some()
+        && is_digits(fraction)
+}
+
+fn check_builtin(name: &str, value: &str) -> Result<(), String> {
+    let trimmed = value.trim();
+    let (valid, expected) = match name {
+        "decimal" => (is_decimal(trimmed), "a decimal number"),
+        "integer" | "int" | "long" | "short" | "byte" => (is_integer(trimmed), "an integer"),
+        "nonNegativeInteger" | "unsignedLong" | "unsignedInt" | "unsignedShort"
+        | "unsignedByte" => (
+            is_integer(trimmed)
+                && (!trimmed.starts_with('-') || trimmed[1..].bytes().all(|b| b == b'0')),
+            "a non-negative integer",
+        ),
+        "positiveInteger" => (
+            is_integer(trimmed)
+                && !trimmed.starts_with('-')
+                && trimmed.bytes().any(|b| (b'1'..=b'9').contains(&b)),
+            "a positive integer",
+        ),
+        "boolean" => (matches!(trimmed, "true" | "false" | "1" | "0"), "a boolean"),
+        "date" => (
+            split_timezone(trimmed).is_some_and(is_date),
+            "a date (YYYY-MM-DD)",
+        ),
+        "dateTime" => (
+            split_timezone(trimmed)
+                .and_then(|value| value.split_once('T'))
+                .is_some_and(|(date, time)| is_date(date) && is_time(time)),
+            "a date and time (YYYY-MM-DDThh:mm:ss)",
+        ),
+        "time" => (
+            split_timezone(trimmed).is_some_and(is_time),
+            "a time (hh:mm:ss)",
+        ),
+        "gYear" => (
+            split_timezone(trimmed).is_some_and(|year| is_integer(year) && year.len() >= 4),
+            "a year (YYYY)",
+        ),
+        "gYearMonth" => (
+            split_timezone(trimmed).is_some_and(|value| is_date(&format!("{}-01", value))),
+            "a year and month (YYYY-MM)",
+        ),
+        "double" | "float" => (
+            matches!(trimmed, "INF" | "-INF" | "NaN") || trimmed.parse::<f64>().is_ok(),
+            "a floating point number",
+        ),
+        _ => (true, ""),
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(expected.to_string()),
+    }
+}
+
+// the numbers of integer and fraction digits of a decimal, without leading and trailing zeros
+fn digits(value: &str) -> (usize, usize) {
+    let value = without_sign(value.trim());
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    (
+        integer.trim_start_matches('0').len(),
+        fraction.trim_end_matches('0').len(),
+    )
+}
+
+// compare a value with a bound, numerically if both are numbers
+fn compare(value: &str, bound: &str) -> Option<std::cmp::Ordering> {
+    match (value.trim().parse::<f64>(), bound.parse::<f64>()) {
+        (Ok(value), Ok(bound)) => value.partial_cmp(&bound),
+        _ => Some(value.trim().cmp(bound)),
+    }
+}
+
+fn check_facet(facet: &Facet, value: &str) -> Result<(), String> {
+    use std::cmp::Ordering::{Equal, Greater, Less};
+    let length = value.chars().count();
+    let (valid, expected) = match facet {
+        Facet::Enumeration(values) => {
+            let listed: Vec<&str> = values
+                .iter()
+                .take(MAX_LISTED_VALUES)
+                .map(|v| v.as_str())
+                .collect();
+            let more = match values.len() > MAX_LISTED_VALUES {
+                true => format!(", ... {} values", values.len()),
+                false => String::new(),
+            };
+            (
+                values.iter().any(|v| v == value),
+                format!("one of ({}{})", listed.join(", "), more),
+            )
+        }
+        Facet::Patterns(patterns) => {
+            let listed: Vec<String> = patterns.iter().map(|(p, _)| format!("'{}'", p)).collect();
+            (
+                patterns.iter().any(|(_, regex)| regex.is_match(value)),
+                format!("a value matching {}", listed.join(" or ")),
+            )
+        }
+        Facet::Length(n) => (length == *n, format!("{} characters", n)),
+        Facet::MinLength(n) => (length >= *n, format!("at least {} characters", n)),
+        Facet::MaxLength(n) => (length <= *n, format!("at most {} characters", n)),
+        Facet::TotalDigits(n) => {
+            let (integer, fraction) = digits(value);
+            (integer + fraction <= *n, format!("at most {} digits", n))
+        }
+        Facet::FractionDigits(n) => (
+            digits(value).1 <= *n,
+            format!("at most {} fraction digits", n),
+        ),
+        Facet::MinInclusive(bound) => (
+            matches!(compare(value, bound), Some(Greater | Equal)),
+            format!("a value of at least {}", bound),
+        ),
+        Facet::MaxInclusive(bound) => (
+            matches!(compare(value, bound), Some(Less | Equal)),
+            format!("a value of at most {}", bound),
+        ),
+        Facet::MinExclusive(bound) => (
+            compare(value, bound) == Some(Greater),
+            format!("a value greater than {}", bound),
+        ),
+        Facet::MaxExclusive(bound) => (
+            compare(value, bound) == Some(Less),
+            format!("a value less than {}", bound),
+        ),
+    };
+    match valid {
+        true => Ok(()),
+        false => Err(expected),
+    }
+}
+
+/*
+Schemas of a directory
+*/
+
+/// The schemas of a local directory the processed documents are validated with
+#[derive(Debug)]
+pub struct SchemaStore {
+    schemas: Vec<Schema>,
+    // the schema of a document type or namespace as configured
+    mapping: HashMap<String, usize>,
+}
+
+impl SchemaStore {
+    /// Read the `.xsd` files of the directory `dir`. `mapping` selects the schema file for a
+    /// document type or namespace, the other documents are validated with the schema whose
+    /// target namespace is the namespace of the document. Files included by another schema
+    /// file are only read on their own if `mapping` names them. Only local files are read.
+    pub fn load(dir: &str, mapping: &HashMap<String, String>) -> UtilrsResult<Self> {
+        let io_error = |source| UtilrsError::Io {
+            path: dir.to_owned(),
+            source,
+        };
+        let mut files: Vec<_> = fs::read_dir(dir)
+            .map_err(io_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<_, _>>()
+            .map_err(io_error)?;
+        files.retain(|path| path.extension().is_some_and(|ext| ext == "xsd"));
+        files.sort();
+        let mut sources = Vec::new();
+        for path in files {
+            let xsd = fs::read_to_string(&path).map_err(|source| UtilrsError::Io {
+                path: path.to_string_lossy().into_owned(),
+                source,
+            })?;
+            sources.push((path, xsd));
+        }
+
+        // the files included by another schema are read as part of that schema, they are
+        // only loaded on their own when the mapping names them
+        let mapped: HashSet<PathBuf> = mapping
+            .values()
+            .map(|file_name| Path::new(dir).join(file_name))
+            .collect();
+        let mut included = HashSet::new();
+        for (path, xsd) in &sources {
+            let root = match read_elements(xsd) {
+                Ok(root) => root,
+                Err(_) => continue,
+            };
+            let parent = path.parent().unwrap_or_else(|| Path::new(dir));
+            included.extend(
+                root.schema_children()
+                    .filter(|child| child.name == "include")
+                    .filter_map(|child| child.attribute("schemaLocation"))
+                    .map(|location| parent.join(location))
+                    .filter(|include| include != path),
+            );
+        }
+        sources.retain(|(path, _)| mapped.contains(path) || !included.contains(path));
+
+        let mut schemas = Vec::new();
+        for (path, xsd) in sources {
+            let file = path.to_string_lossy().into_owned();
+            let schema = Schema::parse(&file, &xsd)
+                .map_err(|message| UtilrsError::schema(&file, message))?;
+            schemas.push(schema);
+        }
+
+        let mut schema_mapping = HashMap::new();
+        for (key, file_name) in mapping {
+            let path = Path::new(dir).join(file_name);
+            match schemas
+                .iter()
+                .position(|schema| Path::new(&schema.file) == path)
+            {
+                Some(index) => schema_mapping.insert(key.to_owned(), index),
+                None => {
+                    return Err(UtilrsError::schema(
+                        file_name,
+                        format!("no such schema file in '{}'", dir),
+                    ))
+                }
+            };
+        }
+        Ok(SchemaStore {
+            schemas,
+            mapping: schema_mapping,
+        })
+    }
+
+    /// The warnings of all schemas, the parts of the schemas which are not checked like
+    /// imports or patterns which can not be translated
+    pub fn warnings(&self) -> impl Iterator<Item = String> + '_ {
+        self.schemas.iter().flat_map(|schema| {
+            schema
+                .warnings
+                .iter()
+                .map(|warning| format!("Schema '{}': {}", schema.file, warning))
+        })
+    }
+
+    /// The schema for a document: the one configured for its namespace or else its type, or
+    /// the one with its namespace as target namespace. None if there is no such schema.
+    pub fn schema_for(&self, doc_type: &str, namespace: Option<&str>) -> Option<&Schema> {
+        let configured = namespace
+            .and_then(|namespace| self.mapping.get(namespace))
+            .or_else(|| self.mapping.get(doc_type));
+        match configured {
+            Some(index) => self.schemas.get(*index),
+            None => self
+                .schemas
+                .iter()
+                .find(|schema| schema.target_namespace.as_deref() == namespace),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r###"<?xml version="1.0" encoding="UTF-8"?>
+<xs:schema xmlns="urn:test" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:test" elementFormDefault="qualified">
+    <xs:element name="Document" type="Document"/>
+    <xs:complexType name="Document">
+        <xs:sequence>
+            <xs:element name="Hdr" type="Header"/>
+            <xs:element name="Tx" type="Transaction" maxOccurs="unbounded"/>
+            <xs:element name="Splmtry" minOccurs="0">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:any namespace="##any" processContents="lax"/>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>
+        </xs:sequence>
+    </xs:complexType>
+    <xs:complexType name="Header">
+        <xs:sequence>
+            <xs:element name="MsgId" type="Max35Text"/>
+            <xs:element name="CreDtTm" type="xs:dateTime"/>
+            <xs:choice>
+                <xs:element name="Cd" type="Code"/>
+                <xs:element name="Prtry" type="Max35Text"/>
+            </xs:choice>
+        </xs:sequence>
+    </xs:complexType>
+    <xs:complexType name="Transaction">
+        <xs:sequence>
+            <xs:element name="Amt" type="Amount"/>
+            <xs:element name="Ctry" type="CountryCode" minOccurs="0"/>
+        </xs:sequence>
+    </xs:complexType>
+    <xs:complexType name="Amount">
+        <xs:simpleContent>
+            <xs:extension base="Decimal">
+                <xs:attribute name="Ccy" type="CurrencyCode" use="required"/>
+            </xs:extension>
+        </xs:simpleContent>
+    </xs:complexType>
+    <xs:simpleType name="Decimal">
+        <xs:restriction base="xs:decimal">
+            <xs:fractionDigits value="2"/>
+            <xs:totalDigits value="8"/>
+            <xs:minInclusive value="0"/>
+        </xs:restriction>
+    </xs:simpleType>
+    <xs:simpleType name="CurrencyCode">
+        <xs:restriction base="xs:string">
+            <xs:pattern value="[A-Z]{3,3}"/>
+        </xs:restriction>
+    </xs:simpleType>
+    <xs:simpleType name="CountryCode">
+        <xs:restriction base="xs:string">
+            <xs:enumeration value="CH"/>
+            <xs:enumeration value="DE"/>
+        </xs:restriction>
+    </xs:simpleType>
+    <xs:simpleType name="Code">
+        <xs:restriction base="xs:string">
+            <xs:length value="4"/>
+        </xs:restriction>
+    </xs:simpleType>
+    <xs:simpleType name="Max35Text">
+        <xs:restriction base="xs:string">
+            <xs:minLength value="1"/>
+            <xs:maxLength value="35"/>
+        </xs:restriction>
+    </xs:simpleType>
+</xs:schema>"###;
+
+    fn violations(xml: &str) -> Vec<String> {
+        let schema = Schema::parse("test.xsd", SCHEMA).unwrap();
+        schema
+            .validate(xml)
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn validate_valid_document() {
+        let xml = r#"<Document xmlns="urn:test"><Hdr><MsgId>1</MsgId><CreDtTm>2024-02-29T10:00:00+01:00</CreDtTm><Prtry>X</Prtry></Hdr><Tx><Amt Ccy="EUR">10.5</Amt><Ctry>CH</Ctry></Tx><Tx><Amt Ccy="CHF">0</Amt></Tx><Splmtry><Any xmlns="urn:other"><Free/></Any></Splmtry></Document>"#;
+        assert!(violations(xml).is_empty());
+    }
+
+    #[test]
+    fn report_values_and_structure() {
+        let xml = r#"<Document xmlns="urn:test"><Hdr><MsgId></MsgId><CreDtTm>2023-02-29T10:00:00</CreDtTm><Cd>ABC</Cd></Hdr><Tx><Amt Ccy="eur" Dt="x">1.234</Amt><Ctry>FR</Ctry></Tx><Tx><Ctry>CH</Ctry></Tx></Document>"#;
+        assert_eq!(
+            violations(xml),
+            vec![
+                "/Document/Hdr/MsgId: expected at least 1 characters, found ''",
+                "/Document/Hdr/CreDtTm: expected a date and time (YYYY-MM-DDThh:mm:ss), found \
+                '2023-02-29T10:00:00'",
+                "/Document/Hdr/Cd: expected 4 characters, found 'ABC'",
+                "/Document/Tx[1]/Amt/@Ccy: expected a value matching '[A-Z]{3,3}', found 'eur'",
+                "/Document/Tx[1]/Amt: expected one of the attributes (Ccy), found attribute 'Dt'",
+                "/Document/Tx[1]/Amt: expected at most 2 fraction digits, found '1.234'",
+                "/Document/Tx[1]/Ctry: expected one of (CH, DE), found 'FR'",
+                "/Document/Tx[2]: expected element 'Amt', found element 'Ctry'",
+            ]
+        );
+
+        let xml = r#"<Document xmlns="urn:test"><Hdr><MsgId>1</MsgId><CreDtTm>2024-01-01T00:00:00</CreDtTm></Hdr><Tx><Amt>1</Amt><Amt Ccy="EUR">1</Amt></Tx></Document>"#;
+        assert_eq!(
+            violations(xml),
+            vec![
+                "/Document/Hdr: expected one of the elements (Cd, Prtry), found end of element",
+                "/Document/Tx: expected element 'Ctry', found element 'Amt'",
+                "/Document/Tx/Amt[1]: expected attribute 'Ccy', found no such attribute",
+            ]
+        );
+
+        assert_eq!(
+            violations(r#"<Document xmlns="urn:other"/>"#),
+            vec!["/: expected element 'Document', found element '{urn:other}Document'"]
+        );
+    }
+
+    // the violations of a document against a schema with the components `body`
+    fn schema_violations(body: &str, xml: &str) -> Vec<String> {
+        let xsd = format!(
+            r#"<xs:schema xmlns="urn:test" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:test" elementFormDefault="qualified">{}</xs:schema>"#,
+            body
+        );
+        Schema::parse("test.xsd", &xsd)
+            .unwrap()
+            .validate(xml)
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn validate_all_and_group_refs() {
+        let body = r#"
+            <xs:element name="Doc">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:group ref="Parties"/>
+                        <xs:element name="Opts">
+                            <xs:complexType>
+                                <xs:all>
+                                    <xs:element name="A" type="xs:string"/>
+                                    <xs:element name="B" type="xs:integer" minOccurs="0"/>
+                                    <xs:element name="C" type="xs:string"/>
+                                </xs:all>
+                            </xs:complexType>
+                        </xs:element>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>
+            <xs:group name="Parties">
+                <xs:sequence>
+                    <xs:element name="Dbtr" type="xs:string"/>
+                    <xs:element name="Cdtr" type="xs:string" maxOccurs="2"/>
+                </xs:sequence>
+            </xs:group>"#;
+        let doc = |content: &str| format!(r#"<Doc xmlns="urn:test">{}</Doc>"#, content);
+
+        let valid = doc("<Dbtr/><Cdtr/><Cdtr/><Opts><C/><B>1</B><A/></Opts>");
+        assert!(schema_violations(body, &valid).is_empty());
+        let valid = doc("<Dbtr/><Cdtr/><Opts><A/><C/></Opts>");
+        assert!(schema_violations(body, &valid).is_empty());
+
+        assert_eq!(
+            schema_violations(body, &doc("<Cdtr/><Opts><A/><C/></Opts>")),
+            vec!["/Doc: expected element 'Dbtr', found element 'Cdtr'"]
+        );
+        assert_eq!(
+            schema_violations(body, &doc("<Dbtr/><Cdtr/><Opts><C/><B>x</B></Opts>")),
+            vec![
+                "/Doc/Opts: expected element 'A', found end of element",
+                "/Doc/Opts/B: expected an integer, found 'x'",
+            ]
+        );
+        assert_eq!(
+            schema_violations(body, &doc("<Dbtr/><Cdtr/><Opts><A/><A/><C/></Opts>")),
+            vec!["/Doc/Opts: expected one of the elements (B, C), found element 'A'"]
+        );
+    }
+
+    #[test]
+    fn validate_complex_content_derivations() {
+        let body = r#"
+            <xs:element name="Doc">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:element name="Ext" type="Extended"/>
+                        <xs:element name="Rstr" type="Restricted"/>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>
+            <xs:complexType name="Base">
+                <xs:sequence>
+                    <xs:element name="Id" type="xs:string"/>
+                    <xs:element name="Nm" type="xs:string" minOccurs="0"/>
+                </xs:sequence>
+                <xs:attribute name="Ver" type="xs:integer"/>
+            </xs:complexType>
+            <xs:complexType name="Extended">
+                <xs:complexContent>
+                    <xs:extension base="Base">
+                        <xs:sequence>
+                            <xs:element name="Dt" type="xs:date"/>
+                        </xs:sequence>
+                        <xs:attribute name="Src" type="xs:string" use="required"/>
+                    </xs:extension>
+                </xs:complexContent>
+            </xs:complexType>
+            <xs:complexType name="Restricted">
+                <xs:complexContent>
+                    <xs:restriction base="Base">
+                        <xs:sequence>
+                            <xs:element name="Id" type="xs:string"/>
+                        </xs:sequence>
+                    </xs:restriction>
+                </xs:complexContent>
+            </xs:complexType>"#;
+        let doc = |ext: &str, rstr: &str| {
+            format!(
+                r#"<Doc xmlns="urn:test"><Ext Ver="1" Src="a">{}</Ext><Rstr Ver="2">{}</Rstr></Doc>"#,
+                ext, rstr
+            )
+        };
+
+        let valid = doc("<Id/><Nm/><Dt>2024-01-31</Dt>", "<Id/>");
+        assert!(schema_violations(body, &valid).is_empty());
+
+        // the content of an extension follows the content of the base type
+        assert_eq!(
+            schema_violations(body, &doc("<Dt>2024-01-31</Dt><Id/>", "<Id/>")),
+            vec!["/Doc/Ext: expected element 'Id', found element 'Dt'"]
+        );
+        assert_eq!(
+            schema_violations(body, &doc("<Id/><Dt>2024-02-30</Dt>", "<Id/>")),
+            vec!["/Doc/Ext/Dt: expected a date (YYYY-MM-DD), found '2024-02-30'"]
+        );
+        // the content of a restriction replaces the content of the base type
+        assert_eq!(
+            schema_violations(body, &doc("<Id/><Dt>2024-01-31</Dt>", "<Id/><Nm/>")),
+            vec!["/Doc/Rstr: expected end of element, found element 'Nm'"]
+        );
+        // the attributes of the base type are inherited
+        let xml = r#"<Doc xmlns="urn:test"><Ext Ver="x"><Id/><Dt>2024-01-31</Dt></Ext><Rstr Ver="y"><Id/></Rstr></Doc>"#;
+        assert_eq!(
+            schema_violations(body, xml),
+            vec![
+                "/Doc/Ext/@Ver: expected an integer, found 'x'",
+                "/Doc/Ext: expected attribute 'Src', found no such attribute",
+                "/Doc/Rstr/@Ver: expected an integer, found 'y'",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_other_namespace_wildcards() {
+        let body = r###"
+            <xs:element name="Doc">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:element name="Id" type="xs:string"/>
+                        <xs:any namespace="##other" processContents="lax" minOccurs="0" maxOccurs="unbounded"/>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>"###;
+
+        let valid = r#"<Doc xmlns="urn:test"><Id/><o:Ext xmlns:o="urn:other"><Free/></o:Ext><o:Ext xmlns:o="urn:other"/></Doc>"#;
+        assert!(schema_violations(body, valid).is_empty());
+        assert_eq!(
+            schema_violations(body, r#"<Doc xmlns="urn:test"><Id/><Ext/></Doc>"#),
+            vec!["/Doc: expected an element of another namespace, found element 'Ext'"]
+        );
+    }
+
+    #[test]
+    fn unsupported_lists_unions_and_imports() {
+        let xsd = r#"<xs:schema xmlns="urn:test" xmlns:o="urn:other" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:test" elementFormDefault="qualified">
+            <xs:import namespace="urn:other" schemaLocation="other.xsd"/>
+            <xs:element name="Doc">
+                <xs:complexType>
+                    <xs:sequence>
+                        <xs:element name="Nbs" type="Numbers"/>
+                        <xs:element name="Val" type="NumberOrCode"/>
+                        <xs:element ref="o:Ext"/>
+                        <xs:element name="Tp" type="o:Type"/>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:element>
+            <xs:simpleType name="Numbers">
+                <xs:list itemType="xs:integer"/>
+            </xs:simpleType>
+            <xs:simpleType name="NumberOrCode">
+                <xs:union memberTypes="xs:integer Code"/>
+            </xs:simpleType>
+            <xs:simpleType name="Code">
+                <xs:restriction base="xs:string">
+                    <xs:enumeration value="NONE"/>
+                </xs:restriction>
+            </xs:simpleType>
+        </xs:schema>"#;
+        let schema = Schema::parse("test.xsd", xsd).unwrap();
+        assert_eq!(
+            schema.warnings,
+            vec![
+                "xs:import of the namespace 'urn:other' is not supported, its elements and types \
+                are not checked"
+            ]
+        );
+
+        // the items of a list, the members of a union and the imported components are not
+        // checked
+        let xml = r#"<Doc xmlns="urn:test" xmlns:o="urn:other"><Nbs>1 x</Nbs><Val>SOME</Val><o:Ext><Any/></o:Ext><Tp><Any/></Tp></Doc>"#;
+        assert!(schema.validate(xml).is_empty());
+        let xml = r#"<Doc xmlns="urn:test"><Nbs/><Val/><Ext/><Tp/></Doc>"#;
+        let violations: Vec<String> = schema
+            .validate(xml)
+            .iter()
+            .map(|violation| violation.to_string())
+            .collect();
+        assert_eq!(
+            violations,
+            vec!["/Doc: expected element '{urn:other}Ext', found element 'Ext'"]
+        );
+    }
+
+    #[test]
+    fn schema_for_mapping_precedence() {
+        let dir = std::env::temp_dir().join("utilrs_schema_for_mapping_precedence");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let xsd = |target_namespace: &str| {
+            format!(
+                r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema" {}><xs:element name="Doc"/></xs:schema>"#,
+                target_namespace
+            )
+        };
+        fs::write(dir.join("a.xsd"), xsd(r#"targetNamespace="urn:a""#)).unwrap();
+        fs::write(dir.join("b.xsd"), xsd(r#"targetNamespace="urn:b""#)).unwrap();
+        fs::write(dir.join("none.xsd"), xsd("")).unwrap();
+        fs::write(dir.join("readme.txt"), "not a schema").unwrap();
+
+        let dir = dir.to_str().unwrap();
+        let mapping = HashMap::from([
+            ("urn:a".to_string(), "b.xsd".to_string()),
+            ("pain.001".to_string(), "none.xsd".to_string()),
+        ]);
+        let store = SchemaStore::load(dir, &mapping).unwrap();
+        let file = |doc_type: &str, namespace: Option<&str>| {
+            store.schema_for(doc_type, namespace).map(|schema| {
+                Path::new(&schema.file)
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        };
+        // a configured namespace comes before a configured document type, which comes
+        // before the target namespace
+        assert_eq!(file("pain.001", Some("urn:a")).as_deref(), Some("b.xsd"));
+        assert_eq!(file("pain.002", Some("urn:a")).as_deref(), Some("b.xsd"));
+        assert_eq!(file("pain.001", Some("urn:b")).as_deref(), Some("none.xsd"));
+        assert_eq!(file("pain.002", Some("urn:b")).as_deref(), Some("b.xsd"));
+        assert_eq!(file("pain.002", None).as_deref(), Some("none.xsd"));
+        assert_eq!(file("pain.002", Some("urn:c")), None);
+    }
+
+    #[test]
+    fn included_schema_files() {
+        let dir = std::env::temp_dir().join("utilrs_included_schema_files");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        // the usual split layout: a fragment in the target namespace using a type of the
+        // main file, with its own element form, and a chameleon fragment
+        fs::write(
+            dir.join("main.xsd"),
+            r#"<xs:schema xmlns="urn:test" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:test" elementFormDefault="qualified">
+                <xs:include schemaLocation="types.xsd"/>
+                <xs:include schemaLocation="common.xsd"/>
+                <xs:element name="Doc" type="Part"/>
+                <xs:complexType name="Amount">
+                    <xs:sequence><xs:element name="Value" type="xs:string"/></xs:sequence>
+                </xs:complexType>
+            </xs:schema>"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("types.xsd"),
+            r#"<xs:schema xmlns="urn:test" xmlns:xs="http://www.w3.org/2001/XMLSchema" targetNamespace="urn:test">
+                <xs:complexType name="Part">
+                    <xs:sequence>
+                        <xs:element name="Amt" type="Amount"/>
+                        <xs:element name="Note" type="Text"/>
+                    </xs:sequence>
+                </xs:complexType>
+            </xs:schema>"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("common.xsd"),
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+                <xs:simpleType name="Text">
+                    <xs:restriction base="xs:string"><xs:maxLength value="5"/></xs:restriction>
+                </xs:simpleType>
+            </xs:schema>"#,
+        )
+        .unwrap();
+
+        let store = SchemaStore::load(dir.to_str().unwrap(), &HashMap::new()).unwrap();
+        assert_eq!(store.schemas.len(), 1);
+        assert!(store.schema_for("Doc", None).is_none());
+        let schema = store.schema_for("Doc", Some("urn:test")).unwrap();
+        // the local elements of the fragment are unqualified, those of the main file not
+        let doc = |note: &str| {
+            format!(
+                r#"<t:Doc xmlns:t="urn:test"><Amt><t:Value>1</t:Value></Amt><Note>{}</Note></t:Doc>"#,
+                note
+            )
+        };
+        assert!(schema.validate(&doc("abc")).is_empty());
+        assert_eq!(
+            schema.validate(&doc("abcdef"))[0].to_string(),
+            "/Doc/Note: expected at most 5 characters, found 'abcdef'"
+        );
+
+        // a fragment named in the mapping is read on its own
+        let mapping = HashMap::from([("Text".to_string(), "common.xsd".to_string())]);
+        let store = SchemaStore::load(dir.to_str().unwrap(), &mapping).unwrap();
+        assert_eq!(store.schemas.len(), 2);
+    }
+
+    #[test]
+    fn translate_xsd_patterns() {
+        let matches = |pattern: &str, value: &str| {
+            Regex::new(&translate_pattern(pattern).unwrap())
+                .unwrap()
+                .is_match(value)
+        };
+        assert!(matches(r"\i\c*", "_a-1.b"));
+        assert!(!matches(r"\i\c*", "1a"));
+        assert!(matches(r"[\i-[:]][\c-[:]]*", "a1"));
+        assert!(!matches(r"[\i-[:]][\c-[:]]*", "a:1"));
+        assert!(matches(r"\p{IsBasicLatin}+", "abc"));
+        assert!(!matches(r"\p{IsBasicLatin}+", "äbc"));
+        assert!(matches(r"\P{IsBasicLatin}", "ä"));
+        assert!(matches(r"\p{Lu}\p{Ll}*", "Abc"));
+        assert!(matches(r"[a-z-[aeiou]]+", "xyz"));
+        assert!(!matches(r"[a-z-[aeiou]]+", "xaz"));
+        assert!(matches(r"[^a-z-[0-9]]", "A"));
+        assert!(!matches(r"[^a-z-[0-9]]", "5"));
+        assert!(!matches(r"[^a-z-[0-9]]", "a"));
+        assert!(matches(r"^a$", "^a$"));
+        assert!(!matches(r"^a$", "a"));
+        assert!(matches(r"a.c", "abc"));
+        assert!(!matches(r"a.c", "a\nc"));
+        assert!(matches(r"[&~]+", "&~"));
+        assert!(matches(r"\w+", "ab1"));
+        assert!(!matches(r"\w+", "a b"));
+        assert!(matches(r"\s\S", " x"));
+        assert!(matches(r"\+[0-9]{1,3}-[0-9()+\-]{1,30}", "+41-(0)44-123"));
+
+        assert_eq!(
+            translate_pattern(r"\p{IsUnknown}").unwrap_err(),
+            "the block 'Unknown' is not supported"
+        );
+        assert_eq!(
+            translate_pattern(r"\x").unwrap_err(),
+            "the escape '\\x' is not supported"
+        );
+        assert_eq!(
+            translate_pattern("[a[b]]").unwrap_err(),
+            "'[' in a character class"
+        );
+    }
+
+    #[test]
+    fn skip_unsupported_patterns() {
+        let schema = Schema::parse(
+            "test.xsd",
+            r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">
+                <xs:element name="A">
+                    <xs:simpleType>
+                        <xs:restriction base="xs:string">
+                            <xs:pattern value="[a-z]+"/>
+                            <xs:pattern value="\p{IsUnknown}+"/>
+                            <xs:maxLength value="3"/>
+                        </xs:restriction>
+                    </xs:simpleType>
+                </xs:element>
+            </xs:schema>"#,
+        )
+        .unwrap();
+        assert_eq!(
+            schema.warnings,
+            vec![
+                "the pattern '\\p{IsUnknown}+' is not checked: the block 'Unknown' is not \
+                supported"
+            ]
+        );
+        // the other pattern is not checked either, the value may match the skipped one
+        assert!(schema.validate("<A>AB1</A>").is_empty());
+        assert_eq!(
+            schema.validate("<A>ABCD</A>")[0].to_string(),
+            "/A: expected at most 3 characters, found 'ABCD'"
+        );
+    }
+
+    #[test]
+    fn invalid_schemas() {
+        let schema = |body: &str| {
+            Schema::parse(
+                "test.xsd",
+                &format!(
+                    r#"<xs:schema xmlns:xs="http://www.w3.org/2001/XMLSchema">{}</xs:schema>"#,
+                    body
+                ),
+            )
+        };
+        assert!(schema(r#"<xs:element name="A" type="xs:string"/>"#).is_ok());
+        assert_eq!(
+            schema(r#"<xs:element name="A" type="B"/>"#).unwrap_err(),
+            "the type 'B' is not defined"
+        );
+        assert_eq!(
+            schema(r#"<xs:simpleType name="B"><xs:restriction base="xs:string"><xs:pattern value="[a"/></xs:restriction></xs:simpleType>"#)
+                .unwrap()
+                .warnings,
+            vec!["the pattern '[a' is not checked: a character class is not closed"]
+        );
+        assert_eq!(
+            schema(r#"<xs:include schemaLocation="http://example.com/a.xsd"/>"#).unwrap_err(),
+            "the included schema 'http://example.com/a.xsd' is not a local file"
+        );
+        assert!(Schema::parse("test.xsd", "<schema/>").is_err());
+    }
+}